@@ -0,0 +1,81 @@
+// Standard base64 with padding, for binary data in JSON and HTTP headers
+
+const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+pub fn encode(data: &[u8]) -> String {
+    let mut encoded = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bytes = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = (u32::from(bytes[0]) << 16) | (u32::from(bytes[1]) << 8) | u32::from(bytes[2]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(n >> (18 - i * 6)) as usize & 0x3F] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+/// None when the text isn't base64, whitespace is ignored
+pub fn decode(text: &str) -> Option<Vec<u8>> {
+    let digits: Vec<u8> = text.bytes().filter(|b| !b.is_ascii_whitespace()).collect();
+    let digits = match digits.iter().position(|&b| b == b'=') {
+        Some(padding) if digits[padding..].iter().all(|&b| b == b'=') => &digits[..padding],
+        Some(_) => return None,
+        None => &digits[..],
+    };
+    if digits.len() % 4 == 1 {
+        return None;
+    }
+
+    let mut decoded = Vec::with_capacity(digits.len() * 3 / 4);
+    for chunk in digits.chunks(4) {
+        let mut n = 0u32;
+        for (i, &digit) in chunk.iter().enumerate() {
+            let value = ALPHABET.iter().position(|&a| a == digit)? as u32;
+            n |= value << (18 - i * 6);
+        }
+        // Each digit after the first adds a byte
+        for i in 0..chunk.len() - 1 {
+            decoded.push((n >> (16 - i * 8)) as u8);
+        }
+    }
+    Some(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_pads_to_four_digits() {
+        assert_eq!(encode(b""), "");
+        assert_eq!(encode(b"f"), "Zg==");
+        assert_eq!(encode(b"fo"), "Zm8=");
+        assert_eq!(encode(b"foo"), "Zm9v");
+        assert_eq!(encode(b"foobar"), "Zm9vYmFy");
+    }
+
+    #[test]
+    fn decode_reads_back_the_encoding() {
+        let data: Vec<u8> = (0..=255).collect();
+        for len in 0..8 {
+            assert_eq!(decode(&encode(&data[..len])).unwrap(), &data[..len]);
+        }
+        assert_eq!(decode(&encode(&data)).unwrap(), data);
+        assert_eq!(decode("Zm9v\nYmFy").unwrap(), b"foobar");
+    }
+
+    #[test]
+    fn decode_refuses_other_text() {
+        assert!(decode("Zm9v!").is_none());
+        assert!(decode("Z").is_none());
+        assert!(decode("Zg=a").is_none());
+    }
+}
@@ -0,0 +1,1281 @@
+// Geometry of the stroke centerlines: interpolation, hit-testing, smoothing and simplification,
+// and the triangles sent to the vbo for each kind of stroke, in the vertex layout of VERTEX_SIZE,
+// or the centerlines expanded into quads on the GPU, in the layout of LINE_POINT_SIZE
+
+use std::f32::consts::PI;
+
+use serde::{Deserialize, Serialize};
+
+use crate::stroke::{ArrowHead, Shape, Stroke, StrokePoint};
+use crate::{screen_to_gl, Point, Rect2D, LINE_POINT_SIZE, VERTEX_SIZE};
+
+// Floats in the two triangles of each segment of a tessellated line
+pub const SEGMENT_SIZE: usize = 6 * VERTEX_SIZE;
+
+// Width added around strokes by their contrasting outline, in pixels
+pub const OUTLINE_WIDTH: f32 = 3.0;
+// Points added along each taper, so the width narrows smoothly even on long segments
+const TAPER_STEPS: f32 = 8.0;
+// Cutoff increase of the 1€ filter per pixel of speed between points
+const ONE_EURO_BETA: f32 = 0.01;
+
+/// How the centerline of a line is smoothed once drawn, with the smoothing range as strength
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum SmoothingMode {
+    None,
+    /// Average of the points around each point, see smooth_polyline
+    #[default]
+    MovingAverage,
+    /// Exponential average forwards then backwards, so the line doesn't lag behind
+    Exponential,
+    /// Curve through every few points, for diagrams with clean curves
+    Spline,
+    /// Filter smoothing the slow parts more than the fast ones, for handwriting
+    OneEuro,
+}
+
+pub fn lerp_point(a: StrokePoint, b: StrokePoint, t: f32) -> StrokePoint {
+    StrokePoint {
+        x: a.x + (b.x - a.x) * t,
+        y: a.y + (b.y - a.y) * t,
+        pressure: a.pressure + (b.pressure - a.pressure) * t,
+        time: a.time + (b.time - a.time) * t,
+    }
+}
+
+/// Part of the segment a-b inside a circle, as a range of interpolation factors between 0 and 1
+pub fn circle_segment_intersection(
+    x: f32,
+    y: f32,
+    radius: f32,
+    a: StrokePoint,
+    b: StrokePoint,
+) -> Option<(f32, f32)> {
+    let dx = b.x - a.x;
+    let dy = b.y - a.y;
+    let fx = a.x - x;
+    let fy = a.y - y;
+
+    // Solve |a + t * (b - a) - center|² = radius²
+    let qa = dx * dx + dy * dy;
+    let qb = 2.0 * (fx * dx + fy * dy);
+    let qc = fx * fx + fy * fy - radius * radius;
+
+    if qa == 0.0 {
+        // Zero length segment, it's either fully in or out
+        return if qc <= 0.0 { Some((0.0, 1.0)) } else { None };
+    }
+
+    let discriminant = qb * qb - 4.0 * qa * qc;
+    if discriminant < 0.0 {
+        return None;
+    }
+
+    let sqrt_discriminant = discriminant.sqrt();
+    let t_in = ((-qb - sqrt_discriminant) / (2.0 * qa)).max(0.0);
+    let t_out = ((-qb + sqrt_discriminant) / (2.0 * qa)).min(1.0);
+
+    if t_in > t_out {
+        None
+    } else {
+        Some((t_in, t_out))
+    }
+}
+
+/// Interpolation factor of the point of the segment a-b closest to x, y, between 0 and 1
+fn project_on_segment(x: f32, y: f32, a: StrokePoint, b: StrokePoint) -> f32 {
+    let dx = b.x - a.x;
+    let dy = b.y - a.y;
+    let len_sq = dx * dx + dy * dy;
+    if len_sq > 0.0 {
+        (((x - a.x) * dx + (y - a.y) * dy) / len_sq).clamp(0.0, 1.0)
+    } else {
+        0.0
+    }
+}
+
+/// Distance in pixels between a point and a centerline segment
+pub fn distance_to_segment(x: f32, y: f32, a: StrokePoint, b: StrokePoint) -> f32 {
+    let closest = lerp_point(a, b, project_on_segment(x, y, a, b));
+    (closest.x - x).hypot(closest.y - y)
+}
+
+/// Moving average of the points of a centerline, except its ends which stay in place
+///
+/// Each point is averaged with up to `range` points before it and `range - 1` after it. Points
+/// are smoothed from the start, so the ones before are already smoothed.
+///
+/// Reference: https://stackoverflow.com/a/18830268
+pub fn smooth_polyline(points: &mut [StrokePoint], range: usize) {
+    if range == 0 || points.len() < 3 {
+        return;
+    }
+
+    let last = points.len() - 1;
+    for i in 1..last {
+        // The last point is never part of the average
+        let window = i.saturating_sub(range)..(i + range).min(last);
+        let n = window.len() as f32;
+        let (x, y, pressure) = points[window]
+            .iter()
+            .fold((0.0, 0.0, 0.0), |(x, y, pressure), p| {
+                (x + p.x, y + p.y, pressure + p.pressure)
+            });
+        points[i] = StrokePoint {
+            x: x / n,
+            y: y / n,
+            pressure: pressure / n,
+            time: points[i].time,
+        };
+    }
+}
+
+/// Smooth a centerline with one of the modes, `intensity` times for the averages. The ends stay
+/// in place
+pub fn smooth_centerline(
+    points: &mut Vec<StrokePoint>,
+    mode: SmoothingMode,
+    range: usize,
+    intensity: usize,
+) {
+    match mode {
+        SmoothingMode::None => (),
+        SmoothingMode::MovingAverage => {
+            for _ in 0..intensity {
+                smooth_polyline(points, range);
+            }
+        }
+        SmoothingMode::Exponential => {
+            for _ in 0..intensity {
+                smooth_exponential(points, range);
+            }
+        }
+        SmoothingMode::Spline => fit_spline(points, range),
+        SmoothingMode::OneEuro => filter_one_euro(points, range),
+    }
+}
+
+/// Exponential average with a factor of 1 / (range + 1), forwards then backwards
+fn smooth_exponential(points: &mut [StrokePoint], range: usize) {
+    if range == 0 || points.len() < 3 {
+        return;
+    }
+    let factor = 1.0 / (range as f32 + 1.0);
+    let (first, last) = (points[0], points[points.len() - 1]);
+    for i in 1..points.len() {
+        points[i] = lerp_point(points[i - 1], points[i], factor);
+    }
+    for i in (0..points.len() - 1).rev() {
+        points[i] = lerp_point(points[i + 1], points[i], factor);
+    }
+    points[0] = first;
+    *points.last_mut().unwrap() = last;
+}
+
+/// Replace the centerline by a Catmull-Rom spline through every `range + 1`th point and the
+/// last one, sampled as often as the points it replaces
+fn fit_spline(points: &mut Vec<StrokePoint>, range: usize) {
+    if range == 0 || points.len() < 3 {
+        return;
+    }
+    let step = range + 1;
+    let mut controls: Vec<StrokePoint> = points.iter().step_by(step).copied().collect();
+    if !(points.len() - 1).is_multiple_of(step) {
+        controls.push(points[points.len() - 1]);
+    }
+
+    let last = controls.len() - 1;
+    let mut fitted = vec![controls[0]];
+    for i in 0..last {
+        let (p0, p1) = (controls[i.saturating_sub(1)], controls[i]);
+        let (p2, p3) = (controls[i + 1], controls[(i + 2).min(last)]);
+        for sample in 1..step {
+            let t = sample as f32 / step as f32;
+            let catmull_rom = |a: f32, b: f32, c: f32, d: f32| {
+                0.5 * (2.0 * b
+                    + (c - a) * t
+                    + (2.0 * a - 5.0 * b + 4.0 * c - d) * t * t
+                    + (3.0 * b - a - 3.0 * c + d) * t * t * t)
+            };
+            fitted.push(StrokePoint {
+                x: catmull_rom(p0.x, p1.x, p2.x, p3.x),
+                y: catmull_rom(p0.y, p1.y, p2.y, p3.y),
+                // The pressure could overshoot to nothing
+                pressure: p1.pressure + (p2.pressure - p1.pressure) * t,
+                time: p1.time + (p2.time - p1.time) * t,
+            });
+        }
+        fitted.push(p2);
+    }
+    *points = fitted;
+}
+
+/// 1€ filter, with the points as time steps: the cutoff frequency grows with the speed, so slow
+/// parts are smoothed a lot and fast ones don't lag
+///
+/// Reference: https://gery.casiez.net/1euro/
+fn filter_one_euro(points: &mut [StrokePoint], range: usize) {
+    if range == 0 || points.len() < 3 {
+        return;
+    }
+    let factor = |cutoff: f32| 1.0 / (1.0 + 1.0 / (2.0 * PI * cutoff));
+    let min_cutoff = 0.25 / range as f32;
+    let last = points[points.len() - 1];
+
+    let mut filtered = points[0];
+    let mut speed = 0.0;
+    for point in points.iter_mut().skip(1) {
+        let raw_speed = (point.x - filtered.x).hypot(point.y - filtered.y);
+        speed += (raw_speed - speed) * factor(1.0);
+        filtered = lerp_point(filtered, *point, factor(min_cutoff + ONE_EURO_BETA * speed));
+        *point = filtered;
+    }
+    *points.last_mut().unwrap() = last;
+}
+
+/// Centerline with its pressure narrowing to nothing over `start` pixels from its first point
+/// and `end` pixels before its last one, split into more points along the tapers
+///
+/// Tapers longer than the line are shortened, keeping their proportions.
+pub fn taper_centerline(points: &[StrokePoint], start: f32, end: f32) -> Vec<StrokePoint> {
+    let length: f32 = points
+        .windows(2)
+        .map(|s| (s[1].x - s[0].x).hypot(s[1].y - s[0].y))
+        .sum();
+    if start + end <= 0.0 || length <= 0.0 {
+        return points.to_vec();
+    }
+    let shortening = (length / (start + end)).min(1.0);
+    let (start, end) = (start * shortening, end * shortening);
+    let factor = |distance: f32| {
+        let from_start = if start > 0.0 { distance / start } else { 1.0 };
+        let from_end = if end > 0.0 {
+            (length - distance) / end
+        } else {
+            1.0
+        };
+        from_start.min(from_end).clamp(0.0, 1.0)
+    };
+    let shortest = [start, end]
+        .iter()
+        .copied()
+        .filter(|&t| t > 0.0)
+        .fold(f32::INFINITY, f32::min);
+    let step = shortest / TAPER_STEPS;
+    let tapered = |p: StrokePoint, distance: f32| StrokePoint {
+        pressure: p.pressure * factor(distance),
+        ..p
+    };
+
+    let mut result = vec![tapered(points[0], 0.0)];
+    let mut distance = 0.0;
+    for segment in points.windows(2) {
+        let segment_length = (segment[1].x - segment[0].x).hypot(segment[1].y - segment[0].y);
+        // Only the parts of the segment in a taper are split
+        let is_tapered = distance < start || distance + segment_length > length - end;
+        let n_steps = if is_tapered {
+            (segment_length / step).ceil().max(1.0) as usize
+        } else {
+            1
+        };
+        for i in 1..=n_steps {
+            let t = i as f32 / n_steps as f32;
+            let point = lerp_point(segment[0], segment[1], t);
+            result.push(tapered(point, distance + segment_length * t));
+        }
+        distance += segment_length;
+    }
+    result
+}
+
+/// Drop the points of a centerline closer than `tolerance` pixels to the line between the
+/// points kept around them (Ramer-Douglas-Peucker), the ends are always kept
+///
+/// The pressure counts too, `width` turning its difference into pixels of line radius.
+pub fn simplify_polyline(points: &[StrokePoint], tolerance: f32, width: f32) -> Vec<StrokePoint> {
+    if points.len() < 3 {
+        return points.to_vec();
+    }
+
+    let mut is_kept = vec![false; points.len()];
+    is_kept[0] = true;
+    is_kept[points.len() - 1] = true;
+
+    let mut ranges = vec![(0, points.len() - 1)];
+    while let Some((start, end)) = ranges.pop() {
+        let (a, b) = (points[start], points[end]);
+        let farthest = (start + 1..end)
+            .map(|i| (i, simplification_error(points[i], a, b, width)))
+            .max_by(|(_, e1), (_, e2)| e1.total_cmp(e2));
+        if let Some((i, error)) = farthest {
+            if error > tolerance {
+                is_kept[i] = true;
+                ranges.push((start, i));
+                ranges.push((i, end));
+            }
+        }
+    }
+
+    points
+        .iter()
+        .zip(is_kept)
+        .filter(|&(_, is_kept)| is_kept)
+        .map(|(&p, _)| p)
+        .collect()
+}
+
+/// The centerline as a straight line between its ends when none of its points is farther than
+/// `tolerance` pixels from it, None otherwise
+///
+/// The points are moved onto the line rather than dropped, so they keep their pressure and time.
+/// Lines not much longer than the tolerance, such as dots, are left as they are.
+pub fn straighten_polyline(points: &[StrokePoint], tolerance: f32) -> Option<Vec<StrokePoint>> {
+    let (&a, &b) = (points.first()?, points.last()?);
+    if points.len() < 3 || (b.x - a.x).hypot(b.y - a.y) <= tolerance * 2.0 {
+        return None;
+    }
+    if points
+        .iter()
+        .any(|p| distance_to_segment(p.x, p.y, a, b) > tolerance)
+    {
+        return None;
+    }
+
+    Some(
+        points
+            .iter()
+            .map(|&p| {
+                let on_line = lerp_point(a, b, project_on_segment(p.x, p.y, a, b));
+                StrokePoint {
+                    x: on_line.x,
+                    y: on_line.y,
+                    ..p
+                }
+            })
+            .collect(),
+    )
+}
+
+/// Whether the ends of a centerline are within `distance` pixels of each other on a line long
+/// enough to be a loop, in which case the last point is moved onto the first to close it
+///
+/// Short lines with close ends, such as dots and ticks, are left open.
+pub fn close_polyline(points: &mut [StrokePoint], distance: f32) -> bool {
+    let (a, b) = match (points.first(), points.last()) {
+        (Some(&a), Some(&b)) if points.len() >= 3 => (a, b),
+        _ => return false,
+    };
+    let gap = (b.x - a.x).hypot(b.y - a.y);
+    let length: f32 = points
+        .windows(2)
+        .map(|s| (s[1].x - s[0].x).hypot(s[1].y - s[0].y))
+        .sum();
+    if gap == 0.0 || gap > distance || length <= distance * 4.0 {
+        return false;
+    }
+
+    let last = points.len() - 1;
+    points[last].x = a.x;
+    points[last].y = a.y;
+    true
+}
+
+/// How far a point is from the segment a-b replacing it, in pixels of position or line radius
+fn simplification_error(p: StrokePoint, a: StrokePoint, b: StrokePoint, width: f32) -> f32 {
+    let closest = lerp_point(a, b, project_on_segment(p.x, p.y, a, b));
+    let distance = (closest.x - p.x).hypot(closest.y - p.y);
+    distance.max((closest.pressure - p.pressure).abs() * width)
+}
+
+/// Add the triangles of a line going through a list of points
+pub fn tessellate_polyline(
+    vertices: &mut Vec<f32>,
+    points: &[StrokePoint],
+    width: f32,
+    color: [f32; 3],
+    hardness: f32,
+    overlay_rect: &Rect2D,
+) {
+    extend_polyline(vertices, points, 0, width, color, hardness, overlay_rect);
+}
+
+/// Add the triangles of the segments of a line ending on its points from `from` on, the same
+/// as the ones tessellate_polyline makes for them. Each segment has SEGMENT_SIZE floats
+pub fn extend_polyline(
+    vertices: &mut Vec<f32>,
+    points: &[StrokePoint],
+    from: usize,
+    width: f32,
+    color: [f32; 3],
+    hardness: f32,
+    overlay_rect: &Rect2D,
+) {
+    /*
+    Each line segment is formed of 2 triangles that form a quad
+
+    p3 __ p4    - previous point
+      |\ |
+      | \|
+    p1 ¯¯ p2    - current point
+
+    p1: current point - line width
+    p2: current point + line width
+    p3: previous point - line width
+    p4: previous point + line width
+
+    The centerline is always between the two points
+    p3 ____ previous ____ p4
+      |                  |
+      |                  |
+      |                  |
+      |                  |
+    p1¯¯¯¯¯ current ¯¯¯¯ p2
+    */
+    let mut prev_sides: Option<(Point, Point)> = None;
+
+    // The sides of a point only depend on the segment ending on it
+    for i in from.saturating_sub(1)..points.len() {
+        let point = points[i];
+
+        // Direction of the segment ending on this point.
+        // The first point takes the direction of the first segment
+        let (from, to) = if i == 0 {
+            match points.get(1) {
+                Some(next) => (point, *next),
+                None => break,
+            }
+        } else {
+            (points[i - 1], point)
+        };
+
+        let dx = to.x - from.x;
+        let dy = to.y - from.y;
+        let len = (dx * dx + dy * dy).sqrt();
+        let (nx, ny) = if len > 0.0 {
+            (-dy / len, dx / len)
+        } else {
+            (0.0, 0.0)
+        };
+
+        let radius = width * point.pressure;
+        let p1 = screen_to_gl(point.x - nx * radius, point.y - ny * radius, overlay_rect);
+        let p2 = screen_to_gl(point.x + nx * radius, point.y + ny * radius, overlay_rect);
+
+        if let Some((p3, p4)) = prev_sides {
+            // Triangle 3-2-1 and Triangle 3-2-4
+            // The edge goes from -1 on the 1-3 side to 1 on the 2-4 side
+            for &(p, edge) in [
+                (p3, -1.0),
+                (p2, 1.0),
+                (p1, -1.0),
+                (p3, -1.0),
+                (p2, 1.0),
+                (p4, 1.0),
+            ]
+            .iter()
+            {
+                push_vertex(vertices, p, color, edge, hardness);
+            }
+        }
+
+        prev_sides = Some((p1, p2));
+    }
+}
+
+/// Add a centerline to a list of line points, for the renderers expanding each of its segments
+/// into a quad on the GPU the way tessellate_polyline does on the CPU
+///
+/// Each segment is drawn from three consecutive points: the one before it, for the direction of
+/// its start, and its two ends. The first point is repeated as the point before the first
+/// segment, not joined to it, and unjoined points end the segments leading to them, so lines
+/// can follow each other in the same list.
+pub fn push_line_points(
+    line_points: &mut Vec<f32>,
+    points: &[StrokePoint],
+    width: f32,
+    color: [f32; 3],
+    hardness: f32,
+) {
+    if points.len() < 2 {
+        return;
+    }
+    line_points.reserve((points.len() + 1) * LINE_POINT_SIZE);
+    for (i, p) in std::iter::once(&points[0]).chain(points).enumerate() {
+        let joined = if i < 2 { 0.0 } else { 1.0 };
+        line_points.extend(&[p.x, p.y, p.pressure, width]);
+        line_points.extend(&color);
+        line_points.extend(&[hardness, 1.0, joined]);
+    }
+}
+
+/// Add a triangle (in screen pixels) to a list of vertices
+pub fn push_triangle(
+    vertices: &mut Vec<f32>,
+    corners: [(f32, f32); 3],
+    color: [f32; 3],
+    overlay_rect: &Rect2D,
+) {
+    for &(x, y) in corners.iter() {
+        push_vertex(vertices, screen_to_gl(x, y, overlay_rect), color, 0.0, 1.0);
+    }
+}
+
+/// Add a single vertex to a list of vertices
+///
+/// `edge` is the position across the line, from -1 to 1, and `hardness` the part of the line
+/// drawn before its edges start fading out. Shapes that are never feathered use 0 and 1.
+pub fn push_vertex(vertices: &mut Vec<f32>, p: Point, color: [f32; 3], edge: f32, hardness: f32) {
+    vertices.extend(&p.to_array());
+    vertices.extend(&color);
+    vertices.push(edge);
+    vertices.push(hardness);
+    vertices.push(1.0);
+}
+
+/// Add a see-through triangle (in screen pixels) to a list of vertices
+pub fn push_translucent_triangle(
+    vertices: &mut Vec<f32>,
+    corners: [(f32, f32); 3],
+    color: [f32; 3],
+    opacity: f32,
+    overlay_rect: &Rect2D,
+) {
+    let start = vertices.len();
+    push_triangle(vertices, corners, color, overlay_rect);
+    for vertex in vertices[start..].chunks_mut(VERTEX_SIZE) {
+        vertex[8] = opacity;
+    }
+}
+
+/// Add a filled rectangle (in screen pixels) to a list of vertices
+pub fn push_quad(
+    vertices: &mut Vec<f32>,
+    (x0, y0): (f32, f32),
+    (x1, y1): (f32, f32),
+    color: [f32; 3],
+    overlay_rect: &Rect2D,
+) {
+    push_triangle(
+        vertices,
+        [(x0, y0), (x1, y0), (x1, y1)],
+        color,
+        overlay_rect,
+    );
+    push_triangle(
+        vertices,
+        [(x0, y0), (x1, y1), (x0, y1)],
+        color,
+        overlay_rect,
+    );
+}
+
+/// Add the shaft and heads of an arrow going from the first to the last point of the stroke
+pub fn tessellate_arrow(
+    stroke: &mut Stroke,
+    head: ArrowHead,
+    both_ends: bool,
+    overlay_rect: &Rect2D,
+) {
+    let (start, end) = match (stroke.points.first(), stroke.points.last()) {
+        (Some(&start), Some(&end)) => (start, end),
+        _ => return,
+    };
+
+    let dx = end.x - start.x;
+    let dy = end.y - start.y;
+    let len = (dx * dx + dy * dy).sqrt();
+    if len == 0.0 {
+        return;
+    }
+
+    // Unit direction and normal of the shaft
+    let (ux, uy) = (dx / len, dy / len);
+    let (nx, ny) = (-uy, ux);
+
+    // Heads grow with the line width, but never overlap each other
+    let max_head_len = if both_ends { len / 2.0 } else { len };
+    let head_len = (stroke.width * 4.0 + 8.0).min(max_head_len);
+    let head_half_width = head_len * 0.6;
+
+    let mut heads = vec![(end, 1.0)];
+    if both_ends {
+        heads.push((start, -1.0));
+    }
+
+    // Filled triangles cover the end of the shaft, so the shaft stops at their base
+    let mut shaft = [start, end];
+    if head == ArrowHead::Triangle {
+        shaft[1] = lerp_point(start, end, 1.0 - head_len / len);
+        if both_ends {
+            shaft[0] = lerp_point(start, end, head_len / len);
+        }
+    }
+    // Heads cover the ends of the shaft, only the start of a single head arrow tapers
+    let start_taper = if both_ends { 0.0 } else { stroke.taper };
+    tessellate_polyline(
+        &mut stroke.vertices,
+        &taper_centerline(&shaft, start_taper, 0.0),
+        stroke.width,
+        stroke.color,
+        stroke.hardness,
+        overlay_rect,
+    );
+
+    for (tip, direction) in heads {
+        // Center of the base of the head, behind the tip
+        let base_x = tip.x - ux * head_len * direction;
+        let base_y = tip.y - uy * head_len * direction;
+        let left = (base_x + nx * head_half_width, base_y + ny * head_half_width);
+        let right = (base_x - nx * head_half_width, base_y - ny * head_half_width);
+
+        match head {
+            ArrowHead::Triangle => push_triangle(
+                &mut stroke.vertices,
+                [(tip.x, tip.y), left, right],
+                stroke.color,
+                overlay_rect,
+            ),
+            ArrowHead::Open => {
+                for &(x, y) in [left, right].iter() {
+                    let side = [
+                        tip,
+                        StrokePoint {
+                            x,
+                            y,
+                            pressure: tip.pressure,
+                            time: tip.time,
+                        },
+                    ];
+                    tessellate_polyline(
+                        &mut stroke.vertices,
+                        &side,
+                        stroke.width,
+                        stroke.color,
+                        stroke.hardness,
+                        overlay_rect,
+                    );
+                }
+            }
+            ArrowHead::Dot => {
+                let radius = head_half_width;
+                let n_segments = 16;
+                for i in 0..n_segments {
+                    let a1 = i as f32 / n_segments as f32 * 2.0 * PI;
+                    let a2 = (i + 1) as f32 / n_segments as f32 * 2.0 * PI;
+                    push_triangle(
+                        &mut stroke.vertices,
+                        [
+                            (tip.x, tip.y),
+                            (tip.x + a1.cos() * radius, tip.y + a1.sin() * radius),
+                            (tip.x + a2.cos() * radius, tip.y + a2.sin() * radius),
+                        ],
+                        stroke.color,
+                        overlay_rect,
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Add the blocks of a redaction covering the rectangle between the first and last points
+pub fn tessellate_redaction(stroke: &mut Stroke, columns: usize, overlay_rect: &Rect2D) {
+    let (a, b) = match (stroke.points.first(), stroke.points.last()) {
+        (Some(&a), Some(&b)) => (a, b),
+        _ => return,
+    };
+    let (min_x, min_y) = (a.x.min(b.x), a.y.min(b.y));
+    let (max_x, max_y) = (a.x.max(b.x), a.y.max(b.y));
+
+    if stroke.blocks.is_empty() || columns == 0 {
+        push_quad(
+            &mut stroke.vertices,
+            (min_x, min_y),
+            (max_x, max_y),
+            stroke.color,
+            overlay_rect,
+        );
+        return;
+    }
+
+    let rows = stroke.blocks.len() / columns;
+    let block_width = (max_x - min_x) / columns as f32;
+    let block_height = (max_y - min_y) / rows as f32;
+    for (i, &color) in stroke.blocks.iter().enumerate() {
+        let x = min_x + (i % columns) as f32 * block_width;
+        let y = min_y + (i / columns) as f32 * block_height;
+        push_quad(
+            &mut stroke.vertices,
+            (x, y),
+            (x + block_width, y + block_height),
+            color,
+            overlay_rect,
+        );
+    }
+}
+
+/// Contrasting halo around a stroke, drawn under it so it shows over any background, not
+/// tessellated yet. Text, redactions and images have none
+pub fn outline_stroke(stroke: &Stroke) -> Option<Stroke> {
+    let shape = match stroke.shape {
+        Shape::Text { .. } | Shape::Redaction { .. } | Shape::Image => return None,
+        // Only the box outline gets a halo, not its tinted inside
+        Shape::HighlightBox { .. } => Shape::HighlightBox { fill_opacity: 0.0 },
+        shape => shape,
+    };
+    // Black around light ink, white around dark ink
+    let [r, g, b] = stroke.color;
+    let luminance = 0.299 * r + 0.587 * g + 0.114 * b;
+    let color = if luminance > 0.5 {
+        [0.0, 0.0, 0.0]
+    } else {
+        [1.0, 1.0, 1.0]
+    };
+    Some(Stroke {
+        points: stroke.points.clone(),
+        color,
+        width: stroke.width + OUTLINE_WIDTH,
+        hardness: stroke.hardness,
+        shape,
+        taper: stroke.taper,
+        ..Default::default()
+    })
+}
+
+/// Add the bold outline and tinted inside of a box between the first and last points
+pub fn tessellate_highlight_box(stroke: &mut Stroke, fill_opacity: f32, overlay_rect: &Rect2D) {
+    let (a, b) = match (stroke.points.first(), stroke.points.last()) {
+        (Some(&a), Some(&b)) => (a, b),
+        _ => return,
+    };
+    let (min_x, min_y) = (a.x.min(b.x), a.y.min(b.y));
+    let (max_x, max_y) = (a.x.max(b.x), a.y.max(b.y));
+    let w = stroke.width;
+
+    if fill_opacity > 0.0 {
+        for &corners in [
+            [(min_x, min_y), (max_x, min_y), (max_x, max_y)],
+            [(min_x, min_y), (max_x, max_y), (min_x, max_y)],
+        ]
+        .iter()
+        {
+            push_translucent_triangle(
+                &mut stroke.vertices,
+                corners,
+                stroke.color,
+                fill_opacity,
+                overlay_rect,
+            );
+        }
+    }
+
+    // Top and bottom sides cover the corners, left and right sides fit between them
+    for &(start, end) in [
+        ((min_x - w, min_y - w), (max_x + w, min_y + w)),
+        ((min_x - w, max_y - w), (max_x + w, max_y + w)),
+        ((min_x - w, min_y + w), (min_x + w, max_y - w)),
+        ((max_x - w, min_y + w), (max_x + w, max_y - w)),
+    ]
+    .iter()
+    {
+        push_quad(&mut stroke.vertices, start, end, stroke.color, overlay_rect);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Random centerlines are checked against each property, from a fixed seed so failures repeat
+    const N_CASES: usize = 500;
+
+    /// Xorshift generator, enough to spread test cases without a dependency
+    struct Random(u64);
+
+    impl Random {
+        fn next(&mut self) -> f32 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            (self.0 >> 40) as f32 / (1u64 << 24) as f32
+        }
+
+        fn range(&mut self, min: f32, max: f32) -> f32 {
+            min + self.next() * (max - min)
+        }
+
+        /// A centerline wandering around the screen, without repeated points
+        fn polyline(&mut self) -> Vec<StrokePoint> {
+            let n_points = 2 + (self.next() * 40.0) as usize;
+            let mut points = vec![self.point(0.0, 1920.0)];
+            while points.len() < n_points {
+                let last = points[points.len() - 1];
+                let next = StrokePoint {
+                    x: last.x + self.range(-40.0, 40.0),
+                    y: last.y + self.range(-40.0, 40.0),
+                    pressure: self.range(0.1, 1.0),
+                    time: 0.0,
+                };
+                if (next.x - last.x).hypot(next.y - last.y) > 0.5 {
+                    points.push(next);
+                }
+            }
+            points
+        }
+
+        /// A centerline turning less than 60 degrees at each point, with segments longer than
+        /// the line is wide, as pens draw at usual speeds
+        fn pen_path(&mut self) -> Vec<StrokePoint> {
+            let n_points = 2 + (self.next() * 40.0) as usize;
+            let mut points = vec![self.point(0.0, 1080.0)];
+            let mut heading = self.range(0.0, 2.0 * PI);
+            while points.len() < n_points {
+                let last = points[points.len() - 1];
+                let len = self.range(10.0, 40.0);
+                heading += self.range(-PI / 3.0, PI / 3.0);
+                points.push(StrokePoint {
+                    x: last.x + heading.cos() * len,
+                    y: last.y + heading.sin() * len,
+                    pressure: self.range(0.1, 1.0),
+                    time: 0.0,
+                });
+            }
+            points
+        }
+
+        fn point(&mut self, min: f32, max: f32) -> StrokePoint {
+            StrokePoint {
+                x: self.range(min, max),
+                y: self.range(min, max),
+                pressure: self.range(0.1, 1.0),
+                time: 0.0,
+            }
+        }
+    }
+
+    fn point(x: f32, y: f32) -> StrokePoint {
+        StrokePoint {
+            x,
+            y,
+            pressure: 1.0,
+            time: 0.0,
+        }
+    }
+
+    fn screen() -> Rect2D {
+        Rect2D {
+            x: 0.0,
+            y: 0.0,
+            width: 1920.0,
+            height: 1080.0,
+        }
+    }
+
+    fn same_point(a: StrokePoint, b: StrokePoint) -> bool {
+        a.x == b.x && a.y == b.y && a.pressure == b.pressure
+    }
+
+    #[test]
+    fn smoothing_preserves_endpoints() {
+        let mut random = Random(1);
+        for _ in 0..N_CASES {
+            let mut points = random.polyline();
+            let (first, last) = (points[0], points[points.len() - 1]);
+            smooth_polyline(&mut points, 1 + (random.next() * 8.0) as usize);
+            assert!(same_point(points[0], first));
+            assert!(same_point(points[points.len() - 1], last));
+        }
+    }
+
+    #[test]
+    fn smoothing_keeps_the_point_count_and_bounds() {
+        let mut random = Random(2);
+        for _ in 0..N_CASES {
+            let mut points = random.polyline();
+            let n_points = points.len();
+            let min_x = points.iter().map(|p| p.x).fold(f32::MAX, f32::min);
+            let max_x = points.iter().map(|p| p.x).fold(f32::MIN, f32::max);
+            let min_y = points.iter().map(|p| p.y).fold(f32::MAX, f32::min);
+            let max_y = points.iter().map(|p| p.y).fold(f32::MIN, f32::max);
+
+            smooth_polyline(&mut points, 1 + (random.next() * 8.0) as usize);
+
+            // Averages never leave the box of the points they come from
+            assert_eq!(points.len(), n_points);
+            for p in &points {
+                assert!(p.x >= min_x - 1e-3 && p.x <= max_x + 1e-3);
+                assert!(p.y >= min_y - 1e-3 && p.y <= max_y + 1e-3);
+                assert!(p.pressure > 0.0 && p.pressure <= 1.0 + 1e-6);
+            }
+        }
+    }
+
+    #[test]
+    fn smoothing_without_range_or_inner_points_changes_nothing() {
+        let mut random = Random(3);
+        let original = random.polyline();
+        let mut points = original.clone();
+        smooth_polyline(&mut points, 0);
+        assert!(points
+            .iter()
+            .zip(&original)
+            .all(|(&a, &b)| same_point(a, b)));
+
+        let mut segment = vec![point(0.0, 0.0), point(10.0, 5.0)];
+        smooth_polyline(&mut segment, 4);
+        assert!(same_point(segment[0], point(0.0, 0.0)));
+        assert!(same_point(segment[1], point(10.0, 5.0)));
+    }
+
+    #[test]
+    fn smoothing_straightens_a_zigzag() {
+        let mut points: Vec<_> = (0..20)
+            .map(|i| point(i as f32 * 10.0, if i % 2 == 0 { 0.0 } else { 10.0 }))
+            .collect();
+        smooth_polyline(&mut points, 2);
+        let inner = &points[2..points.len() - 2];
+        assert!(inner.iter().all(|p| p.y > 2.0 && p.y < 8.0));
+    }
+
+    #[test]
+    fn every_smoothing_mode_keeps_the_ends() {
+        let modes = [
+            SmoothingMode::None,
+            SmoothingMode::MovingAverage,
+            SmoothingMode::Exponential,
+            SmoothingMode::Spline,
+            SmoothingMode::OneEuro,
+        ];
+        let mut random = Random(11);
+        for _ in 0..N_CASES {
+            let original = random.polyline();
+            let range = 1 + (random.next() * 8.0) as usize;
+            for &mode in modes.iter() {
+                let mut points = original.clone();
+                smooth_centerline(&mut points, mode, range, 2);
+                assert!(same_point(points[0], original[0]));
+                assert!(same_point(
+                    points[points.len() - 1],
+                    original[original.len() - 1]
+                ));
+                assert!(points.iter().all(|p| p.pressure > 0.0));
+            }
+        }
+    }
+
+    #[test]
+    fn every_smoothing_mode_evens_out_jitter() {
+        let modes = [
+            SmoothingMode::MovingAverage,
+            SmoothingMode::Exponential,
+            SmoothingMode::Spline,
+            SmoothingMode::OneEuro,
+        ];
+        let original: Vec<_> = (0..41)
+            .map(|i| point(i as f32 * 10.0, if i % 2 == 0 { 0.0 } else { 2.0 }))
+            .collect();
+        for &mode in modes.iter() {
+            let mut points = original.clone();
+            smooth_centerline(&mut points, mode, 3, 1);
+            let inner = &points[4..points.len() - 4];
+            let spread = inner.iter().map(|p| p.y).fold(f32::MIN, f32::max)
+                - inner.iter().map(|p| p.y).fold(f32::MAX, f32::min);
+            assert!(spread < 1.2, "{:?} left a spread of {}", mode, spread);
+        }
+    }
+
+    #[test]
+    fn tapers_narrow_the_ends_to_nothing() {
+        let points = vec![point(0.0, 0.0), point(100.0, 0.0), point(200.0, 0.0)];
+        let tapered = taper_centerline(&points, 20.0, 40.0);
+        assert_eq!(tapered[0].pressure, 0.0);
+        assert_eq!(tapered[tapered.len() - 1].pressure, 0.0);
+        assert!(same_point(
+            tapered[0],
+            StrokePoint {
+                pressure: 0.0,
+                ..points[0]
+            }
+        ));
+
+        // Full width between the tapers, growing steadily along them
+        for p in &tapered {
+            let expected = (p.x / 20.0).min((200.0 - p.x) / 40.0).min(1.0);
+            assert!((p.pressure - expected).abs() < 1e-4, "{:?}", p);
+        }
+        assert!(tapered.iter().filter(|p| p.x < 20.0).count() >= 8);
+    }
+
+    #[test]
+    fn tapers_are_shortened_on_short_lines() {
+        let points = vec![point(0.0, 0.0), point(30.0, 0.0)];
+        let tapered = taper_centerline(&points, 20.0, 40.0);
+        let widest = tapered.iter().map(|p| p.pressure).fold(0.0, f32::max);
+        assert!((widest - 1.0).abs() < 0.15);
+        assert!(taper_centerline(&points, 0.0, 0.0)
+            .iter()
+            .zip(&points)
+            .all(|(&a, &b)| same_point(a, b)));
+    }
+
+    #[test]
+    fn line_points_join_the_segments_of_each_line() {
+        let points = vec![point(0.0, 0.0), point(10.0, 0.0), point(10.0, 10.0)];
+        let mut line_points = Vec::new();
+        push_line_points(&mut line_points, &points, 4.0, [1.0, 0.0, 0.0], 0.5);
+        push_line_points(&mut line_points, &points[..2], 2.0, [0.0, 1.0, 0.0], 1.0);
+        push_line_points(&mut line_points, &points[..1], 2.0, [0.0, 1.0, 0.0], 1.0);
+
+        let line: Vec<&[f32]> = line_points.chunks(LINE_POINT_SIZE).collect();
+        assert_eq!(line.len(), 4 + 3);
+        let joined: Vec<f32> = line.iter().map(|p| p[9]).collect();
+        assert_eq!(joined, vec![0.0, 0.0, 1.0, 1.0, 0.0, 0.0, 1.0]);
+        // The point before the first segment is its start
+        assert_eq!(line[0][..2], line[1][..2]);
+        assert_eq!(
+            line[3][..9],
+            [10.0, 10.0, 1.0, 4.0, 1.0, 0.0, 0.0, 0.5, 1.0]
+        );
+        assert_eq!(line[6][3], 2.0);
+    }
+
+    #[test]
+    fn tessellation_produces_non_degenerate_triangles() {
+        // Sharper turns and segments shorter than the width twist the quads, the sides of a
+        // point follow the segment ending on it
+        let mut random = Random(4);
+        let rect = screen();
+        for _ in 0..N_CASES {
+            let points = random.pen_path();
+            let mut vertices = Vec::new();
+            tessellate_polyline(&mut vertices, &points, 4.0, [1.0, 0.0, 0.0], 0.5, &rect);
+
+            // Two triangles per segment
+            assert_eq!(vertices.len(), (points.len() - 1) * 6 * VERTEX_SIZE);
+            for triangle in vertices.chunks(3 * VERTEX_SIZE) {
+                let corner = |i: usize| {
+                    let v = i * VERTEX_SIZE;
+                    // Back to pixels, so the area is comparable to the line width
+                    (
+                        triangle[v] * rect.width / 2.0,
+                        triangle[v + 1] * rect.height / 2.0,
+                    )
+                };
+                let ((x0, y0), (x1, y1), (x2, y2)) = (corner(0), corner(1), corner(2));
+                let area = ((x1 - x0) * (y2 - y0) - (x2 - x0) * (y1 - y0)).abs() / 2.0;
+                assert!(area > 1e-3, "degenerate triangle in {:?}", points);
+            }
+        }
+    }
+
+    #[test]
+    fn tessellation_edges_go_across_the_line() {
+        let mut random = Random(5);
+        let mut vertices = Vec::new();
+        let points = random.polyline();
+        tessellate_polyline(&mut vertices, &points, 4.0, [0.0, 1.0, 0.0], 0.7, &screen());
+        for vertex in vertices.chunks(VERTEX_SIZE) {
+            assert_eq!(&vertex[3..6], &[0.0, 1.0, 0.0]);
+            assert!(vertex[6] == -1.0 || vertex[6] == 1.0);
+            assert_eq!(vertex[7], 0.7);
+            assert_eq!(vertex[8], 1.0);
+        }
+    }
+
+    #[test]
+    fn tessellation_of_a_single_point_is_empty() {
+        let mut vertices = Vec::new();
+        tessellate_polyline(
+            &mut vertices,
+            &[point(5.0, 5.0)],
+            4.0,
+            [1.0; 3],
+            1.0,
+            &screen(),
+        );
+        assert!(vertices.is_empty());
+    }
+
+    #[test]
+    fn extending_a_line_matches_tessellating_it_whole() {
+        let mut random = Random(7);
+        let rect = screen();
+        for _ in 0..N_CASES {
+            let points = random.pen_path();
+            let mut whole = Vec::new();
+            tessellate_polyline(&mut whole, &points, 4.0, [1.0, 0.0, 0.0], 0.5, &rect);
+
+            // Point by point, as a line being drawn
+            let mut extended = Vec::new();
+            for n in 1..=points.len() {
+                extend_polyline(
+                    &mut extended,
+                    &points[..n],
+                    n - 1,
+                    4.0,
+                    [1.0, 0.0, 0.0],
+                    0.5,
+                    &rect,
+                );
+            }
+            assert_eq!(extended, whole);
+            assert_eq!(extended.len(), (points.len() - 1) * SEGMENT_SIZE);
+        }
+    }
+
+    #[test]
+    fn distance_is_zero_on_the_segment() {
+        let mut random = Random(6);
+        for _ in 0..N_CASES {
+            let (a, b) = (random.point(0.0, 1000.0), random.point(0.0, 1000.0));
+            let on_segment = lerp_point(a, b, random.next());
+            assert!(distance_to_segment(a.x, a.y, a, b) < 1e-3);
+            assert!(distance_to_segment(b.x, b.y, a, b) < 1e-3);
+            assert!(distance_to_segment(on_segment.x, on_segment.y, a, b) < 1e-2);
+        }
+    }
+
+    #[test]
+    fn distance_is_symmetric_and_bounded_by_the_ends() {
+        let mut random = Random(7);
+        for _ in 0..N_CASES {
+            let (a, b, p) = (
+                random.point(0.0, 1000.0),
+                random.point(0.0, 1000.0),
+                random.point(0.0, 1000.0),
+            );
+            let distance = distance_to_segment(p.x, p.y, a, b);
+            assert!((distance - distance_to_segment(p.x, p.y, b, a)).abs() < 1e-2);
+            assert!(distance <= (p.x - a.x).hypot(p.y - a.y) + 1e-3);
+            assert!(distance <= (p.x - b.x).hypot(p.y - b.y) + 1e-3);
+        }
+    }
+
+    #[test]
+    fn distance_to_a_point_segment_is_the_point_distance() {
+        let p = point(3.0, 4.0);
+        assert_eq!(distance_to_segment(0.0, 0.0, p, p), 5.0);
+    }
+
+    #[test]
+    fn circle_intersection_ends_on_the_circle() {
+        let mut random = Random(8);
+        for _ in 0..N_CASES {
+            let (a, b) = (random.point(0.0, 200.0), random.point(0.0, 200.0));
+            let (x, y, radius) = (
+                random.range(0.0, 200.0),
+                random.range(0.0, 200.0),
+                random.range(5.0, 80.0),
+            );
+            let is_inside = |p: StrokePoint| (p.x - x).hypot(p.y - y) <= radius + 1e-2;
+            match circle_segment_intersection(x, y, radius, a, b) {
+                Some((t_in, t_out)) => {
+                    assert!(0.0 <= t_in && t_in <= t_out && t_out <= 1.0);
+                    assert!(is_inside(lerp_point(a, b, t_in)));
+                    assert!(is_inside(lerp_point(a, b, t_out)));
+                    assert!(is_inside(lerp_point(a, b, (t_in + t_out) / 2.0)));
+                }
+                None => assert!(distance_to_segment(x, y, a, b) >= radius - 1e-2),
+            }
+        }
+    }
+
+    #[test]
+    fn simplification_keeps_the_ends_and_stays_within_tolerance() {
+        let mut random = Random(9);
+        for _ in 0..N_CASES {
+            let points = random.polyline();
+            let tolerance = random.range(0.1, 5.0);
+            let simplified = simplify_polyline(&points, tolerance, 4.0);
+
+            assert!(simplified.len() >= 2 && simplified.len() <= points.len());
+            assert!(same_point(simplified[0], points[0]));
+            assert!(same_point(
+                simplified[simplified.len() - 1],
+                points[points.len() - 1]
+            ));
+            // Every dropped point is close to the simplified line
+            for p in &points {
+                let distance = simplified
+                    .windows(2)
+                    .map(|s| distance_to_segment(p.x, p.y, s[0], s[1]))
+                    .fold(f32::MAX, f32::min);
+                assert!(distance <= tolerance + 1e-3);
+            }
+        }
+    }
+
+    #[test]
+    fn simplification_drops_points_of_a_straight_line() {
+        let points: Vec<_> = (0..50).map(|i| point(i as f32 * 3.0, 100.0)).collect();
+        let simplified = simplify_polyline(&points, 0.25, 4.0);
+        assert_eq!(simplified.len(), 2);
+    }
+
+    #[test]
+    fn simplification_keeps_pressure_changes() {
+        let mut points: Vec<_> = (0..50).map(|i| point(i as f32 * 3.0, 100.0)).collect();
+        points[25].pressure = 0.2;
+        let simplified = simplify_polyline(&points, 0.25, 4.0);
+        assert!(simplified.iter().any(|p| p.pressure == 0.2));
+    }
+
+    #[test]
+    fn simplification_keeps_corners() {
+        let points = [point(0.0, 0.0), point(50.0, 0.0), point(50.0, 50.0)];
+        assert_eq!(simplify_polyline(&points, 0.25, 4.0).len(), 3);
+    }
+
+    #[test]
+    fn nearly_straight_lines_are_straightened() {
+        let mut points: Vec<_> = (0..50)
+            .map(|i| point(i as f32 * 3.0, 100.0 + (i as f32 * PI / 7.0).sin() * 2.0))
+            .collect();
+        points[20].pressure = 0.2;
+        let (first, last) = (points[0], points[49]);
+        let straight = straighten_polyline(&points, 3.0).unwrap();
+
+        assert_eq!(straight.len(), points.len());
+        assert!(same_point(straight[0], first));
+        assert!(same_point(straight[49], last));
+        for p in &straight {
+            assert!(distance_to_segment(p.x, p.y, first, last) < 1e-3);
+        }
+        assert_eq!(straight[20].pressure, 0.2);
+    }
+
+    #[test]
+    fn nearly_closed_loops_are_closed() {
+        let mut circle: Vec<_> = (0..60)
+            .map(|i| {
+                let angle = i as f32 / 60.0 * 1.95 * PI;
+                point(100.0 + angle.cos() * 50.0, 100.0 + angle.sin() * 50.0)
+            })
+            .collect();
+        let first = circle[0];
+        assert!(close_polyline(&mut circle, 16.0));
+        assert!(same_point(circle[59], first));
+
+        // Already closed, too far apart or too short
+        assert!(!close_polyline(&mut circle, 16.0));
+        let mut open = [point(0.0, 0.0), point(50.0, 50.0), point(100.0, 0.0)];
+        assert!(!close_polyline(&mut open, 16.0));
+        let mut tick = [point(0.0, 0.0), point(5.0, 5.0), point(8.0, 0.0)];
+        assert!(!close_polyline(&mut tick, 16.0));
+    }
+
+    #[test]
+    fn curves_and_dots_are_not_straightened() {
+        let arc: Vec<_> = (0..50)
+            .map(|i| {
+                let angle = i as f32 / 49.0 * PI;
+                point(100.0 + angle.cos() * 50.0, 100.0 + angle.sin() * 50.0)
+            })
+            .collect();
+        assert!(straighten_polyline(&arc, 3.0).is_none());
+
+        let dot = [point(10.0, 10.0), point(11.0, 10.5), point(12.0, 10.0)];
+        assert!(straighten_polyline(&dot, 3.0).is_none());
+    }
+}
@@ -0,0 +1,404 @@
+// JSON interchange format of the strokes, for external tools analyzing or converting drawings,
+// and for annotation templates imported back into Inke
+//
+// The format is versioned: fields may be added to a version, readers ignore the fields they
+// don't know. Removing or changing the meaning of a field needs a new version.
+//
+// {
+//   "format": "inke-strokes",
+//   "version": 1,
+//   "width": 1920, "height": 1080,
+//   "strokes": [
+//     {
+//       "shape": {"type": "freehand"},
+//       "color": [255, 0, 0], "width": 3.0, "hardness": 1.0,
+//       "start_time": 1700000000000,
+//       "points": [{"x": 10.0, "y": 20.0, "pressure": 1.0, "time": 16.0}, ...]
+//     },
+//     {"shape": {"type": "arrow", "head": "triangle", "both_ends": false}, ...},
+//     {"shape": {"type": "highlight_box", "fill_opacity": 0.2}, ...},
+//     {"shape": {"type": "redaction", "columns": 12}, "blocks": [[12, 40, 200], ...], ...},
+//     {"shape": {"type": "text", "size": 24.0}, "text": "Hello", ...},
+//     {"shape": {"type": "image"}, "image": "iVBORw0KGgo...", ...}
+//   ]
+// }
+//
+// Positions are in pixels from the top left of the overlay, which covers all the screens and is
+// `width` by `height` pixels. Colors are rgb from 0 to 255. `group` and `locked` are only
+// written for grouped and locked strokes, `taper` for tapered ones. Strokes drawn live have the
+// milliseconds since the Unix epoch when they started, and their points the milliseconds since
+// then, others have neither. Images are PNG files in base64, stretched between the first and
+// last points.
+
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::base64;
+
+use crate::stroke::{ArrowHead, Shape, Stroke, StrokePoint};
+
+pub const FORMAT_NAME: &str = "inke-strokes";
+pub const FORMAT_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Document {
+    pub format: String,
+    pub version: u32,
+    pub width: f32,
+    pub height: f32,
+    pub strokes: Vec<StrokeData>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StrokeData {
+    pub shape: ShapeData,
+    pub color: [u8; 3],
+    pub width: f32,
+    pub hardness: f32,
+    pub points: Vec<PointData>,
+    /// Colors of the blocks of a redaction, row by row
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub blocks: Vec<[u8; 3]>,
+    /// Content of a text annotation, lines separated by \n
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub text: String,
+    /// PNG file of an image, in base64
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<u32>,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub locked: bool,
+    /// Length in pixels over which the width narrows to nothing at the ends
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub taper: f32,
+    /// Milliseconds since the Unix epoch when the stroke started, for the times of its points
+    #[serde(default, skip_serializing_if = "is_zero_time")]
+    pub start_time: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct PointData {
+    pub x: f32,
+    pub y: f32,
+    pub pressure: f32,
+    /// Milliseconds since the stroke started
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub time: f32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ShapeData {
+    Freehand,
+    Arrow { head: ArrowHead, both_ends: bool },
+    Redaction { columns: usize },
+    HighlightBox { fill_opacity: f32 },
+    Text { size: f32 },
+    Image,
+}
+
+fn is_false(value: &bool) -> bool {
+    !*value
+}
+
+fn is_zero(value: &f32) -> bool {
+    *value == 0.0
+}
+
+fn is_zero_time(value: &u64) -> bool {
+    *value == 0
+}
+
+fn color_to_bytes(color: [f32; 3]) -> [u8; 3] {
+    let byte = |channel: f32| (channel.clamp(0.0, 1.0) * 255.0).round() as u8;
+    [byte(color[0]), byte(color[1]), byte(color[2])]
+}
+
+fn color_from_bytes(color: [u8; 3]) -> [f32; 3] {
+    [
+        f32::from(color[0]) / 255.0,
+        f32::from(color[1]) / 255.0,
+        f32::from(color[2]) / 255.0,
+    ]
+}
+
+impl From<Shape> for ShapeData {
+    fn from(shape: Shape) -> Self {
+        match shape {
+            Shape::Freehand => ShapeData::Freehand,
+            Shape::Arrow { head, both_ends } => ShapeData::Arrow { head, both_ends },
+            Shape::Redaction { columns } => ShapeData::Redaction { columns },
+            Shape::HighlightBox { fill_opacity } => ShapeData::HighlightBox { fill_opacity },
+            Shape::Text { size } => ShapeData::Text { size },
+            Shape::Image => ShapeData::Image,
+        }
+    }
+}
+
+impl From<&Stroke> for StrokeData {
+    fn from(stroke: &Stroke) -> Self {
+        StrokeData {
+            shape: stroke.shape.into(),
+            color: color_to_bytes(stroke.color),
+            width: stroke.width,
+            hardness: stroke.hardness,
+            points: stroke
+                .points
+                .iter()
+                .map(|p| PointData {
+                    x: p.x,
+                    y: p.y,
+                    pressure: p.pressure,
+                    time: p.time,
+                })
+                .collect(),
+            blocks: stroke.blocks.iter().map(|&c| color_to_bytes(c)).collect(),
+            text: stroke.text.clone(),
+            image: stroke.image.as_ref().map(|png| base64::encode(png)),
+            group: stroke.group,
+            locked: stroke.locked,
+            taper: stroke.taper,
+            start_time: stroke.start_time,
+        }
+    }
+}
+
+impl From<ShapeData> for Shape {
+    fn from(shape: ShapeData) -> Self {
+        match shape {
+            ShapeData::Freehand => Shape::Freehand,
+            ShapeData::Arrow { head, both_ends } => Shape::Arrow { head, both_ends },
+            ShapeData::Redaction { columns } => Shape::Redaction { columns },
+            ShapeData::HighlightBox { fill_opacity } => Shape::HighlightBox { fill_opacity },
+            ShapeData::Text { size } => Shape::Text { size },
+            ShapeData::Image => Shape::Image,
+        }
+    }
+}
+
+/// The stroke isn't tessellated, its vertices are empty
+impl From<StrokeData> for Stroke {
+    fn from(stroke: StrokeData) -> Self {
+        Stroke {
+            points: stroke
+                .points
+                .iter()
+                .map(|p| StrokePoint {
+                    x: p.x,
+                    y: p.y,
+                    pressure: p.pressure,
+                    time: p.time,
+                })
+                .collect(),
+            color: color_from_bytes(stroke.color),
+            width: stroke.width,
+            hardness: stroke.hardness,
+            shape: stroke.shape.into(),
+            blocks: stroke.blocks.into_iter().map(color_from_bytes).collect(),
+            text: stroke.text,
+            image: stroke
+                .image
+                .and_then(|png| base64::decode(&png))
+                .map(Arc::new),
+            group: stroke.group,
+            locked: stroke.locked,
+            taper: stroke.taper,
+            start_time: stroke.start_time,
+            vertices: Vec::new(),
+        }
+    }
+}
+
+/// Encode strokes drawn on an overlay of `width` by `height` pixels
+pub fn export_json<'a>(
+    strokes: impl IntoIterator<Item = &'a Stroke>,
+    width: f32,
+    height: f32,
+) -> String {
+    let document = Document {
+        format: String::from(FORMAT_NAME),
+        version: FORMAT_VERSION,
+        width,
+        height,
+        strokes: strokes.into_iter().map(StrokeData::from).collect(),
+    };
+    serde_json::to_string_pretty(&document).expect("Failed to encode the strokes")
+}
+
+/// Decode the strokes of a document, refusing other formats and newer versions
+///
+/// The strokes aren't tessellated, strokes without points and images without a valid file are
+/// dropped.
+pub fn import_json(json: &str) -> Result<Vec<Stroke>, String> {
+    let document: Document = serde_json::from_str(json).map_err(|e| e.to_string())?;
+    if document.format != FORMAT_NAME {
+        return Err(format!("Not an {} file", FORMAT_NAME));
+    }
+    if document.version > FORMAT_VERSION {
+        return Err(format!(
+            "Version {} is newer than the supported version {}",
+            document.version, FORMAT_VERSION
+        ));
+    }
+    Ok(document
+        .strokes
+        .into_iter()
+        .filter(|stroke| !stroke.points.is_empty())
+        .map(Stroke::from)
+        .filter(|stroke| stroke.shape != Shape::Image || stroke.image.is_some())
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(x: f32, y: f32) -> StrokePoint {
+        StrokePoint {
+            x,
+            y,
+            pressure: 0.5,
+            time: 0.0,
+        }
+    }
+
+    #[test]
+    fn export_writes_the_format_and_version() {
+        let json = export_json(&[], 800.0, 600.0);
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["format"], FORMAT_NAME);
+        assert_eq!(value["version"], FORMAT_VERSION);
+        assert_eq!(value["width"], 800.0);
+        assert_eq!(value["strokes"], serde_json::json!([]));
+    }
+
+    #[test]
+    fn export_writes_the_style_and_tagged_shape() {
+        let stroke = Stroke {
+            points: vec![point(1.0, 2.0), point(3.0, 4.0)],
+            color: [1.0, 0.5, 0.0],
+            width: 3.0,
+            hardness: 0.75,
+            shape: Shape::Arrow {
+                head: ArrowHead::Open,
+                both_ends: true,
+            },
+            ..Default::default()
+        };
+        let json = export_json(&[stroke], 800.0, 600.0);
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        let stroke = &value["strokes"][0];
+        assert_eq!(
+            stroke["shape"],
+            serde_json::json!({"type": "arrow", "head": "open", "both_ends": true})
+        );
+        assert_eq!(stroke["color"], serde_json::json!([255, 128, 0]));
+        assert_eq!(stroke["hardness"], 0.75);
+        assert_eq!(
+            stroke["points"][1],
+            serde_json::json!({"x": 3.0, "y": 4.0, "pressure": 0.5})
+        );
+        // Unused fields are left out
+        assert!(stroke.get("text").is_none());
+        assert!(stroke.get("locked").is_none());
+    }
+
+    #[test]
+    fn import_reads_back_the_export() {
+        let strokes = vec![
+            Stroke {
+                points: vec![point(1.0, 2.0), point(3.0, 4.0), point(5.0, 2.0)],
+                color: [1.0, 0.0, 0.0],
+                width: 5.0,
+                hardness: 0.5,
+                group: Some(3),
+                ..Default::default()
+            },
+            Stroke {
+                points: vec![point(10.0, 10.0), point(60.0, 30.0)],
+                color: [0.0, 0.0, 1.0],
+                width: 1.0,
+                hardness: 1.0,
+                shape: Shape::Text { size: 24.0 },
+                text: String::from("Hello\nWorld"),
+                locked: true,
+                ..Default::default()
+            },
+        ];
+        let imported = import_json(&export_json(&strokes, 800.0, 600.0)).unwrap();
+        assert_eq!(imported.len(), strokes.len());
+        for (imported, stroke) in imported.iter().zip(&strokes) {
+            assert_eq!(StrokeData::from(imported), StrokeData::from(stroke));
+        }
+    }
+
+    #[test]
+    fn points_keep_their_time() {
+        let mut stroke = Stroke {
+            points: vec![point(1.0, 2.0), point(3.0, 4.0)],
+            start_time: 1_700_000_000_123,
+            ..Default::default()
+        };
+        stroke.points[1].time = 16.5;
+        let json = export_json(&[stroke], 800.0, 600.0);
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["strokes"][0]["start_time"], 1_700_000_000_123u64);
+        assert!(value["strokes"][0]["points"][0].get("time").is_none());
+        assert_eq!(value["strokes"][0]["points"][1]["time"], 16.5);
+
+        let imported = import_json(&json).unwrap();
+        assert_eq!(imported[0].start_time, 1_700_000_000_123);
+        assert_eq!(imported[0].points[1].time, 16.5);
+    }
+
+    #[test]
+    fn images_keep_their_file() {
+        let stroke = Stroke {
+            points: vec![point(10.0, 10.0), point(110.0, 60.0)],
+            shape: Shape::Image,
+            image: Some(Arc::new(vec![137, 80, 78, 71, 13, 10, 26, 10])),
+            ..Default::default()
+        };
+        let json = export_json(&[stroke], 800.0, 600.0);
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            value["strokes"][0]["shape"],
+            serde_json::json!({"type": "image"})
+        );
+        assert_eq!(value["strokes"][0]["image"], "iVBORw0KGgo=");
+
+        let imported = import_json(&json).unwrap();
+        assert_eq!(
+            imported[0].image.as_deref(),
+            Some(&vec![137, 80, 78, 71, 13, 10, 26, 10])
+        );
+
+        // Images without their file have nothing to show
+        let broken = json.replace("iVBORw0KGgo=", "not base64!");
+        assert!(import_json(&broken).unwrap().is_empty());
+    }
+
+    #[test]
+    fn import_ignores_unknown_fields() {
+        let json = r#"{"format": "inke-strokes", "version": 1, "width": 800, "height": 600,
+            "author": "someone", "strokes": [{"shape": {"type": "freehand"}, "color": [0, 0, 0],
+            "width": 3, "hardness": 1, "points": [{"x": 1, "y": 2, "pressure": 1, "tilt": 0}]}]}"#;
+        let strokes = import_json(json).unwrap();
+        assert_eq!(strokes.len(), 1);
+        assert_eq!(strokes[0].points[0].y, 2.0);
+    }
+
+    #[test]
+    fn import_refuses_other_formats_and_newer_versions() {
+        let other = r#"{"format": "other", "version": 1, "width": 1, "height": 1, "strokes": []}"#;
+        assert!(import_json(other).is_err());
+        let newer = format!(
+            r#"{{"format": "{}", "version": {}, "width": 1, "height": 1, "strokes": []}}"#,
+            FORMAT_NAME,
+            FORMAT_VERSION + 1
+        );
+        assert!(import_json(&newer).is_err());
+    }
+}
@@ -0,0 +1,65 @@
+// Headless part of Inke: the stroke model, its geometry and tessellation, without any window
+// or OpenGL code, so it can be tested and reused by other frontends
+
+pub mod base64;
+pub mod geometry;
+pub mod interchange;
+pub mod qr;
+pub mod stroke;
+pub mod timeline;
+
+// Floats per vertex: x, y, z, r, g, b, edge, hardness, opacity
+pub const VERTEX_SIZE: usize = 9;
+// Floats per point of a line expanded on the GPU: x, y, pressure, width, r, g, b, hardness,
+// opacity, joined
+pub const LINE_POINT_SIZE: usize = 10;
+
+#[derive(Default, Debug)]
+pub struct Rect2D {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+#[derive(Default, Debug)]
+pub struct Size2D {
+    pub width: f32,
+    pub height: f32,
+}
+
+#[derive(Default, Debug, Copy, Clone)]
+pub struct Point {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+impl Point {
+    pub fn to_array(self) -> [f32; 3] {
+        [self.x, self.y, self.z]
+    }
+}
+
+pub fn screen_size_to_gl(w: f32, h: f32, overlay_rect: &Rect2D) -> Size2D {
+    Size2D {
+        width: w / overlay_rect.width * 2.0,
+        height: h / overlay_rect.height * 2.0,
+    }
+}
+
+pub fn screen_point_to_gl(x: f32, y: f32, overlay_rect: &Rect2D) -> Point {
+    Point {
+        x: x / overlay_rect.width * 2.0,
+        y: y / overlay_rect.height * 2.0,
+        z: 0.0,
+    }
+}
+
+pub fn screen_to_gl(x: f32, y: f32, overlay_rect: &Rect2D) -> Point {
+    Point {
+        x: x / overlay_rect.width * 2.0 - 1.0,
+        y: y / overlay_rect.height * -2.0 + 1.0,
+        z: 0.0,
+    }
+}
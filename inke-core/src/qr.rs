@@ -0,0 +1,509 @@
+// QR codes of text, so audiences can scan a link off the screen
+//
+// Text is encoded in byte mode with the medium error correction level (15% of the code can be
+// damaged), in the smallest version it fits, following ISO/IEC 18004.
+
+// Error correction codewords per block and number of blocks for the medium level, by version
+const ECC_CODEWORDS_PER_BLOCK: [usize; 41] = [
+    0, 10, 16, 26, 18, 24, 16, 18, 22, 22, 26, 30, 22, 22, 24, 24, 28, 28, 26, 26, 26, 26, 28, 28,
+    28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28,
+];
+const ECC_BLOCKS: [usize; 41] = [
+    0, 1, 1, 1, 2, 2, 4, 4, 4, 5, 5, 5, 8, 9, 9, 10, 10, 11, 13, 14, 16, 17, 17, 18, 20, 21, 23,
+    25, 26, 28, 29, 31, 33, 35, 37, 38, 40, 43, 45, 47, 49,
+];
+const MAX_VERSION: usize = 40;
+// Bits of the medium level in the format information
+const MEDIUM_FORMAT_BITS: u32 = 0;
+
+/// Square of dark and light modules, without the quiet zone around it
+pub struct QrCode {
+    size: usize,
+    modules: Vec<bool>,
+    is_function: Vec<bool>, // Patterns that aren't data, never masked
+}
+
+impl QrCode {
+    /// None when the text is too long for the largest version
+    pub fn encode(text: &str) -> Option<QrCode> {
+        let bytes = text.as_bytes();
+        let version = (1..=MAX_VERSION).find(|&version| {
+            let count_bits = if version < 10 { 8 } else { 16 };
+            4 + count_bits + bytes.len() * 8 <= data_codewords(version) * 8
+        })?;
+
+        // Byte mode, character count, then the text
+        let mut bits = BitBuffer::default();
+        bits.push(0b0100, 4);
+        bits.push(bytes.len() as u32, if version < 10 { 8 } else { 16 });
+        for &byte in bytes {
+            bits.push(u32::from(byte), 8);
+        }
+
+        // Terminator, padding to a byte, then alternating pad bytes
+        let capacity = data_codewords(version) * 8;
+        bits.push(0, (capacity - bits.len()).min(4));
+        bits.push(0, (8 - bits.len() % 8) % 8);
+        for &pad in [0xEC, 0x11].iter().cycle() {
+            if bits.len() >= capacity {
+                break;
+            }
+            bits.push(pad, 8);
+        }
+
+        let mut code = QrCode {
+            size: version * 4 + 17,
+            modules: Vec::new(),
+            is_function: Vec::new(),
+        };
+        code.modules = vec![false; code.size * code.size];
+        code.is_function = vec![false; code.size * code.size];
+        code.draw_function_patterns(version);
+        code.draw_codewords(&add_error_correction(&bits.to_bytes(), version));
+
+        // Keep the mask that makes the code easiest to read
+        let mask = (0..8)
+            .min_by_key(|&mask| {
+                code.apply_mask(mask);
+                code.draw_format_bits(mask);
+                let penalty = code.penalty();
+                code.apply_mask(mask);
+                penalty
+            })
+            .unwrap();
+        code.apply_mask(mask);
+        code.draw_format_bits(mask);
+        Some(code)
+    }
+
+    /// Modules on a side
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn is_dark(&self, x: usize, y: usize) -> bool {
+        self.modules[y * self.size + x]
+    }
+
+    fn set_function(&mut self, x: usize, y: usize, is_dark: bool) {
+        self.modules[y * self.size + x] = is_dark;
+        self.is_function[y * self.size + x] = true;
+    }
+
+    fn draw_function_patterns(&mut self, version: usize) {
+        let size = self.size;
+        for i in 0..size {
+            self.set_function(6, i, i % 2 == 0);
+            self.set_function(i, 6, i % 2 == 0);
+        }
+
+        // Finders in three corners, with their light separators
+        for &(x, y) in [(3, 3), (size - 4, 3), (3, size - 4)].iter() {
+            for dy in -4i32..=4 {
+                for dx in -4i32..=4 {
+                    let (mx, my) = (x as i32 + dx, y as i32 + dy);
+                    if mx >= 0 && mx < size as i32 && my >= 0 && my < size as i32 {
+                        let distance = dx.abs().max(dy.abs());
+                        self.set_function(mx as usize, my as usize, distance != 2 && distance != 4);
+                    }
+                }
+            }
+        }
+
+        // Alignment patterns on a grid, except over the finders
+        let positions = alignment_positions(version);
+        let last = positions.len().saturating_sub(1);
+        let finder_corners = [(0, 0), (0, last), (last, 0)];
+        for (i, &x) in positions.iter().enumerate() {
+            for (j, &y) in positions.iter().enumerate() {
+                if finder_corners.contains(&(i, j)) {
+                    continue;
+                }
+                for dy in -2i32..=2 {
+                    for dx in -2i32..=2 {
+                        let is_dark = dx.abs().max(dy.abs()) != 1;
+                        self.set_function(
+                            (x as i32 + dx) as usize,
+                            (y as i32 + dy) as usize,
+                            is_dark,
+                        );
+                    }
+                }
+            }
+        }
+
+        // Reserved until the mask is known
+        self.draw_format_bits(0);
+
+        if version >= 7 {
+            let bits = version_bits(version);
+            for i in 0..18 {
+                let is_dark = (bits >> i) & 1 == 1;
+                let (a, b) = (size - 11 + i % 3, i / 3);
+                self.set_function(a, b, is_dark);
+                self.set_function(b, a, is_dark);
+            }
+        }
+    }
+
+    fn draw_format_bits(&mut self, mask: u32) {
+        let bits = format_bits(mask);
+        let bit = |i: usize| (bits >> i) & 1 == 1;
+        let size = self.size;
+
+        // Around the top left finder
+        for i in 0..6 {
+            self.set_function(8, i, bit(i));
+        }
+        self.set_function(8, 7, bit(6));
+        self.set_function(8, 8, bit(7));
+        self.set_function(7, 8, bit(8));
+        for i in 9..15 {
+            self.set_function(14 - i, 8, bit(i));
+        }
+
+        // Split between the two other finders
+        for i in 0..8 {
+            self.set_function(size - 1 - i, 8, bit(i));
+        }
+        for i in 8..15 {
+            self.set_function(8, size - 15 + i, bit(i));
+        }
+        self.set_function(8, size - 8, true);
+    }
+
+    /// Place the codewords in two module wide columns, zigzagging up and down from the right
+    fn draw_codewords(&mut self, codewords: &[u8]) {
+        let size = self.size;
+        let mut i = 0;
+        let mut right = size - 1;
+        while right >= 1 {
+            // The vertical timing pattern is skipped
+            if right == 6 {
+                right = 5;
+            }
+            for vertical in 0..size {
+                for j in 0..2 {
+                    let x = right - j;
+                    let is_upward = (right + 1) & 2 == 0;
+                    let y = if is_upward {
+                        size - 1 - vertical
+                    } else {
+                        vertical
+                    };
+                    if !self.is_function[y * size + x] && i < codewords.len() * 8 {
+                        self.modules[y * size + x] = (codewords[i / 8] >> (7 - i % 8)) & 1 == 1;
+                        i += 1;
+                    }
+                }
+            }
+            if right < 2 {
+                break;
+            }
+            right -= 2;
+        }
+    }
+
+    /// Flip the data modules of a mask pattern, applying it twice removes it
+    fn apply_mask(&mut self, mask: u32) {
+        for y in 0..self.size {
+            for x in 0..self.size {
+                let flip = match mask {
+                    0 => (x + y) % 2 == 0,
+                    1 => y % 2 == 0,
+                    2 => x % 3 == 0,
+                    3 => (x + y) % 3 == 0,
+                    4 => (x / 3 + y / 2) % 2 == 0,
+                    5 => x * y % 2 + x * y % 3 == 0,
+                    6 => (x * y % 2 + x * y % 3) % 2 == 0,
+                    _ => ((x + y) % 2 + x * y % 3) % 2 == 0,
+                };
+                let index = y * self.size + x;
+                if flip && !self.is_function[index] {
+                    self.modules[index] = !self.modules[index];
+                }
+            }
+        }
+    }
+
+    /// Penalty of the readers' rules: long runs, blocks, finder lookalikes and dark balance
+    fn penalty(&self) -> usize {
+        let size = self.size;
+        let mut penalty = 0;
+        let finder_like = [true, false, true, true, true, false, true];
+
+        for is_row in [true, false] {
+            for a in 0..size {
+                let line: Vec<bool> = (0..size)
+                    .map(|b| {
+                        if is_row {
+                            self.is_dark(b, a)
+                        } else {
+                            self.is_dark(a, b)
+                        }
+                    })
+                    .collect();
+
+                let mut run = 1;
+                for b in 1..=size {
+                    if b < size && line[b] == line[b - 1] {
+                        run += 1;
+                    } else {
+                        if run >= 5 {
+                            penalty += run - 2;
+                        }
+                        run = 1;
+                    }
+                }
+
+                // Finder lookalikes with four light modules on either side
+                for start in 0..=size - finder_like.len() {
+                    if line[start..start + finder_like.len()] != finder_like {
+                        continue;
+                    }
+                    let is_light = |from: usize, to: usize| line[from..to].iter().all(|&d| !d);
+                    let end = start + finder_like.len();
+                    if (start >= 4 && is_light(start - 4, start))
+                        || (end + 4 <= size && is_light(end, end + 4))
+                    {
+                        penalty += 40;
+                    }
+                }
+            }
+        }
+
+        for y in 0..size - 1 {
+            for x in 0..size - 1 {
+                let color = self.is_dark(x, y);
+                if color == self.is_dark(x + 1, y)
+                    && color == self.is_dark(x, y + 1)
+                    && color == self.is_dark(x + 1, y + 1)
+                {
+                    penalty += 3;
+                }
+            }
+        }
+
+        // 10 for each 5% away from half dark
+        let dark = self.modules.iter().filter(|&&d| d).count();
+        let total = size * size;
+        let deviation = (dark * 20).abs_diff(total * 10);
+        penalty + (deviation.div_ceil(total)).saturating_sub(1) * 10
+    }
+}
+
+#[derive(Default)]
+struct BitBuffer {
+    bits: Vec<bool>,
+}
+
+impl BitBuffer {
+    fn push(&mut self, value: u32, count: usize) {
+        for i in (0..count).rev() {
+            self.bits.push((value >> i) & 1 == 1);
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.bits.len()
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        self.bits
+            .chunks(8)
+            .map(|byte| byte.iter().fold(0, |acc, &bit| (acc << 1) | u8::from(bit)))
+            .collect()
+    }
+}
+
+/// Modules left for the codewords once the function patterns are drawn
+fn raw_data_modules(version: usize) -> usize {
+    let mut modules = (16 * version + 128) * version + 64;
+    if version >= 2 {
+        let alignments = version / 7 + 2;
+        modules -= (25 * alignments - 10) * alignments - 55;
+        if version >= 7 {
+            modules -= 36;
+        }
+    }
+    modules
+}
+
+fn data_codewords(version: usize) -> usize {
+    raw_data_modules(version) / 8 - ECC_CODEWORDS_PER_BLOCK[version] * ECC_BLOCKS[version]
+}
+
+/// Centers of the alignment patterns on each axis
+fn alignment_positions(version: usize) -> Vec<usize> {
+    if version == 1 {
+        return Vec::new();
+    }
+    let count = version / 7 + 2;
+    let size = version * 4 + 17;
+    let step = if version == 32 {
+        26
+    } else {
+        (version * 4 + count * 2 + 1) / (count * 2 - 2) * 2
+    };
+    let mut positions: Vec<usize> = (0..count - 1).map(|i| size - 7 - i * step).collect();
+    positions.push(6);
+    positions.reverse();
+    positions
+}
+
+/// Error correction level and mask with their BCH code
+fn format_bits(mask: u32) -> u32 {
+    let data = (MEDIUM_FORMAT_BITS << 3) | mask;
+    let mut remainder = data;
+    for _ in 0..10 {
+        remainder = (remainder << 1) ^ ((remainder >> 9) * 0x537);
+    }
+    ((data << 10) | remainder) ^ 0x5412
+}
+
+/// Version with its BCH code, for versions 7 and up
+fn version_bits(version: usize) -> u32 {
+    let mut remainder = version as u32;
+    for _ in 0..12 {
+        remainder = (remainder << 1) ^ ((remainder >> 11) * 0x1F25);
+    }
+    ((version as u32) << 12) | remainder
+}
+
+/// Split the data in blocks, add their error correction, and interleave them
+fn add_error_correction(data: &[u8], version: usize) -> Vec<u8> {
+    let block_count = ECC_BLOCKS[version];
+    let ecc_len = ECC_CODEWORDS_PER_BLOCK[version];
+    let raw_codewords = raw_data_modules(version) / 8;
+    // The last blocks hold one more data codeword
+    let short_blocks = block_count - raw_codewords % block_count;
+    let short_block_len = raw_codewords / block_count;
+    let divisor = reed_solomon_divisor(ecc_len);
+
+    let mut blocks = Vec::new();
+    let mut start = 0;
+    for i in 0..block_count {
+        let data_len = short_block_len - ecc_len + usize::from(i >= short_blocks);
+        let block_data = &data[start..start + data_len];
+        start += data_len;
+        let mut block = block_data.to_vec();
+        if i < short_blocks {
+            block.push(0);
+        }
+        block.extend(reed_solomon_remainder(block_data, &divisor));
+        blocks.push(block);
+    }
+
+    let mut codewords = Vec::with_capacity(raw_codewords);
+    for i in 0..=short_block_len {
+        for (j, block) in blocks.iter().enumerate() {
+            // Skip the padding of the short blocks
+            if i != short_block_len - ecc_len || j >= short_blocks {
+                codewords.push(block[i]);
+            }
+        }
+    }
+    codewords
+}
+
+/// Product in GF(2^8) modulo x^8 + x^4 + x^3 + x^2 + 1
+fn gf_multiply(x: u8, y: u8) -> u8 {
+    let mut z: u32 = 0;
+    for i in (0..8).rev() {
+        z = (z << 1) ^ ((z >> 7) * 0x11D);
+        z ^= u32::from((y >> i) & 1) * u32::from(x);
+    }
+    z as u8
+}
+
+/// Generator polynomial of a degree, without its leading 1
+fn reed_solomon_divisor(degree: usize) -> Vec<u8> {
+    let mut divisor = vec![0; degree];
+    divisor[degree - 1] = 1;
+    let mut root = 1;
+    for _ in 0..degree {
+        for j in 0..degree {
+            divisor[j] = gf_multiply(divisor[j], root);
+            if j + 1 < degree {
+                divisor[j] ^= divisor[j + 1];
+            }
+        }
+        root = gf_multiply(root, 0x02);
+    }
+    divisor
+}
+
+fn reed_solomon_remainder(data: &[u8], divisor: &[u8]) -> Vec<u8> {
+    let mut remainder = vec![0; divisor.len()];
+    for &byte in data {
+        let factor = byte ^ remainder.remove(0);
+        remainder.push(0);
+        for (r, &coefficient) in remainder.iter_mut().zip(divisor) {
+            *r ^= gf_multiply(coefficient, factor);
+        }
+    }
+    remainder
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn error_correction_matches_the_standard_example() {
+        // "HELLO WORLD" in version 1 with the medium level
+        let data = [
+            32, 91, 11, 120, 209, 114, 220, 77, 67, 64, 236, 17, 236, 17, 236, 17,
+        ];
+        assert_eq!(
+            reed_solomon_remainder(&data, &reed_solomon_divisor(10)),
+            [196, 35, 39, 119, 235, 215, 231, 226, 93, 23]
+        );
+    }
+
+    #[test]
+    fn format_and_version_bits_match_the_tables() {
+        assert_eq!(format_bits(0), 0b101010000010010);
+        assert_eq!(format_bits(5), 0b100000011001110);
+        assert_eq!(version_bits(7), 0x07C94);
+        assert_eq!(version_bits(40), 0x28C69);
+    }
+
+    #[test]
+    fn every_codeword_has_room() {
+        for version in 1..=MAX_VERSION {
+            let size = version * 4 + 17;
+            let mut code = QrCode {
+                size,
+                modules: vec![false; size * size],
+                is_function: vec![false; size * size],
+            };
+            code.draw_function_patterns(version);
+            let free = code.is_function.iter().filter(|&&f| !f).count();
+            assert_eq!(free, raw_data_modules(version), "version {}", version);
+            assert_eq!(
+                add_error_correction(&vec![0; data_codewords(version)], version).len(),
+                raw_data_modules(version) / 8
+            );
+        }
+    }
+
+    #[test]
+    fn text_gets_the_smallest_version() {
+        // 14 bytes fit in version 1, 26 in version 2
+        assert_eq!(QrCode::encode("abcdefghijklmn").unwrap().size(), 21);
+        assert_eq!(QrCode::encode("https://example.com/").unwrap().size(), 25);
+        assert_eq!(QrCode::encode(&"a".repeat(2331)).unwrap().size(), 177);
+        assert!(QrCode::encode(&"a".repeat(2332)).is_none());
+    }
+
+    #[test]
+    fn finders_are_in_three_corners() {
+        let code = QrCode::encode("https://example.com/").unwrap();
+        let size = code.size();
+        for &(x, y) in [(0, 0), (size - 7, 0), (0, size - 7)].iter() {
+            assert!(code.is_dark(x, y) && code.is_dark(x + 6, y + 6));
+            assert!(!code.is_dark(x + 1, y + 1) && code.is_dark(x + 3, y + 3));
+        }
+    }
+}
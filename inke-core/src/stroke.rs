@@ -0,0 +1,346 @@
+// Strokes drawn on the overlay, from their centerline
+
+use std::mem;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::geometry::{
+    circle_segment_intersection, distance_to_segment, extend_polyline, lerp_point,
+    push_line_points, smooth_centerline, taper_centerline, tessellate_arrow,
+    tessellate_highlight_box, tessellate_polyline, tessellate_redaction, SmoothingMode,
+    SEGMENT_SIZE,
+};
+use crate::Rect2D;
+
+/// A point of a stroke centerline, in screen pixels
+#[derive(Default, Debug, Copy, Clone)]
+pub struct StrokePoint {
+    pub x: f32,
+    pub y: f32,
+    pub pressure: f32,
+    pub time: f32, // Milliseconds since the stroke started, 0 for points not drawn live
+}
+
+/// A single continuous line drawn by the user
+///
+/// The centerline is kept so the stroke can be hit-tested and re-tessellated,
+/// `vertices` caches the triangles sent to the vbo.
+#[derive(Default, Debug, Clone)]
+pub struct Stroke {
+    pub points: Vec<StrokePoint>,
+    pub color: [f32; 3],
+    pub width: f32,
+    pub hardness: f32,
+    pub shape: Shape,
+    pub blocks: Vec<[f32; 3]>, // Colors of the blocks of a pixelated redaction, row by row
+    pub text: String,          // Content of a text annotation, lines separated by \n
+    pub image: Option<Arc<Vec<u8>>>, // PNG file of an image, shared by the copies of the stroke
+    pub group: Option<u32>,
+    pub locked: bool,
+    pub taper: f32, // Length (in pixels) over which the width narrows to nothing at the ends
+    pub start_time: u64, // Milliseconds since the Unix epoch when it started, 0 when unknown
+    pub vertices: Vec<f32>,
+}
+
+/// How a stroke is drawn from its centerline
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum Shape {
+    /// Line going through all the points
+    #[default]
+    Freehand,
+    /// Straight arrow from the first to the last point
+    Arrow { head: ArrowHead, both_ends: bool },
+    /// Rectangle between the first and last points, filled with the blocks or the stroke color
+    Redaction { columns: usize },
+    /// Outlined rectangle between the first and last points, with a see-through inside
+    HighlightBox { fill_opacity: f32 },
+    /// Text from the first point, the last point being the bottom right corner of its layout
+    Text { size: f32 },
+    /// PNG image stretched between the first and last points
+    Image,
+}
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ArrowHead {
+    Triangle,
+    Open,
+    Dot,
+}
+
+impl Stroke {
+    /// Rebuild the cached triangles from the centerline. Freehand lines are left without any when
+    /// `freehand_on_gpu`, their renderer expanding them from push_line_points instead
+    pub fn tessellate(&mut self, overlay_rect: &Rect2D, freehand_on_gpu: bool) {
+        self.vertices.clear();
+
+        match self.shape {
+            Shape::Freehand if freehand_on_gpu => (),
+            Shape::Freehand => tessellate_polyline(
+                &mut self.vertices,
+                &taper_centerline(&self.points, self.taper, self.taper),
+                self.width,
+                self.color,
+                self.hardness,
+                overlay_rect,
+            ),
+            Shape::Arrow { head, both_ends } => {
+                tessellate_arrow(self, head, both_ends, overlay_rect)
+            }
+            Shape::Redaction { columns } => tessellate_redaction(self, columns, overlay_rect),
+            Shape::HighlightBox { fill_opacity } => {
+                tessellate_highlight_box(self, fill_opacity, overlay_rect)
+            }
+            // Text and images go through their own renderers instead of the vbo
+            Shape::Text { .. } | Shape::Image => (),
+        }
+    }
+
+    /// Replace the triangles of the segments ending on the points from `from` on, keeping the
+    /// ones before, so a line being drawn isn't tessellated again whole at each point. The taper
+    /// depends on where the line ends, it is left to the next call to tessellate
+    pub fn tessellate_from(&mut self, from: usize, overlay_rect: &Rect2D, freehand_on_gpu: bool) {
+        if self.shape != Shape::Freehand {
+            self.tessellate(overlay_rect, freehand_on_gpu);
+        } else if !freehand_on_gpu {
+            // Segments missing before `from` are added too
+            let from = from.min(self.vertices.len() / SEGMENT_SIZE + 1);
+            self.vertices
+                .truncate(from.saturating_sub(1) * SEGMENT_SIZE);
+            extend_polyline(
+                &mut self.vertices,
+                &self.points,
+                from,
+                self.width,
+                self.color,
+                self.hardness,
+                overlay_rect,
+            );
+        }
+    }
+
+    /// Add the centerline of a freehand line to a list of line points, see
+    /// geometry::push_line_points. Other shapes are only drawn from their triangles
+    pub fn push_line_points(&self, line_points: &mut Vec<f32>) {
+        if self.shape == Shape::Freehand {
+            push_line_points(
+                line_points,
+                &taper_centerline(&self.points, self.taper, self.taper),
+                self.width,
+                self.color,
+                self.hardness,
+            );
+        }
+    }
+
+    /// Apply line smoothing to the centerline, see geometry::smooth_centerline
+    pub fn smooth(&mut self, mode: SmoothingMode, range: usize, intensity: usize) {
+        smooth_centerline(&mut self.points, mode, range, intensity);
+    }
+
+    /// Check if a circle (in screen pixels) touches the stroke
+    pub fn hit_test(&self, x: f32, y: f32, radius: f32) -> bool {
+        // Filled boxes are hit anywhere inside
+        let is_filled = match self.shape {
+            Shape::Redaction { .. } => true,
+            Shape::HighlightBox { fill_opacity } => fill_opacity > 0.0,
+            Shape::Text { .. } | Shape::Image => true,
+            _ => false,
+        };
+        if is_filled {
+            let bounds = self.bounds();
+            return x >= bounds.x - radius
+                && x <= bounds.x + bounds.width + radius
+                && y >= bounds.y - radius
+                && y <= bounds.y + bounds.height + radius;
+        }
+
+        // Empty boxes are only hit on their outline
+        if let (Shape::HighlightBox { .. }, Some(&a), Some(&b)) =
+            (self.shape, self.points.first(), self.points.last())
+        {
+            let corner = |x: f32, y: f32| StrokePoint {
+                x,
+                y,
+                pressure: 1.0,
+                time: 0.0,
+            };
+            let corners = [
+                corner(a.x, a.y),
+                corner(b.x, a.y),
+                corner(b.x, b.y),
+                corner(a.x, b.y),
+                corner(a.x, a.y),
+            ];
+            return corners
+                .windows(2)
+                .any(|side| distance_to_segment(x, y, side[0], side[1]) <= radius + self.width);
+        }
+
+        if self.points.len() == 1 {
+            let p = self.points[0];
+            return distance_to_segment(x, y, p, p) <= radius + self.width * p.pressure;
+        }
+
+        self.points.windows(2).any(|segment| {
+            let stroke_radius = self.width * segment[0].pressure.max(segment[1].pressure);
+            distance_to_segment(x, y, segment[0], segment[1]) <= radius + stroke_radius
+        })
+    }
+
+    /// Points (in screen pixels) other lines can be connected to
+    pub fn endpoints(&self) -> Vec<(f32, f32)> {
+        let (a, b) = match (self.points.first(), self.points.last()) {
+            (Some(&a), Some(&b)) => (a, b),
+            _ => return Vec::new(),
+        };
+        match self.shape {
+            Shape::Freehand | Shape::Arrow { .. } => vec![(a.x, a.y), (b.x, b.y)],
+            Shape::HighlightBox { .. } => vec![(a.x, a.y), (b.x, a.y), (b.x, b.y), (a.x, b.y)],
+            Shape::Redaction { .. } | Shape::Text { .. } | Shape::Image => Vec::new(),
+        }
+    }
+
+    /// Bounding box of the stroke, including its width
+    pub fn bounds(&self) -> Rect2D {
+        let mut min_x = f32::MAX;
+        let mut min_y = f32::MAX;
+        let mut max_x = f32::MIN;
+        let mut max_y = f32::MIN;
+
+        for p in &self.points {
+            let radius = self.width * p.pressure;
+            min_x = min_x.min(p.x - radius);
+            min_y = min_y.min(p.y - radius);
+            max_x = max_x.max(p.x + radius);
+            max_y = max_y.max(p.y + radius);
+        }
+
+        Rect2D {
+            x: min_x,
+            y: min_y,
+            width: max_x - min_x,
+            height: max_y - min_y,
+        }
+    }
+
+    /// Scale the centerline around a point, and optionally the width with it
+    pub fn scale(&mut self, x: f32, y: f32, factor: f32, scale_width: bool) {
+        for p in self.points.iter_mut() {
+            p.x = x + (p.x - x) * factor;
+            p.y = y + (p.y - y) * factor;
+        }
+        if scale_width {
+            self.width *= factor;
+        }
+        if let Shape::Text { size } = &mut self.shape {
+            *size *= factor;
+        }
+    }
+
+    /// Rotate the centerline around a point, angle in radians
+    pub fn rotate(&mut self, x: f32, y: f32, angle: f32) {
+        let (sin, cos) = angle.sin_cos();
+
+        // Text and images stay level, they only move with their corner
+        let is_level = matches!(self.shape, Shape::Text { .. } | Shape::Image);
+        if let (true, Some(&corner)) = (is_level, self.points.first()) {
+            let (dx, dy) = (corner.x - x, corner.y - y);
+            let offset_x = x + dx * cos - dy * sin - corner.x;
+            let offset_y = y + dx * sin + dy * cos - corner.y;
+            for p in self.points.iter_mut() {
+                p.x += offset_x;
+                p.y += offset_y;
+            }
+            return;
+        }
+
+        for p in self.points.iter_mut() {
+            let dx = p.x - x;
+            let dy = p.y - y;
+            p.x = x + dx * cos - dy * sin;
+            p.y = y + dx * sin + dy * cos;
+        }
+    }
+
+    /// Remove the part of the centerline inside a circle (in screen pixels)
+    ///
+    /// Returns the remaining pieces when the stroke was touched, each piece being a new stroke
+    pub fn erase_circle(&self, x: f32, y: f32, radius: f32) -> Option<Vec<Stroke>> {
+        // Shapes can't be cut in pieces, they are erased whole
+        if self.shape != Shape::Freehand {
+            return if self.hit_test(x, y, radius) {
+                Some(Vec::new())
+            } else {
+                None
+            };
+        }
+
+        let first = *self.points.first()?;
+        let is_inside = |p: StrokePoint| (p.x - x).powi(2) + (p.y - y).powi(2) <= radius * radius;
+
+        let mut is_touched = is_inside(first);
+        let mut pieces = Vec::new();
+        let mut current = Vec::new();
+
+        if !is_touched {
+            current.push(first);
+        }
+
+        for segment in self.points.windows(2) {
+            let (a, b) = (segment[0], segment[1]);
+
+            match circle_segment_intersection(x, y, radius, a, b) {
+                None => current.push(b),
+                Some((t_in, t_out)) => {
+                    is_touched = true;
+
+                    // The segment enters the circle, end the current piece on the circle edge
+                    if t_in > 0.0 {
+                        current.push(lerp_point(a, b, t_in));
+                    }
+                    if current.len() > 1 {
+                        pieces.push(mem::take(&mut current));
+                    }
+                    current.clear();
+
+                    // The segment exits the circle, start a new piece on the circle edge
+                    if t_out < 1.0 {
+                        current.push(lerp_point(a, b, t_out));
+                        current.push(b);
+                    }
+                }
+            }
+        }
+
+        if !is_touched {
+            return None;
+        }
+
+        if current.len() > 1 {
+            pieces.push(current);
+        }
+
+        Some(
+            pieces
+                .into_iter()
+                .map(|points| Stroke {
+                    points,
+                    color: self.color,
+                    width: self.width,
+                    hardness: self.hardness,
+                    shape: self.shape,
+                    blocks: self.blocks.clone(),
+                    text: self.text.clone(),
+                    image: self.image.clone(),
+                    group: self.group,
+                    locked: self.locked,
+                    taper: self.taper,
+                    start_time: self.start_time,
+                    vertices: Vec::new(),
+                })
+                .collect(),
+        )
+    }
+}
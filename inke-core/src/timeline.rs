@@ -0,0 +1,239 @@
+// JSON timeline of a drawing session, for external renderers and playback: the strokes of the
+// interchange format with when they were drawn, on a single clock
+//
+// Versioned like the interchange format: fields may be added to a version, readers ignore the
+// fields they don't know.
+//
+// {
+//   "format": "inke-timeline",
+//   "version": 1,
+//   "width": 1920, "height": 1080,
+//   "start_time": 1700000000000,
+//   "duration": 5230.0,
+//   "strokes": [
+//     {
+//       "id": 0, "t0": 0.0, "t1": 850.0,
+//       "shape": {"type": "freehand"},
+//       "color": [255, 0, 0], "width": 3.0, "hardness": 1.0,
+//       "points": [{"x": 10.0, "y": 20.0, "pressure": 1.0}, {..., "time": 16.0}, ...]
+//     },
+//     ...
+//   ]
+// }
+//
+// Each stroke is a stroke of the interchange format, with its `id` (its place in the drawing,
+// from the bottom) and the milliseconds since `start_time` when it started (`t0`) and ended
+// (`t1`). A point was drawn at `t0` plus its `time`. Strokes are in drawing order, later ones
+// cover earlier ones. Strokes without a time, such as shapes and imported strokes, appear at
+// once when the strokes below them are done.
+
+use serde::{Deserialize, Serialize};
+
+use crate::interchange::StrokeData;
+use crate::stroke::{Shape, Stroke};
+
+pub const FORMAT_NAME: &str = "inke-timeline";
+pub const FORMAT_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Timeline {
+    pub format: String,
+    pub version: u32,
+    pub width: f32,
+    pub height: f32,
+    /// Milliseconds since the Unix epoch when the first stroke started, 0 if none has a time
+    pub start_time: u64,
+    /// Milliseconds from `start_time` to the end of the last stroke
+    pub duration: f32,
+    pub strokes: Vec<TimedStroke>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TimedStroke {
+    pub id: usize,
+    pub t0: f32,
+    pub t1: f32,
+    #[serde(flatten)]
+    pub stroke: StrokeData,
+}
+
+impl Timeline {
+    /// Timeline of strokes drawn on an overlay of `width` by `height` pixels, in drawing order
+    pub fn new<'a>(strokes: impl IntoIterator<Item = &'a Stroke>, width: f32, height: f32) -> Self {
+        let strokes: Vec<&Stroke> = strokes.into_iter().collect();
+        let start_time = strokes
+            .iter()
+            .map(|stroke| stroke.start_time)
+            .filter(|&time| time != 0)
+            .min()
+            .unwrap_or_default();
+
+        let mut end = 0.0f32;
+        let strokes: Vec<TimedStroke> = strokes
+            .into_iter()
+            .enumerate()
+            .map(|(id, stroke)| {
+                let t0 = if stroke.start_time == 0 {
+                    end
+                } else {
+                    (stroke.start_time - start_time) as f32
+                };
+                let t1 = t0 + stroke.points.iter().fold(0.0f32, |t, p| t.max(p.time));
+                end = end.max(t1);
+                TimedStroke {
+                    id,
+                    t0,
+                    t1,
+                    stroke: StrokeData::from(stroke),
+                }
+            })
+            .collect();
+
+        Timeline {
+            format: String::from(FORMAT_NAME),
+            version: FORMAT_VERSION,
+            width,
+            height,
+            start_time,
+            duration: end,
+            strokes,
+        }
+    }
+
+    /// The strokes as drawn `time` milliseconds after the start, with only the points drawn by
+    /// then. The strokes aren't tessellated
+    pub fn strokes_at(&self, time: f32) -> Vec<Stroke> {
+        self.strokes
+            .iter()
+            .filter(|timed| timed.t0 <= time)
+            .filter_map(|timed| {
+                let mut stroke = Stroke::from(timed.stroke.clone());
+                // Shapes are placed whole, only lines are drawn point by point
+                if stroke.shape == Shape::Freehand {
+                    stroke.points.retain(|p| timed.t0 + p.time <= time);
+                }
+                if stroke.points.is_empty() {
+                    None
+                } else {
+                    Some(stroke)
+                }
+            })
+            .collect()
+    }
+}
+
+/// Encode the timeline of strokes drawn on an overlay of `width` by `height` pixels
+pub fn export_json<'a>(
+    strokes: impl IntoIterator<Item = &'a Stroke>,
+    width: f32,
+    height: f32,
+) -> String {
+    serde_json::to_string_pretty(&Timeline::new(strokes, width, height))
+        .expect("Failed to encode the timeline")
+}
+
+/// Decode a timeline, refusing other formats and newer versions
+pub fn import_json(json: &str) -> Result<Timeline, String> {
+    let timeline: Timeline = serde_json::from_str(json).map_err(|e| e.to_string())?;
+    if timeline.format != FORMAT_NAME {
+        return Err(format!("Not an {} file", FORMAT_NAME));
+    }
+    if timeline.version > FORMAT_VERSION {
+        return Err(format!(
+            "Version {} is newer than the supported version {}",
+            timeline.version, FORMAT_VERSION
+        ));
+    }
+    Ok(timeline)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stroke::{ArrowHead, StrokePoint};
+
+    fn point(x: f32, time: f32) -> StrokePoint {
+        StrokePoint {
+            x,
+            y: 0.0,
+            pressure: 1.0,
+            time,
+        }
+    }
+
+    fn line(start_time: u64, times: &[f32]) -> Stroke {
+        Stroke {
+            points: times.iter().map(|&time| point(time, time)).collect(),
+            start_time,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn strokes_are_placed_on_the_clock_of_the_first() {
+        let strokes = [
+            line(1_000_500, &[0.0, 20.0, 40.0]),
+            line(1_000_000, &[0.0, 100.0]),
+        ];
+        let timeline = Timeline::new(&strokes, 800.0, 600.0);
+        assert_eq!(timeline.start_time, 1_000_000);
+        assert_eq!(timeline.duration, 540.0);
+        assert_eq!(timeline.strokes[0].id, 0);
+        assert_eq!(
+            (timeline.strokes[0].t0, timeline.strokes[0].t1),
+            (500.0, 540.0)
+        );
+        assert_eq!(
+            (timeline.strokes[1].t0, timeline.strokes[1].t1),
+            (0.0, 100.0)
+        );
+    }
+
+    #[test]
+    fn untimed_strokes_follow_the_ones_below() {
+        let arrow = Stroke {
+            points: vec![point(0.0, 0.0), point(10.0, 0.0)],
+            shape: Shape::Arrow {
+                head: ArrowHead::Triangle,
+                both_ends: false,
+            },
+            ..Default::default()
+        };
+        let strokes = [line(2_000, &[0.0, 300.0]), arrow];
+        let timeline = Timeline::new(&strokes, 800.0, 600.0);
+        assert_eq!(
+            (timeline.strokes[1].t0, timeline.strokes[1].t1),
+            (300.0, 300.0)
+        );
+    }
+
+    #[test]
+    fn strokes_at_keeps_the_points_drawn_by_then() {
+        let strokes = [line(1_000, &[0.0, 50.0, 100.0]), line(1_200, &[0.0, 50.0])];
+        let timeline = Timeline::new(&strokes, 800.0, 600.0);
+        assert!(timeline.strokes_at(-1.0).is_empty());
+        let drawn = timeline.strokes_at(60.0);
+        assert_eq!(drawn.len(), 1);
+        assert_eq!(drawn[0].points.len(), 2);
+        let drawn = timeline.strokes_at(timeline.duration);
+        assert_eq!(drawn.len(), 2);
+        assert_eq!(drawn[1].points.len(), 2);
+    }
+
+    #[test]
+    fn import_reads_back_the_export() {
+        let strokes = [line(1_000, &[0.0, 16.0]), line(0, &[0.0, 0.0])];
+        let json = export_json(&strokes, 800.0, 600.0);
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["format"], FORMAT_NAME);
+        assert_eq!(value["strokes"][0]["t1"], 16.0);
+        assert_eq!(value["strokes"][0]["shape"]["type"], "freehand");
+        assert_eq!(value["strokes"][1]["id"], 1);
+
+        let timeline = import_json(&json).unwrap();
+        assert_eq!(timeline, Timeline::new(&strokes, 800.0, 600.0));
+
+        let strokes_file = crate::interchange::export_json(&strokes, 800.0, 600.0);
+        assert!(import_json(&strokes_file).is_err());
+    }
+}
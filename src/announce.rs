@@ -0,0 +1,21 @@
+// Spoken announcements of the tool, color and size, for presenters who can't see the overlay
+
+use std::process::{Command, Stdio};
+
+/// Run the text-to-speech command of the config with the text as its last argument, without
+/// waiting for it to finish
+pub fn speak(command: &[String], text: &str) {
+    let (program, args) = match command.split_first() {
+        Some(split) => split,
+        None => return,
+    };
+    let result = Command::new(program)
+        .args(args)
+        .arg(text)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn();
+    if let Err(e) = result {
+        log::error!("Failed to run the announcement command {}: {}", program, e);
+    }
+}
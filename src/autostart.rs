@@ -0,0 +1,167 @@
+// Start Inke hidden when the user logs in, so the overlay is always ready to be brought up
+
+use std::env;
+use std::io;
+#[cfg(not(target_os = "windows"))]
+use std::{fs, path::PathBuf};
+
+/// Command line used to start Inke at login
+///
+/// The config directory is the current one, since config.json is read from the working directory
+fn autostart_command() -> io::Result<Vec<String>> {
+    Ok(vec![
+        env::current_exe()?.display().to_string(),
+        "--start-hidden".to_string(),
+        "--config-dir".to_string(),
+        env::current_dir()?.display().to_string(),
+    ])
+}
+
+#[cfg(not(target_os = "windows"))]
+fn home_dir() -> io::Result<PathBuf> {
+    env::var_os("HOME")
+        .map(PathBuf::from)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "HOME is not set"))
+}
+
+// XDG autostart entry
+
+#[cfg(all(unix, not(target_os = "macos")))]
+fn entry_path() -> io::Result<PathBuf> {
+    let config_dir = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) => PathBuf::from(dir),
+        None => home_dir()?.join(".config"),
+    };
+    Ok(config_dir.join("autostart").join("inke.desktop"))
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+pub fn install() -> io::Result<()> {
+    // Quote every argument of the Exec key as the desktop entry spec requires
+    let exec = autostart_command()?
+        .iter()
+        .map(|arg| {
+            let mut quoted = String::from("\"");
+            for c in arg.chars() {
+                if c == '"' || c == '`' || c == '$' || c == '\\' {
+                    quoted.push('\\');
+                }
+                quoted.push(c);
+            }
+            quoted.push('"');
+            quoted
+        })
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    let path = entry_path()?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(
+        &path,
+        format!(
+            "[Desktop Entry]\nType=Application\nName=Inke\nExec={}\nX-GNOME-Autostart-enabled=true\n",
+            // Backslashes are escaped a second time as part of a string value
+            exec.replace('\\', "\\\\")
+        ),
+    )
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+pub fn uninstall() -> io::Result<()> {
+    fs::remove_file(entry_path()?)
+}
+
+// macOS LaunchAgent
+
+#[cfg(target_os = "macos")]
+fn entry_path() -> io::Result<PathBuf> {
+    Ok(home_dir()?
+        .join("Library")
+        .join("LaunchAgents")
+        .join("com.julienduranleau.inke.plist"))
+}
+
+#[cfg(target_os = "macos")]
+pub fn install() -> io::Result<()> {
+    let arguments: String = autostart_command()?
+        .iter()
+        .map(|arg| {
+            let escaped = arg
+                .replace('&', "&amp;")
+                .replace('<', "&lt;")
+                .replace('>', "&gt;");
+            format!("        <string>{}</string>\n", escaped)
+        })
+        .collect();
+
+    let path = entry_path()?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(
+        &path,
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>Label</key>
+    <string>com.julienduranleau.inke</string>
+    <key>ProgramArguments</key>
+    <array>
+{}    </array>
+    <key>RunAtLoad</key>
+    <true/>
+</dict>
+</plist>
+"#,
+            arguments
+        ),
+    )
+}
+
+#[cfg(target_os = "macos")]
+pub fn uninstall() -> io::Result<()> {
+    fs::remove_file(entry_path()?)
+}
+
+// Windows Run registry key
+
+#[cfg(target_os = "windows")]
+const RUN_KEY: &str = r"HKCU\Software\Microsoft\Windows\CurrentVersion\Run";
+
+#[cfg(target_os = "windows")]
+fn reg(args: &[&str]) -> io::Result<()> {
+    let status = std::process::Command::new("reg").args(args).status()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other("reg.exe failed"))
+    }
+}
+
+#[cfg(target_os = "windows")]
+pub fn install() -> io::Result<()> {
+    let command = autostart_command()?
+        .iter()
+        .map(|arg| {
+            if arg.starts_with("--") {
+                arg.clone()
+            } else {
+                format!("\"{}\"", arg)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    reg(&[
+        "add", RUN_KEY, "/v", "Inke", "/t", "REG_SZ", "/d", &command, "/f",
+    ])
+}
+
+#[cfg(target_os = "windows")]
+pub fn uninstall() -> io::Result<()> {
+    reg(&["delete", RUN_KEY, "/v", "Inke", "/f"])
+}
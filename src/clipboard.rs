@@ -0,0 +1,70 @@
+// Text of the system clipboard
+
+/// Text of the clipboard, None when it's empty or doesn't hold text
+pub fn read_text() -> Option<String> {
+    let text = read_platform()?.replace("\r\n", "\n");
+    let text = text.trim_end_matches('\n');
+    if text.trim().is_empty() {
+        None
+    } else {
+        Some(text.to_string())
+    }
+}
+
+/// Ask the clipboard tools of Wayland then X11, one of them is usually installed
+#[cfg(all(unix, not(target_os = "macos")))]
+fn read_platform() -> Option<String> {
+    let commands: [&[&str]; 3] = [
+        &["wl-paste", "--no-newline"],
+        &["xclip", "-selection", "clipboard", "-o"],
+        &["xsel", "--clipboard", "--output"],
+    ];
+    commands.iter().find_map(|command| run(command))
+}
+
+#[cfg(target_os = "macos")]
+fn read_platform() -> Option<String> {
+    run(&["pbpaste"])
+}
+
+#[cfg(unix)]
+fn run(command: &[&str]) -> Option<String> {
+    let output = std::process::Command::new(command[0])
+        .args(&command[1..])
+        .output()
+        .ok()?;
+    if output.status.success() {
+        String::from_utf8(output.stdout).ok()
+    } else {
+        None
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn read_platform() -> Option<String> {
+    use winapi::um::winbase::{GlobalLock, GlobalUnlock};
+    use winapi::um::winuser::{CloseClipboard, GetClipboardData, OpenClipboard, CF_UNICODETEXT};
+
+    unsafe {
+        if OpenClipboard(std::ptr::null_mut()) == 0 {
+            return None;
+        }
+        let handle = GetClipboardData(CF_UNICODETEXT);
+        let data = if handle.is_null() {
+            std::ptr::null_mut()
+        } else {
+            GlobalLock(handle) as *const u16
+        };
+        // Null terminated UTF-16
+        let text = if data.is_null() {
+            None
+        } else {
+            let len = (0..).take_while(|&i| *data.add(i) != 0).count();
+            let text = String::from_utf16_lossy(std::slice::from_raw_parts(data, len));
+            GlobalUnlock(handle);
+            Some(text)
+        };
+        CloseClipboard();
+        text
+    }
+}
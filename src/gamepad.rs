@@ -0,0 +1,184 @@
+// Gamepad input, polled on a thread of its own and sent to the event loop about every frame
+
+// Nothing reads the gamepad on Mac yet
+#![cfg_attr(target_os = "macos", allow(dead_code))]
+
+use std::time::Duration;
+
+use glutin::event_loop::EventLoopProxy;
+
+use crate::instance::Command;
+
+// Time between two readings of the gamepad
+const POLL_INTERVAL: Duration = Duration::from_millis(16);
+// Part of the stick travel ignored around its center, where it never quite rests at 0
+const STICK_DEADZONE: f32 = 0.25;
+
+// Buttons, as bits of GamepadState::buttons
+pub const BUTTON_UNDO: u8 = 1;
+pub const BUTTON_NEXT_COLOR: u8 = 1 << 1;
+pub const BUTTON_PREVIOUS_COLOR: u8 = 1 << 2;
+pub const BUTTON_LARGER_SIZE: u8 = 1 << 3;
+pub const BUTTON_SMALLER_SIZE: u8 = 1 << 4;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct GamepadState {
+    /// Left stick, from -1 to 1 with y going down, 0 inside the deadzone
+    pub stick: (f32, f32),
+    /// Right trigger, from 0 to 1
+    pub trigger: f32,
+    pub buttons: u8,
+}
+
+impl GamepadState {
+    /// Moving the cursor needs a state every frame while the stick is pushed, otherwise only
+    /// changes are sent
+    fn needs_sending(&self, last_sent: &GamepadState) -> bool {
+        self != last_sent || self.stick != (0.0, 0.0)
+    }
+}
+
+/// Stick axis from -1 to 1, with the deadzone removed so the movement starts from 0
+fn stick_axis(value: f32) -> f32 {
+    if value.abs() < STICK_DEADZONE {
+        0.0
+    } else {
+        value.signum() * (value.abs() - STICK_DEADZONE) / (1.0 - STICK_DEADZONE)
+    }
+}
+
+/// Read the first joystick device of Linux (/dev/input/js0), on a thread of its own
+#[cfg(all(unix, not(target_os = "macos")))]
+pub fn listen(proxy: EventLoopProxy<Command>) {
+    use std::io::Read;
+    use std::sync::{Arc, Mutex};
+
+    // Event types of the joystick API, the init flag is set on the events describing the
+    // initial state
+    const JS_EVENT_BUTTON: u8 = 0x01;
+    const JS_EVENT_AXIS: u8 = 0x02;
+    const JS_EVENT_INIT: u8 = 0x80;
+
+    let mut device = match std::fs::File::open("/dev/input/js0") {
+        Ok(device) => device,
+        Err(e) => {
+            log::error!("Failed to open the gamepad /dev/input/js0: {}", e);
+            return;
+        }
+    };
+
+    // Reads block until the next event, the state is sent from a second thread
+    let state = Arc::new(Mutex::new(GamepadState::default()));
+    let reader_state = state.clone();
+    std::thread::spawn(move || {
+        // struct js_event { u32 time; i16 value; u8 type; u8 number; }
+        let mut event = [0u8; 8];
+        while device.read_exact(&mut event).is_ok() {
+            let value = f32::from(i16::from_ne_bytes([event[4], event[5]])) / 32767.0;
+            let number = event[7];
+            let mut state = reader_state.lock().unwrap();
+            match event[6] & !JS_EVENT_INIT {
+                // Xbox layout: left stick x and y, then the right trigger from -1 to 1
+                JS_EVENT_AXIS => match number {
+                    0 => state.stick.0 = stick_axis(value),
+                    1 => state.stick.1 = stick_axis(value),
+                    5 => state.trigger = (value + 1.0) / 2.0,
+                    _ => (),
+                },
+                // Xbox layout: B, X, Y, LB, RB
+                JS_EVENT_BUTTON => {
+                    let button = match number {
+                        1 => BUTTON_UNDO,
+                        2 => BUTTON_PREVIOUS_COLOR,
+                        3 => BUTTON_NEXT_COLOR,
+                        4 => BUTTON_SMALLER_SIZE,
+                        5 => BUTTON_LARGER_SIZE,
+                        _ => continue,
+                    };
+                    if value != 0.0 {
+                        state.buttons |= button;
+                    } else {
+                        state.buttons &= !button;
+                    }
+                }
+                _ => (),
+            }
+        }
+    });
+
+    std::thread::spawn(move || {
+        let mut last_sent = GamepadState::default();
+        loop {
+            let current = *state.lock().unwrap();
+            if current.needs_sending(&last_sent) {
+                // The event loop is gone, Inke is closing
+                if proxy.send_event(Command::Gamepad(current)).is_err() {
+                    return;
+                }
+                last_sent = current;
+            }
+            std::thread::sleep(POLL_INTERVAL);
+        }
+    });
+}
+
+/// Poll the first XInput controller, on a thread of its own
+#[cfg(target_os = "windows")]
+pub fn listen(proxy: EventLoopProxy<Command>) {
+    use winapi::shared::winerror::ERROR_SUCCESS;
+    use winapi::um::xinput::{
+        XInputGetState, XINPUT_GAMEPAD_B, XINPUT_GAMEPAD_LEFT_SHOULDER,
+        XINPUT_GAMEPAD_RIGHT_SHOULDER, XINPUT_GAMEPAD_X, XINPUT_GAMEPAD_Y, XINPUT_STATE,
+    };
+
+    std::thread::spawn(move || {
+        let mut last_sent = GamepadState::default();
+        loop {
+            let mut xinput_state: XINPUT_STATE = unsafe { std::mem::zeroed() };
+            // A disconnected controller reads as released
+            let current = if unsafe { XInputGetState(0, &mut xinput_state) } == ERROR_SUCCESS {
+                let pad = xinput_state.Gamepad;
+                let mut buttons = 0;
+                for &(flag, button) in [
+                    (XINPUT_GAMEPAD_B, BUTTON_UNDO),
+                    (XINPUT_GAMEPAD_X, BUTTON_PREVIOUS_COLOR),
+                    (XINPUT_GAMEPAD_Y, BUTTON_NEXT_COLOR),
+                    (XINPUT_GAMEPAD_LEFT_SHOULDER, BUTTON_SMALLER_SIZE),
+                    (XINPUT_GAMEPAD_RIGHT_SHOULDER, BUTTON_LARGER_SIZE),
+                ]
+                .iter()
+                {
+                    if pad.wButtons & flag != 0 {
+                        buttons |= button;
+                    }
+                }
+                GamepadState {
+                    // XInput's y goes up
+                    stick: (
+                        stick_axis(f32::from(pad.sThumbLX) / 32767.0),
+                        stick_axis(-f32::from(pad.sThumbLY) / 32767.0),
+                    ),
+                    trigger: f32::from(pad.bRightTrigger) / 255.0,
+                    buttons,
+                }
+            } else {
+                GamepadState::default()
+            };
+
+            if current.needs_sending(&last_sent) {
+                // The event loop is gone, Inke is closing
+                if proxy.send_event(Command::Gamepad(current)).is_err() {
+                    return;
+                }
+                last_sent = current;
+            }
+            std::thread::sleep(POLL_INTERVAL);
+        }
+    });
+}
+
+/// Game controllers aren't supported on Mac yet
+#[cfg(target_os = "macos")]
+pub fn listen(_proxy: EventLoopProxy<Command>) {
+    log::warn!("Gamepad input isn't supported on Mac yet");
+}
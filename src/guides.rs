@@ -0,0 +1,30 @@
+// Templates of guide shapes loaded from a JSON file, such as the layout of a streaming scene
+
+use std::fs;
+
+use serde::Deserialize;
+
+/// Rectangle of a template, in pixels from the top left of the screens
+#[derive(Deserialize, Debug, Clone)]
+pub struct GuideRect {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    #[serde(default = "default_color")]
+    pub color: [u32; 3],
+    /// Shown inside the top left corner of the rectangle
+    #[serde(default)]
+    pub label: Option<String>,
+}
+
+fn default_color() -> [u32; 3] {
+    [0, 200, 255]
+}
+
+/// Read the rectangles of a template file, a JSON list of
+/// `{"x": 0, "y": 0, "width": 1920, "height": 1080, "color": [0, 200, 255], "label": "Safe area"}`
+pub fn load(path: &str) -> Result<Vec<GuideRect>, String> {
+    let contents = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    serde_json::from_str(&contents).map_err(|e| e.to_string())
+}
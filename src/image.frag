@@ -0,0 +1,15 @@
+#version 150
+uniform sampler2D image;
+in vec2 fUv;
+in float fOpacity;
+out vec4 out_color;
+
+void main() {
+    vec4 color = texture(image, fUv);
+#ifdef PREMULTIPLIED_ALPHA
+    // The textures are premultiplied when uploaded
+    out_color = color * fOpacity;
+#else
+    out_color = vec4(color.rgb, color.a * fOpacity);
+#endif
+}
\ No newline at end of file
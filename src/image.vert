@@ -0,0 +1,12 @@
+#version 150
+in vec2 position;
+in vec2 vUv;
+in float vOpacity;
+out vec2 fUv;
+out float fOpacity;
+
+void main() {
+    fUv = vUv;
+    fOpacity = vOpacity;
+    gl_Position = vec4(position, 0.0, 1.0);
+}
\ No newline at end of file
@@ -0,0 +1,275 @@
+// Images dropped on the overlay, each one uploaded to a texture of its own the first time it
+// is drawn
+
+use std::ffi::CStr;
+use std::mem;
+use std::sync::Arc;
+
+use gl::types::*;
+use inke_core::stroke::Stroke;
+use inke_core::{screen_to_gl, Rect2D};
+
+use crate::text::keep_bindings;
+use crate::{compile_shader, is_gles, is_premultiplied_alpha, link_program};
+
+static VS_SRC: &str = include_str!("image.vert");
+static FS_SRC: &str = include_str!("image.frag");
+// Floats per vertex: x, y, u, v, opacity
+const IMAGE_VERTEX_SIZE: usize = 5;
+
+/// Texture of an image, 0 when its file can't be decoded
+struct ImageTexture {
+    png: Arc<Vec<u8>>,
+    texture: GLuint,
+    is_drawn: bool,
+}
+
+pub struct ImageRenderer {
+    textures: Vec<ImageTexture>,
+    program: GLuint,
+    vs: GLuint,
+    fs: GLuint,
+    vao: GLuint,
+    vbo: GLuint,
+}
+
+/// Width, height and RGBA pixels of a PNG file
+pub fn decode_png(png: &[u8]) -> Result<(u32, u32, Vec<u8>), String> {
+    let mut decoder = png::Decoder::new(png);
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().map_err(|e| e.to_string())?;
+    let mut pixels = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut pixels).map_err(|e| e.to_string())?;
+    pixels.truncate(info.buffer_size());
+
+    let rgba = match info.color_type {
+        png::ColorType::Rgba => pixels,
+        png::ColorType::Rgb => pixels
+            .chunks(3)
+            .flat_map(|p| [p[0], p[1], p[2], 255])
+            .collect(),
+        png::ColorType::GrayscaleAlpha => pixels
+            .chunks(2)
+            .flat_map(|p| [p[0], p[0], p[0], p[1]])
+            .collect(),
+        png::ColorType::Grayscale => pixels.iter().flat_map(|&p| [p, p, p, 255]).collect(),
+        // Palettes are expanded by the transformations
+        png::ColorType::Indexed => return Err(String::from("Unexpected palette")),
+    };
+    Ok((info.width, info.height, rgba))
+}
+
+/// Upload an image to a new texture, None when it can't be decoded or is too large for the GPU
+fn upload(png: &[u8]) -> Option<GLuint> {
+    let (width, height, mut rgba) = match decode_png(png) {
+        Ok(image) => image,
+        Err(e) => {
+            log::error!("Failed to decode an image: {}", e);
+            return None;
+        }
+    };
+    // Linear filtering of premultiplied pixels leaves no dark fringe around transparent parts
+    if is_premultiplied_alpha() {
+        for pixel in rgba.chunks_mut(4) {
+            let alpha = pixel[3] as u32;
+            for channel in &mut pixel[..3] {
+                *channel = (*channel as u32 * alpha / 255) as u8;
+            }
+        }
+    }
+    let mut max_size = 0;
+    unsafe { gl::GetIntegerv(gl::MAX_TEXTURE_SIZE, &mut max_size) };
+    if width > max_size as u32 || height > max_size as u32 {
+        log::error!(
+            "Images of {}x{} pixels are larger than the {} pixels the GPU supports",
+            width,
+            height,
+            max_size
+        );
+        return None;
+    }
+
+    let mut texture = 0;
+    unsafe {
+        gl::GenTextures(1, &mut texture);
+        gl::BindTexture(gl::TEXTURE_2D, texture);
+        gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+        gl::TexImage2D(
+            gl::TEXTURE_2D,
+            0,
+            gl::RGBA as GLint,
+            width as GLsizei,
+            height as GLsizei,
+            0,
+            gl::RGBA,
+            gl::UNSIGNED_BYTE,
+            rgba.as_ptr() as *const GLvoid,
+        );
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as GLint);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as GLint);
+        gl::TexParameteri(
+            gl::TEXTURE_2D,
+            gl::TEXTURE_WRAP_S,
+            gl::CLAMP_TO_EDGE as GLint,
+        );
+        gl::TexParameteri(
+            gl::TEXTURE_2D,
+            gl::TEXTURE_WRAP_T,
+            gl::CLAMP_TO_EDGE as GLint,
+        );
+    }
+    Some(texture)
+}
+
+impl ImageRenderer {
+    /// Create the GL objects drawing the images (the GL context must be current)
+    pub fn new() -> ImageRenderer {
+        let vs = compile_shader(VS_SRC, gl::VERTEX_SHADER);
+        let fs = compile_shader(FS_SRC, gl::FRAGMENT_SHADER);
+        let program = link_program(vs, fs);
+
+        let mut vao = 0;
+        let mut vbo = 0;
+
+        unsafe {
+            keep_bindings(|| {
+                gl::GenVertexArrays(1, &mut vao);
+                gl::BindVertexArray(vao);
+                gl::GenBuffers(1, &mut vbo);
+                gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
+
+                gl::UseProgram(program);
+                // OpenGL ES shaders write gl_FragColor instead
+                if !is_gles() {
+                    gl::BindFragDataLocation(
+                        program,
+                        0,
+                        CStr::from_bytes_with_nul(b"out_color\0").unwrap().as_ptr(),
+                    );
+                }
+
+                // position, uv and opacity attribs
+                let stride = (IMAGE_VERTEX_SIZE * mem::size_of::<f32>()) as GLint;
+                for &(name, size, offset) in [
+                    (&b"position\0"[..], 2, 0),
+                    (&b"vUv\0"[..], 2, 2),
+                    (&b"vOpacity\0"[..], 1, 4),
+                ]
+                .iter()
+                {
+                    let attr = gl::GetAttribLocation(
+                        program,
+                        CStr::from_bytes_with_nul(name).unwrap().as_ptr(),
+                    );
+                    gl::EnableVertexAttribArray(attr as GLuint);
+                    gl::VertexAttribPointer(
+                        attr as GLuint,
+                        size,
+                        gl::FLOAT,
+                        gl::FALSE as GLboolean,
+                        stride,
+                        (offset * mem::size_of::<f32>()) as *const GLvoid,
+                    );
+                }
+            });
+        }
+
+        ImageRenderer {
+            textures: Vec::new(),
+            program,
+            vs,
+            fs,
+            vao,
+            vbo,
+        }
+    }
+
+    /// Draw the image strokes with their opacity
+    pub fn draw<'a>(
+        &mut self,
+        strokes: impl IntoIterator<Item = (&'a Stroke, f32)>,
+        overlay_rect: &Rect2D,
+    ) {
+        unsafe {
+            keep_bindings(|| {
+                gl::UseProgram(self.program);
+                gl::BindVertexArray(self.vao);
+                gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo);
+
+                for (stroke, opacity) in strokes {
+                    let (png, a, b) =
+                        match (&stroke.image, stroke.points.first(), stroke.points.last()) {
+                            (Some(png), Some(&a), Some(&b)) => (png, a, b),
+                            _ => continue,
+                        };
+                    // Copies of a stroke share its image
+                    let index = match self.textures.iter().position(|t| Arc::ptr_eq(&t.png, png)) {
+                        Some(index) => index,
+                        None => {
+                            self.textures.push(ImageTexture {
+                                png: png.clone(),
+                                texture: upload(png).unwrap_or(0),
+                                is_drawn: false,
+                            });
+                            self.textures.len() - 1
+                        }
+                    };
+                    let image = &mut self.textures[index];
+                    image.is_drawn = true;
+                    if image.texture == 0 {
+                        continue;
+                    }
+
+                    // Corners dragged past each other flip the image
+                    let top_left = screen_to_gl(a.x, a.y, overlay_rect);
+                    let bottom_right = screen_to_gl(b.x, b.y, overlay_rect);
+                    let (left, top, right, bottom) =
+                        (top_left.x, top_left.y, bottom_right.x, bottom_right.y);
+                    let vertices = [
+                        left, top, 0.0, 0.0, opacity, //
+                        right, top, 1.0, 0.0, opacity, //
+                        right, bottom, 1.0, 1.0, opacity, //
+                        left, top, 0.0, 0.0, opacity, //
+                        right, bottom, 1.0, 1.0, opacity, //
+                        left, bottom, 0.0, 1.0, opacity,
+                    ];
+                    gl::BindTexture(gl::TEXTURE_2D, image.texture);
+                    gl::BufferData(
+                        gl::ARRAY_BUFFER,
+                        mem::size_of_val(&vertices) as GLsizeiptr,
+                        vertices.as_ptr() as *const GLvoid,
+                        gl::STREAM_DRAW,
+                    );
+                    gl::DrawArrays(gl::TRIANGLES, 0, 6);
+                }
+            });
+        }
+    }
+
+    /// Free the textures of the images not drawn since the last call, once per frame
+    pub fn free_unused(&mut self) {
+        self.textures.retain(|image| {
+            if !image.is_drawn && image.texture != 0 {
+                unsafe { gl::DeleteTextures(1, &image.texture) };
+            }
+            image.is_drawn
+        });
+        for image in self.textures.iter_mut() {
+            image.is_drawn = false;
+        }
+    }
+
+    /// Free the GL objects
+    pub fn delete(&self) {
+        unsafe {
+            gl::DeleteProgram(self.program);
+            gl::DeleteShader(self.fs);
+            gl::DeleteShader(self.vs);
+            gl::DeleteBuffers(1, &self.vbo);
+            gl::DeleteVertexArrays(1, &self.vao);
+            for image in &self.textures {
+                gl::DeleteTextures(1, &image.texture);
+            }
+        }
+    }
+}
@@ -0,0 +1,213 @@
+// Only one instance of Inke runs at a time, launching it again shows the running one
+//
+// Other instances connect to the local port of the running one and first send `inke <token>`,
+// with the token the running instance wrote to TOKEN_FILE in the config directory, then one
+// command per line. Connections without the token are closed, so web pages and other users
+// can't send commands.
+
+use std::collections::hash_map::RandomState;
+use std::fs;
+use std::hash::{BuildHasher, Hasher};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use glutin::event_loop::EventLoopProxy;
+
+use crate::gamepad::GamepadState;
+use crate::Tool;
+
+// Local port the running instance listens on for commands
+const ADDRESS: &str = "127.0.0.1:47437";
+// Token of the running instance, readable by the user only
+const TOKEN_FILE: &str = "instance.token";
+// Longest line accepted from another instance, in bytes
+const MAX_LINE: u64 = 4096;
+// Time another instance has to send each line before it's disconnected
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Commands sent to the event loop by other instances and the macOS menu bar
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Activate,
+    Hide,
+    Clear,
+    ToggleBackground,
+    ToggleStopwatch,
+    ResetStopwatch,
+    /// Index of the counter, from 0, and the amount added to it
+    AddToCounter(usize, i64),
+    ResetCounters,
+    Undo,
+    SetTool(Tool),
+    /// Index of the color in the config
+    SetColor(usize),
+    /// Index of the brush size in the config
+    SetSize(usize),
+    /// Controller number and value of a MIDI knob or slider
+    #[cfg_attr(target_os = "macos", allow(dead_code))]
+    MidiControl(u8, u8),
+    /// Note of a MIDI key or pad pressed
+    #[cfg_attr(target_os = "macos", allow(dead_code))]
+    MidiNote(u8),
+    #[cfg_attr(target_os = "macos", allow(dead_code))]
+    Gamepad(GamepadState),
+    /// The screen below the overlay was captured, the overlay can be drawn again
+    ScreenCaptured,
+    /// A file written in the background, with the message to log
+    Saved(Result<String, String>),
+    /// Save the strokes to a JSON file of the interchange format
+    ExportStrokes,
+    /// Save the strokes with when they were drawn to a JSON timeline
+    ExportTimeline,
+    /// Add the strokes of a JSON file of the interchange format
+    ImportStrokes(String),
+    /// Place a QR code of a link or text at the cursor
+    QrCode(String),
+    /// Message of the other peers of a shared session
+    Session(crate::session::Message),
+    /// Only sent by the macOS menu bar
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+    Quit,
+}
+
+pub enum Instance {
+    /// No other instance is running, the listener receives the commands of the next ones with
+    /// their token (None if the port couldn't be used, in which case instances aren't guarded)
+    First(Option<(TcpListener, String)>),
+    /// Another instance is running and was asked to show itself
+    AlreadyRunning,
+}
+
+/// Become the running instance, or forward an activate command to the one already running
+pub fn acquire() -> Instance {
+    match TcpListener::bind(ADDRESS) {
+        Ok(listener) => match write_token() {
+            Ok(token) => Instance::First(Some((listener, token))),
+            Err(e) => {
+                log::error!("Failed to write {}: {}", TOKEN_FILE, e);
+                Instance::First(None)
+            }
+        },
+        Err(_) => match TcpStream::connect(ADDRESS) {
+            Ok(stream) => {
+                if let Err(e) = send_to(stream, "activate") {
+                    log::error!("Failed to activate the running instance: {}", e);
+                }
+                Instance::AlreadyRunning
+            }
+            // The port is used by something else, run anyway
+            Err(_) => Instance::First(None),
+        },
+    }
+}
+
+/// Write a new token for the other instances to TOKEN_FILE
+fn write_token() -> std::io::Result<String> {
+    // The keys of each RandomState are random, so are the hashes they make
+    let mut token = String::new();
+    for _ in 0..2 {
+        let mut hasher = RandomState::new().build_hasher();
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        hasher.write_u128(time.as_nanos());
+        token.push_str(&format!("{:016x}", hasher.finish()));
+    }
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(TOKEN_FILE)?.write_all(token.as_bytes())?;
+    Ok(token)
+}
+
+/// Forward the commands of other instances to the event loop, each connection on a thread of
+/// its own so one staying connected doesn't hold up the next
+pub fn listen((listener, token): (TcpListener, String), proxy: EventLoopProxy<Command>) {
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let token = token.clone();
+            let proxy = proxy.clone();
+            thread::spawn(move || serve(stream, &token, &proxy));
+        }
+    });
+}
+
+/// Forward the commands of a connection, after checking its token
+fn serve(stream: TcpStream, token: &str, proxy: &EventLoopProxy<Command>) {
+    if stream.set_read_timeout(Some(READ_TIMEOUT)).is_err() {
+        return;
+    }
+    let mut reader = BufReader::new(stream);
+    let mut lines = std::iter::from_fn(|| {
+        let mut line = String::new();
+        match (&mut reader).take(MAX_LINE).read_line(&mut line) {
+            Ok(n) if n > 0 && line.ends_with('\n') => Some(line),
+            _ => None,
+        }
+    });
+
+    let handshake = lines.next().unwrap_or_default();
+    // Browsers can send requests to local ports, such as a form posting commands in its body
+    if handshake.contains(" HTTP/") {
+        log::warn!("Refused an HTTP request on the instance port");
+        return;
+    }
+    if handshake.trim() != format!("inke {}", token) {
+        log::warn!("Refused an instance connection without the token");
+        return;
+    }
+
+    for line in lines {
+        let command = match line.trim() {
+            // Counters are numbered from 1, as their shortcuts
+            command if command.starts_with("counter-") => match parse_counter(command) {
+                Some(command) => command,
+                None => continue,
+            },
+            "activate" => Command::Activate,
+            "stopwatch" => Command::ToggleStopwatch,
+            "stopwatch-reset" => Command::ResetStopwatch,
+            "counters-reset" => Command::ResetCounters,
+            "export" => Command::ExportStrokes,
+            "export-timeline" => Command::ExportTimeline,
+            command if command.starts_with("import ") => {
+                Command::ImportStrokes(command["import ".len()..].trim().to_string())
+            }
+            command if command.starts_with("qr ") => {
+                Command::QrCode(command["qr ".len()..].trim().to_string())
+            }
+            _ => continue,
+        };
+        // The event loop is gone, Inke is closing
+        if proxy.send_event(command).is_err() {
+            return;
+        }
+    }
+}
+
+/// Send a command to the running instance, such as `export`
+pub fn send(command: &str) -> std::io::Result<()> {
+    send_to(TcpStream::connect(ADDRESS)?, command)
+}
+
+/// Send a command on a connection to the running instance, with the token it wrote
+fn send_to(mut stream: TcpStream, command: &str) -> std::io::Result<()> {
+    let token = fs::read_to_string(TOKEN_FILE)?;
+    stream.write_all(format!("inke {}\n{}\n", token.trim(), command).as_bytes())
+}
+
+/// Parse `counter-up <number>` and `counter-down <number>`
+fn parse_counter(command: &str) -> Option<Command> {
+    let mut words = command.split_whitespace();
+    let amount = match words.next()? {
+        "counter-up" => 1,
+        "counter-down" => -1,
+        _ => return None,
+    };
+    let number: usize = words.next()?.parse().ok()?;
+    Some(Command::AddToCounter(number.checked_sub(1)?, amount))
+}
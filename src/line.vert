@@ -0,0 +1,41 @@
+#version 150
+// Each instance is a segment of a line, expanded into the quad of two triangles
+uniform vec2 resolution;
+in vec2 previousPosition;
+in vec4 start; // x, y, pressure, width
+in vec4 end;
+in vec3 vColor;
+in float vHardness;
+in float vOpacity;
+in float vJoined;
+out vec3 fColor;
+out float fEdge;
+out float fHardness;
+out float fOpacity;
+
+// Triangles start-end-start and start-end-end, from the -1 side to the 1 side of the line
+const float ENDS[6] = float[6](0.0, 1.0, 1.0, 0.0, 1.0, 0.0);
+const float EDGES[6] = float[6](-1.0, 1.0, -1.0, -1.0, 1.0, 1.0);
+
+vec2 normal(vec2 from, vec2 to) {
+    float len = distance(from, to);
+    return len > 0.0 ? vec2(from.y - to.y, to.x - from.x) / len : vec2(0.0);
+}
+
+void main() {
+    fColor = vColor;
+    fEdge = EDGES[gl_VertexID];
+    fHardness = vHardness;
+    fOpacity = vOpacity;
+
+    // The start of the first segment takes its own direction, the others the previous one
+    vec2 endNormal = normal(start.xy, end.xy);
+    vec2 startNormal = previousPosition == start.xy ? endNormal : normal(previousPosition, start.xy);
+    bool isEnd = ENDS[gl_VertexID] > 0.5;
+    vec4 point = isEnd ? end : start;
+    vec2 position = point.xy + (isEnd ? endNormal : startNormal) * point.z * point.w * fEdge;
+
+    // The segments leading to the start of the next line collapse into nothing
+    position *= vJoined;
+    gl_Position = vec4(position / resolution * vec2(2.0, -2.0) + vec2(-1.0, 1.0), 0.0, 1.0);
+}
@@ -0,0 +1,230 @@
+// Freehand lines expanded into quads on the GPU: each point of their centerline is uploaded
+// once, and each segment is an instance of the 6 vertices the vertex shader places around it
+
+use std::ffi::CStr;
+use std::mem;
+use std::ops::Range;
+
+use gl::types::*;
+use inke_core::stroke::{Shape, Stroke};
+use inke_core::{Rect2D, LINE_POINT_SIZE, VERTEX_SIZE};
+
+use crate::text::keep_bindings;
+use crate::{draw_vertices, is_gles, try_compile_shader, try_link_program};
+
+static VS_SRC: &str = include_str!("line.vert");
+
+pub struct LineRenderer {
+    program: GLuint,
+    vs: GLuint,
+    vao: GLuint,
+    vbo: GLuint,
+    time_uniform: GLint,
+    resolution_uniform: GLint,
+}
+
+/// Strokes following each other in the same buffer, drawn in a single call
+struct Batch {
+    is_lines: bool,
+    range: Range<usize>,
+}
+
+/// The strokes to draw, in the buffers of the stroke program and of the line renderer
+///
+/// The batches keep the order of the strokes across both buffers, so lines and shapes still
+/// cover the ones drawn before them.
+#[derive(Default)]
+pub struct StrokeBuffers {
+    pub vertices: Vec<f32>,
+    pub line_points: Vec<f32>,
+    batches: Vec<Batch>,
+}
+
+impl StrokeBuffers {
+    pub fn clear(&mut self) {
+        self.vertices.clear();
+        self.line_points.clear();
+        self.batches.clear();
+    }
+
+    /// Add a stroke, its opacity multiplied by `opacity`. Freehand lines go to the line renderer
+    /// when `freehand_on_gpu`, they must have been tessellated the same way
+    pub fn push(&mut self, stroke: &Stroke, opacity: f32, freehand_on_gpu: bool) {
+        let is_lines = stroke.shape == Shape::Freehand && freehand_on_gpu;
+        let (buffer, size) = if is_lines {
+            (&mut self.line_points, LINE_POINT_SIZE)
+        } else {
+            (&mut self.vertices, VERTEX_SIZE)
+        };
+        let start = buffer.len();
+        if is_lines {
+            stroke.push_line_points(buffer);
+        } else {
+            buffer.extend(&stroke.vertices);
+        }
+        if opacity < 1.0 {
+            for vertex in buffer[start..].chunks_mut(size) {
+                vertex[8] *= opacity;
+            }
+        }
+
+        let end = buffer.len();
+        if start == end {
+            return;
+        }
+        match self.batches.last_mut() {
+            Some(batch) if batch.is_lines == is_lines => batch.range.end = end,
+            _ => self.batches.push(Batch {
+                is_lines,
+                range: start..end,
+            }),
+        }
+    }
+
+    /// Draw the strokes in their order
+    pub fn draw(&self, lines: Option<&LineRenderer>, overlay_rect: &Rect2D, time: f32) {
+        for batch in &self.batches {
+            if !batch.is_lines {
+                unsafe { draw_vertices(&self.vertices[batch.range.clone()], gl::TRIANGLES) };
+            } else if let Some(lines) = lines {
+                lines.draw(&self.line_points[batch.range.clone()], overlay_rect, time);
+            }
+        }
+    }
+}
+
+impl LineRenderer {
+    /// Create the GL objects drawing the lines with `fs`, the fragment shader of the strokes.
+    /// None when the context can't draw instances (the GL context must be current)
+    pub fn new(fs: GLuint) -> Option<LineRenderer> {
+        // OpenGL ES 2 only has instances as an extension, and GLSL ES 1.00 has no gl_VertexID
+        if is_gles()
+            || !gl::DrawArraysInstanced::is_loaded()
+            || !gl::VertexAttribDivisor::is_loaded()
+        {
+            log::debug!("Instanced drawing is unsupported, lines are tessellated on the CPU");
+            return None;
+        }
+        let vs = match try_compile_shader(VS_SRC, gl::VERTEX_SHADER) {
+            Ok(vs) => vs,
+            Err(e) => {
+                log::warn!("Failed to compile the line shader: {}", e);
+                return None;
+            }
+        };
+        let program = match try_link_program(vs, fs) {
+            Ok(program) => program,
+            Err(e) => {
+                log::warn!("Failed to link the line shader: {}", e);
+                unsafe { gl::DeleteShader(vs) };
+                return None;
+            }
+        };
+
+        let mut vao = 0;
+        let mut vbo = 0;
+        let uniform = |name: &[u8]| unsafe {
+            gl::GetUniformLocation(program, CStr::from_bytes_with_nul(name).unwrap().as_ptr())
+        };
+        let time_uniform = uniform(b"time\0");
+        let resolution_uniform = uniform(b"resolution\0");
+
+        unsafe {
+            keep_bindings(|| {
+                gl::GenVertexArrays(1, &mut vao);
+                gl::BindVertexArray(vao);
+                gl::GenBuffers(1, &mut vbo);
+                gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
+
+                gl::UseProgram(program);
+                gl::BindFragDataLocation(
+                    program,
+                    0,
+                    CStr::from_bytes_with_nul(b"out_color\0").unwrap().as_ptr(),
+                );
+
+                // Segment i reads points i, i + 1 and i + 2, one point apart in the same buffer
+                let stride = (LINE_POINT_SIZE * mem::size_of::<f32>()) as GLint;
+                for &(name, size, offset) in [
+                    (&b"previousPosition\0"[..], 2, 0),
+                    (&b"start\0"[..], 4, LINE_POINT_SIZE),
+                    (&b"end\0"[..], 4, 2 * LINE_POINT_SIZE),
+                    (&b"vColor\0"[..], 3, 2 * LINE_POINT_SIZE + 4),
+                    (&b"vHardness\0"[..], 1, 2 * LINE_POINT_SIZE + 7),
+                    (&b"vOpacity\0"[..], 1, 2 * LINE_POINT_SIZE + 8),
+                    (&b"vJoined\0"[..], 1, 2 * LINE_POINT_SIZE + 9),
+                ]
+                .iter()
+                {
+                    let attr = gl::GetAttribLocation(
+                        program,
+                        CStr::from_bytes_with_nul(name).unwrap().as_ptr(),
+                    );
+                    // Custom fragment shaders may leave some of them unused
+                    if attr < 0 {
+                        continue;
+                    }
+                    gl::EnableVertexAttribArray(attr as GLuint);
+                    gl::VertexAttribPointer(
+                        attr as GLuint,
+                        size,
+                        gl::FLOAT,
+                        gl::FALSE as GLboolean,
+                        stride,
+                        (offset * mem::size_of::<f32>()) as *const GLvoid,
+                    );
+                    gl::VertexAttribDivisor(attr as GLuint, 1);
+                }
+            });
+        }
+
+        Some(LineRenderer {
+            program,
+            vs,
+            vao,
+            vbo,
+            time_uniform,
+            resolution_uniform,
+        })
+    }
+
+    /// Draw line points, in the layout of LINE_POINT_SIZE
+    pub fn draw(&self, line_points: &[f32], overlay_rect: &Rect2D, time: f32) {
+        let n_segments = (line_points.len() / LINE_POINT_SIZE).saturating_sub(2);
+        if n_segments == 0 {
+            return;
+        }
+        unsafe {
+            keep_bindings(|| {
+                gl::UseProgram(self.program);
+                gl::BindVertexArray(self.vao);
+                gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo);
+                if self.time_uniform != -1 {
+                    gl::Uniform1f(self.time_uniform, time);
+                }
+                gl::Uniform2f(
+                    self.resolution_uniform,
+                    overlay_rect.width,
+                    overlay_rect.height,
+                );
+                gl::BufferData(
+                    gl::ARRAY_BUFFER,
+                    mem::size_of_val(line_points) as GLsizeiptr,
+                    line_points.as_ptr() as *const GLvoid,
+                    gl::STATIC_DRAW,
+                );
+                gl::DrawArraysInstanced(gl::TRIANGLES, 0, 6, n_segments as GLsizei);
+            });
+        }
+    }
+
+    /// Free the GL objects, the fragment shader being the one of the strokes
+    pub fn delete(&self) {
+        unsafe {
+            gl::DeleteProgram(self.program);
+            gl::DeleteShader(self.vs);
+            gl::DeleteBuffers(1, &self.vbo);
+            gl::DeleteVertexArrays(1, &self.vao);
+        }
+    }
+}
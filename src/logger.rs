@@ -0,0 +1,97 @@
+// Backend of the log crate: messages for the user and bug reports, written to stderr and
+// optionally to a log file
+//
+// Debug messages are only written with --verbose.
+
+use std::fs::File;
+use std::io::Write;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+struct Logger {
+    // Messages come from the event loop and from the threads of the inputs and servers
+    file: Mutex<Option<File>>,
+}
+
+static LOGGER: Logger = Logger {
+    file: Mutex::new(None),
+};
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        match record.level() {
+            Level::Info => eprintln!("{}", record.args()),
+            level => eprintln!("{}: {}", level, record.args()),
+        }
+        // A thread panicked while logging, the file itself is still fine
+        let mut file = self.file.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(file) = &mut *file {
+            let _ = writeln!(file, "{} {} {}", timestamp(), record.level(), record.args());
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(file) = &mut *self.file.lock().unwrap_or_else(|e| e.into_inner()) {
+            let _ = file.flush();
+        }
+    }
+}
+
+/// Send the messages of the log macros to stderr, and log panics as errors so they end up in
+/// the log file too
+pub fn init() {
+    if log::set_logger(&LOGGER).is_ok() {
+        log::set_max_level(LevelFilter::Info);
+    }
+
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        if let Ok(mut file) = LOGGER.file.lock() {
+            if let Some(file) = &mut *file {
+                let _ = writeln!(file, "{} ERROR {}", timestamp(), info);
+            }
+        }
+        default_hook(info);
+    }));
+}
+
+/// Write the debug messages too
+pub fn set_verbose(is_verbose: bool) {
+    log::set_max_level(if is_verbose {
+        LevelFilter::Debug
+    } else {
+        LevelFilter::Info
+    });
+}
+
+/// Also write the messages to a file, replacing the log of the last launch
+pub fn open_file(path: &str) {
+    match File::create(path) {
+        Ok(file) => *LOGGER.file.lock().unwrap() = Some(file),
+        Err(e) => log::error!("Failed to create the log file {}: {}", path, e),
+    }
+}
+
+/// Time of day in UTC, such as 14:03:27.512
+fn timestamp() -> String {
+    let since_epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let seconds = since_epoch.as_secs() % (24 * 60 * 60);
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60,
+        since_epoch.subsec_millis()
+    )
+}
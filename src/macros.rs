@@ -0,0 +1,42 @@
+// Macros of the config, steps drawing strokes or changing the brush played with a single key
+
+use glutin::event::VirtualKeyCode;
+use serde::{Deserialize, Serialize};
+
+use crate::Tool;
+
+/// Steps played in order by a key, such as a logo stamped at the cursor or a grid of boxes
+///
+/// In JSON: `{"key": "F5", "at_cursor": true, "steps": [{"color": 2}, {"box": [[0, 0], [80, 40]]},
+/// {"text": {"at": [8, 4], "text": "Logo"}}]}`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Macro {
+    pub key: VirtualKeyCode,
+    /// Positions are from the cursor instead of the top left of the screens
+    #[serde(default)]
+    pub at_cursor: bool,
+    pub steps: Vec<Step>,
+}
+
+/// Positions are in pixels, and strokes use the brush color and size at that step
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum Step {
+    /// Freehand line through the points
+    Line(Vec<[f32; 2]>),
+    Arrow([f32; 2], [f32; 2]),
+    /// Highlight box between two corners
+    Box([f32; 2], [f32; 2]),
+    /// Text annotation from its top left corner
+    Text {
+        at: [f32; 2],
+        text: String,
+    },
+    /// Index of a brush color of the config
+    Color(usize),
+    /// Brush size in pixels
+    Size(f32),
+    Tool(Tool),
+    Clear,
+    Undo,
+}
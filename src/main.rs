@@ -3,53 +3,307 @@
 extern crate gl;
 extern crate glutin;
 
-use std::f32::consts::{FRAC_PI_2, PI};
+mod announce;
+mod autostart;
+mod clipboard;
+mod gamepad;
+mod guides;
+mod images;
+mod instance;
+mod lines;
+mod logger;
+mod macros;
+mod midi;
+mod notation;
+mod passthrough;
+mod patterns;
+mod screenshot;
+mod session;
+#[cfg(target_os = "macos")]
+mod status_item;
+mod text;
+mod tools;
+mod viewer;
+mod watermark;
+mod websocket;
+mod workspace;
+
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::f32::consts::PI;
 use std::ffi::CStr;
 use std::ffi::CString;
 use std::io::Write;
-use std::time::SystemTime;
-use std::{fs, mem, ptr, str};
+use std::path::Path;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use std::{env, fs, mem, ptr, str};
 
 use serde::{Deserialize, Serialize};
 
 use gl::types::*;
 use glutin::dpi::{PhysicalPosition, PhysicalSize};
 use glutin::event::{
-    ElementState, Event, MouseButton, MouseScrollDelta, TouchPhase, VirtualKeyCode, WindowEvent,
+    DeviceEvent, ElementState, Event, KeyboardInput, ModifiersState, MouseButton, MouseScrollDelta,
+    TouchPhase, VirtualKeyCode, WindowEvent,
 };
-use glutin::event_loop::{ControlFlow, EventLoop};
+use glutin::event_loop::{ControlFlow, EventLoop, EventLoopProxy, EventLoopWindowTarget};
 use glutin::monitor::MonitorHandle;
-use glutin::window::Window;
+use glutin::window::{Window, WindowBuilder};
 use glutin::ContextWrapper;
+use inke_core::geometry::{
+    close_polyline, outline_stroke, push_vertex, straighten_polyline, SmoothingMode,
+};
+use inke_core::stroke::{ArrowHead, Shape, Stroke, StrokePoint};
+use inke_core::{interchange, qr, timeline};
+use inke_core::{screen_size_to_gl, screen_to_gl, Point, Rect2D, LINE_POINT_SIZE, VERTEX_SIZE};
 
 // Shader sources
-static VS_SRC: &'static str = include_str!("shader.vert");
-static FS_SRC: &'static str = include_str!("shader.frag");
+static VS_SRC: &str = include_str!("shader.vert");
+static FS_SRC: &str = include_str!("shader.frag");
+// Whether the shaders output colors multiplied by their alpha, from the config
+static PREMULTIPLIED_ALPHA: AtomicBool = AtomicBool::new(true);
 
 const N_CURSOR_RETICLE_POINTS: usize = 32;
+const N_RECENT_COLORS: usize = 5;
+const SELECTION_COLOR: [f32; 3] = [0.3, 0.6, 1.0];
+// Border tint shown while the clicks go to the windows below
+const INTERACT_MODE_COLOR: [f32; 3] = [1.0, 0.6, 0.0];
+const INTERACT_MODE_BORDER_WIDTH: usize = 4;
+const CROSSHAIR_COLOR: [f32; 3] = [1.0, 0.2, 0.6];
+const CROSSHAIR_SHADOW_COLOR: [f32; 3] = [0.0, 0.0, 0.0];
+// Size of the coordinates text, and its distance from the cursor, in pixels
+const CROSSHAIR_TEXT_SIZE: f32 = 14.0;
+const CROSSHAIR_TEXT_OFFSET: f32 = 16.0;
+// Size of the text showing the tool settings, and its distance from the cursor, in pixels
+const HUD_TEXT_SIZE: f32 = 14.0;
+const HUD_TEXT_OFFSET: f32 = 16.0;
+// Lines of the diagnostics HUD, the session report is shown below them
+const N_STATS_HUD_LINES: usize = 5;
+// Distance (in pixels) the cursor moves before an axis-locked line picks its axis
+const AXIS_LOCK_THRESHOLD: f32 = 4.0;
+const GRID_COLOR: [f32; 3] = [0.5, 0.5, 0.5];
+const GRID_OPACITY: f32 = 0.4;
+// Size of the labels of the guide template, in pixels
+const GUIDE_LABEL_SIZE: f32 = 16.0;
+// Distance (in pixels) from the end of a line under which new lines snap to it
+const ENDPOINT_SNAP_DISTANCE: f32 = 10.0;
+// Half the size of the square shown around the end a line would snap to
+const ENDPOINT_INDICATOR_SIZE: f32 = 5.0;
+// Smallest text size, in pixels
+const MIN_TEXT_SIZE: f32 = 4.0;
+// Longest delay between the clicks of a double-click
+const DOUBLE_CLICK_MS: u64 = 400;
+// Distance (in pixels) the cursor can move between the press and release of an alt-click
+const COLOR_PICK_DISTANCE: f32 = 4.0;
+// The drawing fades out during the last seconds before being cleared for inactivity
+const AUTO_CLEAR_FADE: Duration = Duration::from_secs(3);
+// Lines drawn with the ephemeral modifier fade out during this time once their delay is over
+const EPHEMERAL_FADE: Duration = Duration::from_millis(500);
+// Size of the work and break timer text, and its distance from the corner of the overlay, in pixels
+const TIMER_TEXT_SIZE: f32 = 24.0;
+const TIMER_TEXT_MARGIN: f32 = 16.0;
+// Distance between the labels stacked in the top right corner, in pixels
+const CORNER_LABEL_SPACING: f32 = 4.0;
+// The background flashes this long when the timer changes phase
+const TIMER_FLASH: Duration = Duration::from_millis(600);
+// Part of the trigger travel pressed before a gamepad starts drawing
+const GAMEPAD_TRIGGER_THRESHOLD: f32 = 0.1;
+// Distance (in pixels) the cursor can drift while dwelling, and the size of the square
+// filling up around it until the dwell clicks
+const DWELL_RADIUS: f32 = 8.0;
+const DWELL_INDICATOR_SIZE: f32 = 12.0;
+// Changes are announced once they settle, so scrolling through sizes is announced once
+const ANNOUNCE_DELAY: Duration = Duration::from_millis(300);
+// Holding an arrow key moves the cursor after this delay, at a speed in pixels per second
+// growing with the time held
+const KEYBOARD_CURSOR_DELAY: Duration = Duration::from_millis(250);
+const KEYBOARD_CURSOR_SPEED: f32 = 60.0;
+const KEYBOARD_CURSOR_ACCELERATION: f32 = 600.0;
+const KEYBOARD_CURSOR_MAX_SPEED: f32 = 1500.0;
+// Longest segment predicted ahead of the cursor, in pixels
+const PREDICTION_MAX_DISTANCE: f32 = 24.0;
+// Time between two sends of the line being drawn to the other peers of a shared session
+const SESSION_SHARE_INTERVAL: Duration = Duration::from_millis(100);
+// Time between the frames of animations
+const FRAME_INTERVAL: Duration = Duration::from_millis(16);
+// Rings expanding from the cursor to show where it is: how long each one expands, the time
+// between them, their number, and their largest radius and width in pixels
+const BEACON_RING_DURATION: Duration = Duration::from_millis(700);
+const BEACON_RING_INTERVAL: Duration = Duration::from_millis(200);
+const N_BEACON_RINGS: u32 = 3;
+const BEACON_RADIUS: f32 = 220.0;
+const BEACON_RING_WIDTH: f32 = 10.0;
+const N_BEACON_RING_SEGMENTS: usize = 64;
+// Time given to the compositor to show the screen below the cleared overlay before capturing it
+const SCREENSHOT_DELAY: Duration = Duration::from_millis(100);
+// Modules of light margin around QR codes, and pixels per module, for them to scan reliably
+const QR_QUIET_ZONE: usize = 4;
+const QR_MODULE_PIXELS: usize = 8;
+// Keys bound to the brush colors of the config, in order. Shift is held for the second row
+const COLOR_KEYS: [VirtualKeyCode; 8] = [
+    VirtualKeyCode::Q,
+    VirtualKeyCode::W,
+    VirtualKeyCode::E,
+    VirtualKeyCode::R,
+    VirtualKeyCode::T,
+    VirtualKeyCode::Y,
+    VirtualKeyCode::U,
+    VirtualKeyCode::I,
+];
+// Keys bound to the brush sizes of the config, in order
+const SIZE_KEYS: [VirtualKeyCode; 9] = [
+    VirtualKeyCode::Key1,
+    VirtualKeyCode::Key2,
+    VirtualKeyCode::Key3,
+    VirtualKeyCode::Key4,
+    VirtualKeyCode::Key5,
+    VirtualKeyCode::Key6,
+    VirtualKeyCode::Key7,
+    VirtualKeyCode::Key8,
+    VirtualKeyCode::Key9,
+];
+// Top row keys swapped by the left-handed layout, bringing the colors to p, o, i, u, ...
+const MIRRORED_KEYS: [(VirtualKeyCode, VirtualKeyCode); 5] = [
+    (VirtualKeyCode::Q, VirtualKeyCode::P),
+    (VirtualKeyCode::W, VirtualKeyCode::O),
+    (VirtualKeyCode::E, VirtualKeyCode::I),
+    (VirtualKeyCode::R, VirtualKeyCode::U),
+    (VirtualKeyCode::T, VirtualKeyCode::Y),
+];
+// Numpad keys bound to the brush sizes by the left-handed layout, in order
+const NUMPAD_SIZE_KEYS: [VirtualKeyCode; 9] = [
+    VirtualKeyCode::Numpad1,
+    VirtualKeyCode::Numpad2,
+    VirtualKeyCode::Numpad3,
+    VirtualKeyCode::Numpad4,
+    VirtualKeyCode::Numpad5,
+    VirtualKeyCode::Numpad6,
+    VirtualKeyCode::Numpad7,
+    VirtualKeyCode::Numpad8,
+    VirtualKeyCode::Numpad9,
+];
+// Consecutive scroll transforms closer than this are undone together
+const TRANSFORM_UNDO_DELAY: Duration = Duration::from_millis(500);
+// Rotation applied by one step of the mouse wheel, in degrees
+const ROTATION_STEP: f32 = 5.0;
+// Holding undo waits this long before repeating, then repeats faster and faster
+const UNDO_REPEAT_DELAY: Duration = Duration::from_millis(400);
+const UNDO_REPEAT_MIN_INTERVAL: Duration = Duration::from_millis(30);
 
 #[derive(Serialize, Deserialize, Debug)]
+#[serde(default)]
 struct Config {
     config_version: u8,
+    smoothing_mode: SmoothingMode,
     smoothing_range: usize,
     smoothing_intensity: usize,
+    pressure_smoothing: f32,
+    stroke_taper_mm: f32,
+    straighten_tolerance_mm: f32,
+    close_distance_mm: f32,
+    stroke_grouping_ms: u64,
     default_brush_size: f32,
+    brush_hardness: f32,
+    default_eraser_size: f32,
     default_brush_color_index: i32,
-    brush_colors: [[u32; 3]; 8],
-    brush_sizes: [f32; 5],
+    default_secondary_color_index: i32,
+    brush_colors: Vec<[u32; 3]>,
+    brush_sizes: Vec<f32>,
     background_color: [u32; 3],
     background_color_opacity: f32,
+    background_image_path: Option<String>,
+    background_image_fit: BackgroundFit,
+    premultiplied_alpha: bool,
+    scale_stroke_width: bool,
+    duplicate_offset: [f32; 2],
+    arrow_head: ArrowHead,
+    arrow_both_ends: bool,
+    start_hidden: bool,
+    per_workspace_boards: bool,
+    tiling_wm_compat: bool,
+    macos_all_spaces: bool,
+    hide_from_taskbar: bool,
+    pass_through_key: Option<VirtualKeyCode>,
+    mode_toggle_key: Option<VirtualKeyCode>,
+    push_to_draw_key: Option<VirtualKeyCode>,
+    push_to_draw_ink: PushToDrawInk,
+    redaction_style: RedactionStyle,
+    redaction_block_size: f32,
+    crosshair_coordinates: bool,
+    highlight_box_fill_opacity: f32,
+    axis_lock_modifier: Option<ModifierKey>,
+    ephemeral_modifier: Option<ModifierKey>,
+    ephemeral_stroke_s: f32,
+    grid_size: f32,
+    grid_patterns: Vec<patterns::GridPattern>,
+    snap_to_grid: bool,
+    snap_to_endpoints: bool,
+    font_path: Option<String>,
+    default_text_size: f32,
+    text_notation: bool,
+    auto_clear_delay_s: u64,
+    idle_hide_delay_s: u64,
+    timer_work_min: u64,
+    timer_break_min: u64,
+    counters: Vec<String>,
+    guide_template: Option<String>,
+    websocket_port: Option<u16>,
+    websocket_token: Option<String>,
+    websocket_network_wide: bool,
+    midi_input: bool,
+    midi_size_control: Option<u8>,
+    midi_hardness_control: Option<u8>,
+    midi_color_notes: Vec<u8>,
+    gamepad_input: bool,
+    gamepad_cursor_speed: f32,
+    dwell_time_ms: u64,
+    switch_key: Option<VirtualKeyCode>,
+    stroke_outlines: bool,
+    announce_command: Vec<String>,
+    left_handed_keys: bool,
+    input_prediction: bool,
+    log_file: bool,
+    macros: Vec<macros::Macro>,
+    multisampling: u16,
+    vertex_shader_path: Option<String>,
+    fragment_shader_path: Option<String>,
+    brush_presets: Vec<BrushPreset>,
+    session_port: Option<u16>,
+    session_join: Option<String>,
+    session_token: Option<String>,
+    session_mirror: bool,
+    viewer_port: Option<u16>,
+    viewer_token: Option<String>,
+    watermark_text: Option<String>,
+    watermark_image_path: Option<String>,
+    watermark_size: f32,
+    watermark_corner: watermark::Corner,
+    watermark_opacity: f32,
+    watermark_live: bool,
+    cursor_image_path: Option<String>,
+    cursor_hotspot: [f32; 2],
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
             config_version: 1,
+            smoothing_mode: SmoothingMode::MovingAverage,
             smoothing_range: 1,
             smoothing_intensity: 1,
+            pressure_smoothing: 0.5,
+            stroke_taper_mm: 0.0,
+            straighten_tolerance_mm: 0.0,
+            close_distance_mm: 0.0,
+            stroke_grouping_ms: 200,
             default_brush_size: 3.0,
+            brush_hardness: 1.0,
+            default_eraser_size: 10.0,
             default_brush_color_index: 0,
-            brush_colors: [
+            default_secondary_color_index: 1,
+            brush_colors: vec![
                 [255, 255, 255], // white
                 [10, 10, 10],    // black
                 [255, 150, 0],   // orange
@@ -59,17 +313,143 @@ impl Default for Config {
                 [25, 75, 255],   // blue
                 [255, 255, 0],   // yellow
             ],
-            brush_sizes: [1.0, 3.0, 5.0, 10.0, 30.0],
+            brush_sizes: vec![1.0, 3.0, 5.0, 10.0, 30.0],
             background_color: [0, 0, 0],
             background_color_opacity: 0.8,
+            background_image_path: None,
+            background_image_fit: BackgroundFit::Contain,
+            premultiplied_alpha: true,
+            scale_stroke_width: true,
+            duplicate_offset: [20.0, 20.0],
+            arrow_head: ArrowHead::Triangle,
+            arrow_both_ends: false,
+            start_hidden: false,
+            per_workspace_boards: true,
+            tiling_wm_compat: false,
+            macos_all_spaces: false,
+            hide_from_taskbar: false,
+            pass_through_key: Some(VirtualKeyCode::Grave),
+            mode_toggle_key: Some(VirtualKeyCode::F9),
+            push_to_draw_key: None,
+            push_to_draw_ink: PushToDrawInk::Keep,
+            redaction_style: RedactionStyle::Pixelate,
+            redaction_block_size: 12.0,
+            crosshair_coordinates: true,
+            highlight_box_fill_opacity: 0.15,
+            axis_lock_modifier: None,
+            ephemeral_modifier: None,
+            ephemeral_stroke_s: 2.0,
+            grid_size: 32.0,
+            grid_patterns: vec![patterns::GridPattern::Lines],
+            snap_to_grid: true,
+            snap_to_endpoints: true,
+            font_path: None,
+            default_text_size: 32.0,
+            text_notation: true,
+            auto_clear_delay_s: 0,
+            idle_hide_delay_s: 0,
+            timer_work_min: 25,
+            timer_break_min: 5,
+            counters: Vec::new(),
+            guide_template: None,
+            websocket_port: None,
+            websocket_token: None,
+            websocket_network_wide: false,
+            midi_input: false,
+            midi_size_control: Some(1),
+            midi_hardness_control: Some(2),
+            // The pads of most controllers start at the C2 note
+            midi_color_notes: (36..52).collect(),
+            gamepad_input: false,
+            gamepad_cursor_speed: 12.0,
+            dwell_time_ms: 0,
+            switch_key: None,
+            stroke_outlines: false,
+            announce_command: Vec::new(),
+            left_handed_keys: false,
+            input_prediction: false,
+            log_file: false,
+            macros: Vec::new(),
+            multisampling: 8,
+            vertex_shader_path: None,
+            fragment_shader_path: None,
+            brush_presets: Vec::new(),
+            session_port: None,
+            session_join: None,
+            session_token: None,
+            session_mirror: false,
+            viewer_port: None,
+            viewer_token: None,
+            watermark_text: None,
+            watermark_image_path: None,
+            watermark_size: 24.0,
+            watermark_corner: watermark::Corner::BottomRight,
+            watermark_opacity: 0.7,
+            watermark_live: false,
+            cursor_image_path: None,
+            cursor_hotspot: [0.0, 0.0],
+        }
+    }
+}
+
+/// Runtime settings saved on exit and restored at the next launch
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(default)]
+struct State {
+    color_index: Option<usize>,
+    brush_width: Option<f32>,
+    is_background_visible: bool,
+    is_grid_visible: bool,
+    grid_pattern_index: usize,
+    tool: Tool,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self {
+            color_index: None,
+            brush_width: None,
+            is_background_visible: false,
+            is_grid_visible: false,
+            grid_pattern_index: 0,
+            tool: Tool::Brush,
         }
     }
 }
 
+/// Drawing put aside while the user is on another virtual desktop
+#[derive(Default)]
+struct Board {
+    strokes: Vec<Rc<Stroke>>,
+    undo_steps: Vec<Vec<Rc<Stroke>>>,
+}
+
 #[derive(Default)]
 struct Input {
+    has_focus: bool,
     modifiers: Modifiers,
     cursor: Cursor,
+    pressed_keys: Vec<VirtualKeyCode>, // Keys held down, to tell key presses from OS key repeats
+    undo_repeat: Option<KeyRepeat>,    // Undo repeating while ctrl-z is held
+    last_click: Option<(Instant, f32, f32)>, // Time and position of the last click, for double-clicks
+    gamepad: gamepad::GamepadState, // Last state of the gamepad, to tell presses from holds
+    dwell: Option<Dwell>,           // Where the cursor has been resting, for dwell-clicking
+    arrow_move: Option<(Instant, Instant)>, // Start and last step of the cursor moved with the arrow keys
+}
+
+/// Cursor resting in place, pressing or releasing once it has been there long enough
+#[derive(Debug)]
+struct Dwell {
+    x: f32,
+    y: f32,
+    since: Instant,
+    is_done: bool, // The cursor has to move away before dwelling again
+}
+
+#[derive(Debug)]
+struct KeyRepeat {
+    next_time: Instant,
+    count: u32,
 }
 
 #[derive(Default)]
@@ -80,6 +460,28 @@ struct Modifiers {
     logo: bool,
 }
 
+impl From<ModifiersState> for Modifiers {
+    fn from(state: ModifiersState) -> Self {
+        Modifiers {
+            shift: state.shift(),
+            ctrl: state.ctrl(),
+            alt: state.alt(),
+            logo: state.logo(),
+        }
+    }
+}
+
+impl Modifiers {
+    fn is_held(&self, key: ModifierKey) -> bool {
+        match key {
+            ModifierKey::Shift => self.shift,
+            ModifierKey::Ctrl => self.ctrl,
+            ModifierKey::Alt => self.alt,
+            ModifierKey::Logo => self.logo,
+        }
+    }
+}
+
 #[derive(Default, Debug)]
 struct Cursor {
     x: f32,
@@ -88,42 +490,172 @@ struct Cursor {
     last_y: f32,
     pressed: bool,
     released_time: Option<SystemTime>,
+    samples: Vec<(f32, f32, SystemTime)>, // Positions reported since the last frame while pressed, and when
 }
 
-#[derive(Default, Debug)]
-struct Rect2D {
+/// What happens to the ink when the push-to-draw key is released
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum PushToDrawInk {
+    Keep,
+    Fade,
+}
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum RedactionStyle {
+    Pixelate,
+    Solid,
+}
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum ModifierKey {
+    Shift,
+    Ctrl,
+    Alt,
+    /// Cmd on Mac, the Windows key elsewhere
+    Logo,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Axis {
+    Horizontal,
+    Vertical,
+}
+
+/// Constraint of the line being drawn while the axis lock modifier is held
+struct AxisLock {
     x: f32,
     y: f32,
-    width: f32,
-    height: f32,
+    axis: Option<Axis>, // Picked once the cursor moved far enough from x, y
 }
 
-#[derive(Default, Debug)]
-struct Size2D {
+/// How the background image fills each monitor
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum BackgroundFit {
+    /// Whole image, as large as the monitor allows, on the background color
+    Contain,
+    /// Whole monitor, the sides of the image past it cut
+    Cover,
+    /// Image stretched to the monitor
+    Stretch,
+}
+
+/// PNG image drawn on the background, fitted to each monitor
+struct BackgroundImage {
+    placements: Vec<(Rect2D, Stroke)>, // Monitor in overlay pixels, and the image stroke in it
+}
+
+/// PNG image drawn at the pointer instead of the reticle
+struct CursorImage {
+    png: Arc<Vec<u8>>,
     width: f32,
     height: f32,
+    hotspot: [f32; 2], // Point of the image on the pointer, in pixels from its top left corner
+}
+
+/// Text annotation being typed, keys go to it instead of the shortcuts
+struct TextEdit {
+    index: usize, // Index of the text stroke
+    caret: usize, // Position in characters in the text
+    is_new: bool, // Created by this edit, so its undo step goes with it when left empty
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum TimerPhase {
+    Work,
+    Break,
+}
+
+/// Work and break timer shown in the corner of the overlay
+struct Timer {
+    phase: TimerPhase,
+    phase_end: Instant,
+    next_redraw: Instant, // The remaining time shown changes every second
+}
+
+/// Stopwatch shown in the corner of the overlay
+struct Stopwatch {
+    elapsed: Duration,      // Time counted until the last pause
+    start: Option<Instant>, // Running since then, None when paused
+    next_redraw: Instant,
+}
+
+/// Measurements of the last frames, shown by the diagnostics HUD
+#[derive(Default)]
+struct FrameStats {
+    frame_times: VecDeque<Instant>, // Frames presented during the last second
+    latency: Duration,              // From the event causing the last frame to its present
+    upload_bytes: usize,            // Vertex data sent to the GPU by the last frame
 }
 
-#[derive(Default, Debug, Copy, Clone)]
-struct Point {
+/// Pacing of the session, shown by the session report and logged on exit
+struct SessionStats {
+    start: Instant,
+    strokes: usize,                        // Lines and shapes drawn
+    undos: usize,                          // Steps undone
+    drawing_time: Duration,                // With the pen down, drawing lines and shapes
+    line_start: Option<Instant>,           // When the line being drawn started
+    pages: usize,                          // Boards drawn on, a clear starting a new one
+    drawn_boards: HashSet<Option<String>>, // Workspaces drawn on since their last clear
+    next_redraw: Instant,
+}
+
+/// An alt-press over a stroke, which takes its color instead of drawing when released without
+/// moving
+struct ColorPick {
     x: f32,
     y: f32,
-    z: f32,
+    strokes: Vec<Rc<Stroke>>, // The strokes before the press, without what it drew
+    n_undo_steps: usize,
 }
 
-impl Point {
-    fn into_array(&self) -> [f32; 3] {
-        [self.x, self.y, self.z]
-    }
+/// Strokes being moved with the cursor
+struct StrokeDrag {
+    indices: Vec<usize>,
+    x: f32,
+    y: f32,
+    is_moved: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum Tool {
+    Brush,
+    StrokeEraser,
+    SegmentEraser,
+    Select,
+    Arrow,
+    Screenshot,
+    Redaction,
+    HighlightBox,
+    Text,
+}
+
+/// What a color key changes besides the color, the brush is left as is for the fields not set
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+struct BrushPreset {
+    /// Replaces the color of the key in brush_colors
+    color: Option<[u32; 3]>,
+    size: Option<f32>,
+    hardness: Option<f32>,
+    tool: Option<Tool>,
 }
 
 #[derive(Default)]
 struct LineStyle {
     color: [f32; 3],
     width: f32,
+    hardness: f32,
     pressure: f32,
+    smoothing_mode: SmoothingMode,
     smoothing_range: usize,
     smoothing_intensity: usize,
+    pressure_smoothing: f32,
+    smoothed_pressure: Option<f32>,
 }
 
 struct GLState {
@@ -133,27 +665,168 @@ struct GLState {
     vs: u32,
     vao: u32,
     vbo: u32,
+    // Uniforms only custom shaders declare, -1 when they don't
+    time_uniform: GLint,
+    resolution_uniform: GLint,
+    start_time: Instant,
 }
 
 struct DrawingState {
     config: Config,
     need_redraw: bool,
+    is_context_lost: bool,
+    is_capturing: bool,
+    proxy: EventLoopProxy<instance::Command>,
     is_window_hidden: bool,
     is_background_visible: bool,
     n_points_current_line: u32,
     line_style: LineStyle,
+    tool: Tool,
+    recent_colors: Vec<[f32; 3]>,
+    secondary_color: [f32; 3],
+    arrow_head: ArrowHead,
     gl_context: GLState,
-    undo_steps: Vec<usize>,
-    smooth_index: usize,
-    vertices: Vec<f32>,
+    strokes: Vec<Rc<Stroke>>,
+    strokes_changed: bool,
+    line_changed: bool,
+    undo_steps: Vec<Vec<Rc<Stroke>>>,
+    boards: HashMap<String, Board>,
+    is_passing_through: bool,
+    is_interacting: bool,
+    is_pushing_to_draw: bool,
+    ink_clear_time: Option<Instant>,
+    is_ephemeral_line: bool,
+    ephemeral_strokes: Vec<(Rc<Stroke>, Instant)>,
+    is_crosshair_visible: bool,
+    is_grid_visible: bool,
+    grid_pattern_index: usize,
+    guide_template: Option<Vec<guides::GuideRect>>,
+    workspace: Option<String>,
+    is_erasing: bool,
+    eraser_size: f32,
+    stroke_drag: Option<StrokeDrag>,
+    color_pick: Option<ColorPick>,
+    selection: Vec<usize>,
+    selection_marquee: Option<(f32, f32)>,
+    text_edit: Option<TextEdit>,
+    text_size: f32,
+    last_activity: Instant,
+    timer: Option<Timer>,
+    flash_end: Option<Instant>,
+    stopwatch: Option<Stopwatch>,
+    counter_values: Vec<i64>,
+    announced: (Tool, [f32; 3], f32),
+    announce_time: Option<Instant>,
+    last_frame_cursor: Option<(f32, f32)>,
+    is_stats_visible: bool,
+    stats: FrameStats,
+    is_session_report_visible: bool,
+    session_stats: SessionStats,
+    axis_lock: Option<AxisLock>,
+    last_transform_time: Option<Instant>,
+    next_group_id: u32,
+    session: Option<session::Session>,
+    remote_strokes: BTreeMap<u32, Vec<Stroke>>,
+    shared_strokes: Vec<Rc<Stroke>>,
+    last_share_time: Instant,
+    viewer: Option<viewer::Viewer>,
+    watermark: Option<Arc<watermark::Watermark>>,
+    cursor_image: Option<CursorImage>,
+    background_image: Option<BackgroundImage>,
+    is_viewer_outdated: bool,
+    stroke_buffers: lines::StrokeBuffers,
+    line_buffers: lines::StrokeBuffers,
+    overlay_vertices: Vec<f32>,
+    beacon_vertices: Vec<f32>,
+    beacon_start: Option<Instant>,
+    grid_vertices: Vec<f32>,
+    text: text::TextRenderer,
+    text_vertices: Vec<f32>,
+    hud_text_vertices: Vec<f32>,
+    images: images::ImageRenderer,
+    patterns: patterns::PatternRenderer,
+    lines: Option<lines::LineRenderer>,
     rect: Rect2D,
 }
 
+/// Whether the context is an OpenGL ES one, the fallback for GPUs without desktop OpenGL 3
+fn is_gles() -> bool {
+    // The version of OpenGL ES contexts always starts with this
+    gl_string(gl::VERSION).starts_with("OpenGL ES")
+}
+
+/// Whether colors are written to the window multiplied by their alpha, as compositors expect
+/// them, instead of straight
+fn is_premultiplied_alpha() -> bool {
+    PREMULTIPLIED_ALPHA.load(Ordering::Relaxed)
+}
+
+/// Clear the window to a color of straight alpha
+unsafe fn clear_to(color: [f32; 3], alpha: f32) {
+    let [r, g, b] = if is_premultiplied_alpha() {
+        color.map(|channel| channel * alpha)
+    } else {
+        color
+    };
+    gl::ClearColor(r, g, b, alpha);
+    gl::Clear(gl::COLOR_BUFFER_BIT);
+}
+
+/// Rewrite a GLSL 1.50 shader as a GLSL ES 1.00 one, for OpenGL ES 2 contexts
+fn to_gles_shader(src: &str, ty: GLenum) -> String {
+    let mut gles_src = String::from("#version 100\nprecision mediump float;\n");
+    for line in src.lines().skip(1) {
+        let is_vertex_shader = ty == gl::VERTEX_SHADER;
+        let line = if let Some(rest) = line.strip_prefix("in ") {
+            let qualifier = if is_vertex_shader {
+                "attribute"
+            } else {
+                "varying"
+            };
+            format!("{} {}", qualifier, rest)
+        } else if let Some(rest) = line.strip_prefix("out ") {
+            if !is_vertex_shader {
+                // The only output becomes gl_FragColor
+                continue;
+            }
+            format!("varying {}", rest)
+        } else {
+            line.replace("out_color", "gl_FragColor")
+                .replace("texture(", "texture2D(")
+        };
+        gles_src.push_str(&line);
+        gles_src.push('\n');
+    }
+    gles_src
+}
+
 fn compile_shader(src: &str, ty: GLenum) -> GLuint {
+    try_compile_shader(src, ty).unwrap_or_else(|e| {
+        log::error!("Failed to compile a shader: {}", e);
+        std::process::exit(1);
+    })
+}
+
+/// Compile a shader, or give the compile log
+fn try_compile_shader(src: &str, ty: GLenum) -> Result<GLuint, String> {
     let shader;
     unsafe {
         shader = gl::CreateShader(ty);
+        // Shaders check PREMULTIPLIED_ALPHA to multiply the colors they output, right after
+        // the version
+        let src = match src.split_once('\n') {
+            Some((version, rest)) if is_premultiplied_alpha() => {
+                format!("{}\n#define PREMULTIPLIED_ALPHA\n{}", version, rest)
+            }
+            _ => src.to_string(),
+        };
+        let src = src.as_str();
         // Attempt to compile the shader
+        let src = if is_gles() {
+            to_gles_shader(src, ty)
+        } else {
+            src.to_string()
+        };
         let c_str = CString::new(src.as_bytes()).unwrap();
         gl::ShaderSource(shader, 1, &c_str.as_ptr(), ptr::null());
         gl::CompileShader(shader);
@@ -166,26 +839,44 @@ fn compile_shader(src: &str, ty: GLenum) -> GLuint {
         if status != (gl::TRUE as GLint) {
             let mut len = 0;
             gl::GetShaderiv(shader, gl::INFO_LOG_LENGTH, &mut len);
-            let mut buf = Vec::with_capacity(len as usize);
-            buf.set_len((len as usize) - 1); // subtract 1 to skip the trailing null character
+            let mut buf = vec![0u8; len as usize];
             gl::GetShaderInfoLog(
                 shader,
                 len,
                 ptr::null_mut(),
                 buf.as_mut_ptr() as *mut GLchar,
             );
-            panic!(
-                "{}",
-                str::from_utf8(&buf)
-                    .ok()
-                    .expect("ShaderInfoLog not valid utf8")
-            );
+            buf.pop(); // skip the trailing null character
+            gl::DeleteShader(shader);
+            return Err(String::from_utf8_lossy(&buf).into_owned());
+        }
+    }
+    Ok(shader)
+}
+
+/// A string of glGetString, such as the version or the renderer
+fn gl_string(name: GLenum) -> String {
+    unsafe {
+        let string = gl::GetString(name);
+        if string.is_null() {
+            String::from("unknown")
+        } else {
+            CStr::from_ptr(string as *const _)
+                .to_string_lossy()
+                .into_owned()
         }
     }
-    shader
 }
 
 fn link_program(vs: GLuint, fs: GLuint) -> GLuint {
+    try_link_program(vs, fs).unwrap_or_else(|e| {
+        log::error!("Failed to link a shader program: {}", e);
+        std::process::exit(1);
+    })
+}
+
+/// Link a program, or give the link log
+fn try_link_program(vs: GLuint, fs: GLuint) -> Result<GLuint, String> {
     unsafe {
         let program = gl::CreateProgram();
         gl::AttachShader(program, vs);
@@ -199,42 +890,186 @@ fn link_program(vs: GLuint, fs: GLuint) -> GLuint {
         if status != (gl::TRUE as GLint) {
             let mut len: GLint = 0;
             gl::GetProgramiv(program, gl::INFO_LOG_LENGTH, &mut len);
-            let mut buf = Vec::with_capacity(len as usize);
-            buf.set_len((len as usize) - 1); // subtract 1 to skip the trailing null character
+            let mut buf = vec![0u8; len as usize];
             gl::GetProgramInfoLog(
                 program,
                 len,
                 ptr::null_mut(),
                 buf.as_mut_ptr() as *mut GLchar,
             );
-            panic!(
-                "{}",
-                str::from_utf8(&buf)
-                    .ok()
-                    .expect("ProgramInfoLog not valid utf8")
-            );
+            buf.pop(); // skip the trailing null character
+            gl::DeleteProgram(program);
+            return Err(String::from_utf8_lossy(&buf).into_owned());
         }
-        program
+        Ok(program)
     }
 }
 
-fn screen_size_to_gl(w: f32, h: f32, overlay_rect: &Rect2D) -> Size2D {
-    Size2D {
-        width: w / overlay_rect.width * 2.0,
-        height: h / overlay_rect.height * 2.0,
+/// Compile the shaders of the strokes from the files of the config, or the embedded ones
+///
+/// A custom shader failing to load or compile is replaced by the embedded one.
+fn compile_stroke_program(config: &Config) -> (GLuint, GLuint, GLuint) {
+    let load = |path: &Option<String>, embedded: &str, ty: GLenum| {
+        if let Some(path) = path {
+            match fs::read_to_string(path) {
+                Ok(src) => match try_compile_shader(&src, ty) {
+                    Ok(shader) => return (shader, true),
+                    Err(e) => log::error!("Failed to compile the shader {}: {}", path, e),
+                },
+                Err(e) => log::error!("Failed to read the shader {}: {}", path, e),
+            }
+        }
+        (compile_shader(embedded, ty), false)
+    };
+    let (vs, is_vs_custom) = load(&config.vertex_shader_path, VS_SRC, gl::VERTEX_SHADER);
+    let (fs, is_fs_custom) = load(&config.fragment_shader_path, FS_SRC, gl::FRAGMENT_SHADER);
+    if !is_vs_custom && !is_fs_custom {
+        return (vs, fs, link_program(vs, fs));
+    }
+
+    match try_link_program(vs, fs) {
+        Ok(program) => (vs, fs, program),
+        Err(e) => {
+            log::error!("Failed to link the custom shaders: {}", e);
+            unsafe {
+                gl::DeleteShader(vs);
+                gl::DeleteShader(fs);
+            }
+            let vs = compile_shader(VS_SRC, gl::VERTEX_SHADER);
+            let fs = compile_shader(FS_SRC, gl::FRAGMENT_SHADER);
+            (vs, fs, link_program(vs, fs))
+        }
+    }
+}
+
+/// Tiling window managers float fixed size utility windows instead of tiling them
+#[cfg(all(unix, not(target_os = "macos")))]
+fn with_platform_options(
+    window_builder: WindowBuilder,
+    overlay_rect: &Rect2D,
+    config: &Config,
+) -> WindowBuilder {
+    use glutin::platform::unix::{WindowBuilderExtUnix, XWindowType};
+
+    if !config.tiling_wm_compat {
+        return window_builder;
+    }
+
+    let size = PhysicalSize::new(overlay_rect.width, overlay_rect.height);
+    window_builder
+        .with_min_inner_size(size)
+        .with_max_inner_size(size)
+        .with_always_on_top(true)
+        .with_x11_window_type(vec![XWindowType::Utility])
+        .with_class("inke".to_string(), "Inke".to_string())
+        .with_app_id("inke".to_string())
+}
+
+#[cfg(not(all(unix, not(target_os = "macos"))))]
+fn with_platform_options(
+    window_builder: WindowBuilder,
+    _overlay_rect: &Rect2D,
+    _config: &Config,
+) -> WindowBuilder {
+    window_builder
+}
+
+/// Follow the user to every Space, including the ones of full screen apps, above other windows
+#[cfg(target_os = "macos")]
+fn join_all_spaces(window: &Window, config: &Config) {
+    use cocoa::appkit::{NSWindow, NSWindowCollectionBehavior};
+    use glutin::platform::macos::WindowExtMacOS;
+
+    #[link(name = "CoreGraphics", kind = "framework")]
+    extern "C" {
+        fn CGShieldingWindowLevel() -> i32;
+    }
+
+    if !config.macos_all_spaces {
+        return;
+    }
+
+    unsafe {
+        let ns_window = window.ns_window() as cocoa::base::id;
+        ns_window.setCollectionBehavior_(
+            NSWindowCollectionBehavior::NSWindowCollectionBehaviorCanJoinAllSpaces
+                | NSWindowCollectionBehavior::NSWindowCollectionBehaviorFullScreenAuxiliary,
+        );
+        ns_window.setLevel_(CGShieldingWindowLevel() as cocoa::foundation::NSInteger);
+    }
+}
+
+/// Tool windows are left out of the taskbar and the alt-tab list
+#[cfg(target_os = "windows")]
+fn hide_from_taskbar(window: &Window, config: &Config) {
+    use glutin::platform::windows::WindowExtWindows;
+    use winapi::um::winuser::{
+        GetWindowLongPtrW, SetWindowLongPtrW, GWL_EXSTYLE, WS_EX_APPWINDOW, WS_EX_TOOLWINDOW,
+    };
+
+    if !config.hide_from_taskbar {
+        return;
+    }
+
+    unsafe {
+        let hwnd = window.hwnd() as winapi::shared::windef::HWND;
+        let style = GetWindowLongPtrW(hwnd, GWL_EXSTYLE) as u32;
+        let style = (style | WS_EX_TOOLWINDOW) & !WS_EX_APPWINDOW;
+        SetWindowLongPtrW(hwnd, GWL_EXSTYLE, style as isize);
     }
 }
 
-#[allow(dead_code)]
-fn screen_point_to_gl(x: f32, y: f32, overlay_rect: &Rect2D) -> Point {
-    Point {
-        x: x / overlay_rect.width * 2.0,
-        y: y / overlay_rect.height * 2.0,
-        z: 0.0,
+/// Create the window with the best OpenGL context the GPU allows, from desktop OpenGL with
+/// `samples` per pixel, halving them down to none, to OpenGL ES 2 (through ANGLE on Windows,
+/// when its libEGL.dll and libGLESv2.dll are next to Inke)
+fn build_gl_window(
+    window_builder: WindowBuilder,
+    event_loop: &EventLoopWindowTarget<instance::Command>,
+    samples: u16,
+) -> Option<glutin::WindowedContext<glutin::NotCurrent>> {
+    // Older integrated GPUs, VMs and remote desktop sessions often lack multisampling, some
+    // sample counts or OpenGL 3. Sample counts are powers of 2
+    let highest_samples = match samples {
+        0 | 1 => 0,
+        samples => 1 << (15 - samples.leading_zeros()),
+    };
+    let mut attempts: Vec<(String, glutin::GlRequest, u16)> =
+        std::iter::successors(Some(highest_samples), |samples| Some(samples / 2))
+            .take_while(|&samples| samples >= 2)
+            .map(|samples| {
+                let name = format!("OpenGL with {}x multisampling", samples);
+                (name, glutin::GlRequest::Latest, samples)
+            })
+            .collect();
+    attempts.push((String::from("OpenGL"), glutin::GlRequest::Latest, 0));
+    attempts.push((
+        String::from("OpenGL ES 2"),
+        glutin::GlRequest::Specific(glutin::Api::OpenGlEs, (2, 0)),
+        0,
+    ));
+    for (name, request, samples) in attempts {
+        let context_builder = glutin::ContextBuilder::new()
+            .with_gl(request)
+            .with_multisampling(samples)
+            // Report GPU resets instead of leaving the context in an undefined state
+            .with_gl_robustness(glutin::Robustness::TryRobustLoseContextOnReset);
+        match context_builder.build_windowed(window_builder.clone(), event_loop) {
+            Ok(gl_window) => {
+                log::info!("Created an {} context", name);
+                return Some(gl_window);
+            }
+            Err(e) => log::warn!("Failed to create an {} context: {}", name, e),
+        }
     }
+    None
 }
 
-fn init_gl_window(event_loop: &EventLoop<()>, overlay_rect: &Rect2D) -> GLState {
+fn init_gl_window(
+    event_loop: &EventLoopWindowTarget<instance::Command>,
+    overlay_rect: &Rect2D,
+    config: &Config,
+    start_hidden: bool,
+) -> GLState {
     let window_builder = glutin::window::WindowBuilder::new()
         .with_title("Inke")
         .with_inner_size(PhysicalSize::new(overlay_rect.width, overlay_rect.height))
@@ -242,28 +1077,62 @@ fn init_gl_window(event_loop: &EventLoop<()>, overlay_rect: &Rect2D) -> GLState
         .with_transparent(true)
         .with_resizable(false)
         .with_visible(false);
+    let window_builder = with_platform_options(window_builder, overlay_rect, config);
 
-    let gl_window = glutin::ContextBuilder::new()
-        .with_multisampling(8)
-        .build_windowed(window_builder, &event_loop)
-        .unwrap();
+    let gl_window = match build_gl_window(window_builder, event_loop, config.multisampling) {
+        Some(gl_window) => gl_window,
+        None => {
+            log::error!("Failed to create the OpenGL window");
+            std::process::exit(1);
+        }
+    };
 
-    let gl_window = unsafe { gl_window.make_current() }.unwrap();
+    let gl_window = match unsafe { gl_window.make_current() } {
+        Ok(gl_window) => gl_window,
+        Err((_, e)) => {
+            log::error!("Failed to make the OpenGL context current: {}", e);
+            std::process::exit(1);
+        }
+    };
+    log::debug!("OpenGL pixel format: {:?}", gl_window.get_pixel_format());
 
     gl_window
         .window()
         .set_outer_position(PhysicalPosition::new(overlay_rect.x, overlay_rect.y));
+    #[cfg(target_os = "macos")]
+    join_all_spaces(gl_window.window(), config);
+    #[cfg(target_os = "windows")]
+    hide_from_taskbar(gl_window.window(), config);
     gl_window.window().set_visible(true);
 
-    // Load the OpenGL function pointers
-    gl::load_with(|symbol| gl_window.get_proc_address(symbol));
+    // Wait minimized in the taskbar instead of covering the screens
+    if start_hidden {
+        gl_window.window().set_minimized(true);
+    }
+
+    // Load the OpenGL function pointers. OpenGL ES 2 only has vertex arrays as an extension,
+    // with the suffix of its functions
+    gl::load_with(|symbol| {
+        let function = gl_window.get_proc_address(symbol);
+        if function.is_null() {
+            gl_window.get_proc_address(&format!("{}OES", symbol))
+        } else {
+            function
+        }
+    });
+    log::debug!(
+        "OpenGL {} on {} by {}",
+        gl_string(gl::VERSION),
+        gl_string(gl::RENDERER),
+        gl_string(gl::VENDOR)
+    );
 
-    let vs = compile_shader(VS_SRC, gl::VERTEX_SHADER);
-    let fs = compile_shader(FS_SRC, gl::FRAGMENT_SHADER);
-    let program = link_program(vs, fs);
+    let (vs, fs, program) = compile_stroke_program(config);
 
     let mut vao = 0;
     let mut vbo = 0;
+    let time_uniform;
+    let resolution_uniform;
 
     unsafe {
         // Create Vertex Array Object
@@ -276,10 +1145,22 @@ fn init_gl_window(event_loop: &EventLoop<()>, overlay_rect: &Rect2D) -> GLState
 
         // Use shader program
         gl::UseProgram(program);
-        gl::BindFragDataLocation(
+        // OpenGL ES shaders write gl_FragColor instead
+        if !is_gles() {
+            gl::BindFragDataLocation(
+                program,
+                0,
+                CStr::from_bytes_with_nul(b"out_color\0").unwrap().as_ptr(),
+            );
+        }
+
+        time_uniform = gl::GetUniformLocation(
             program,
-            0,
-            CStr::from_bytes_with_nul(b"out_color\0").unwrap().as_ptr(),
+            CStr::from_bytes_with_nul(b"time\0").unwrap().as_ptr(),
+        );
+        resolution_uniform = gl::GetUniformLocation(
+            program,
+            CStr::from_bytes_with_nul(b"resolution\0").unwrap().as_ptr(),
         );
 
         // position attrib
@@ -289,12 +1170,12 @@ fn init_gl_window(event_loop: &EventLoop<()>, overlay_rect: &Rect2D) -> GLState
         );
         gl::EnableVertexAttribArray(pos_attr as GLuint);
         gl::VertexAttribPointer(
-            pos_attr as GLuint,                                   // index of attribute
-            3,                                                    // the number of components
-            gl::FLOAT,                                            // data type
-            gl::FALSE as GLboolean,                               // normalized
-            (6 * std::mem::size_of::<f32>()) as gl::types::GLint, // stride (byte offset)
-            ptr::null(),                                          // offset of the first component
+            pos_attr as GLuint,     // index of attribute
+            3,                      // the number of components
+            gl::FLOAT,              // data type
+            gl::FALSE as GLboolean, // normalized
+            (VERTEX_SIZE * std::mem::size_of::<f32>()) as gl::types::GLint, // stride (byte offset)
+            ptr::null(),            // offset of the first component
         );
 
         // vertex_color attrib
@@ -304,105 +1185,84 @@ fn init_gl_window(event_loop: &EventLoop<()>, overlay_rect: &Rect2D) -> GLState
         );
         gl::EnableVertexAttribArray(color_attr as GLuint);
         gl::VertexAttribPointer(
-            color_attr as GLuint,                                 // index of attribute
-            3,                                                    // the number of components
-            gl::FLOAT,                                            // data type
-            gl::FALSE as GLboolean,                               // normalized
-            (6 * std::mem::size_of::<f32>()) as gl::types::GLint, // stride (byte offset)
+            color_attr as GLuint,   // index of attribute
+            3,                      // the number of components
+            gl::FLOAT,              // data type
+            gl::FALSE as GLboolean, // normalized
+            (VERTEX_SIZE * std::mem::size_of::<f32>()) as gl::types::GLint, // stride (byte offset)
             (3 * std::mem::size_of::<f32>()) as *const gl::types::GLvoid, // offset of the first component
         );
-    };
 
-    GLState {
-        window_context: gl_window,
-        program: program,
-        vs: vs,
-        fs: fs,
-        vbo: vbo,
-        vao: vao,
-    }
-}
+        // edge attrib
+        let edge_attr = gl::GetAttribLocation(
+            program,
+            CStr::from_bytes_with_nul(b"vEdge\0").unwrap().as_ptr(),
+        );
+        gl::EnableVertexAttribArray(edge_attr as GLuint);
+        gl::VertexAttribPointer(
+            edge_attr as GLuint,    // index of attribute
+            1,                      // the number of components
+            gl::FLOAT,              // data type
+            gl::FALSE as GLboolean, // normalized
+            (VERTEX_SIZE * std::mem::size_of::<f32>()) as gl::types::GLint, // stride (byte offset)
+            (6 * std::mem::size_of::<f32>()) as *const gl::types::GLvoid, // offset of the first component
+        );
 
-/// Apply line smoothing to parts of a point list
-///
-/// Reference: https://stackoverflow.com/a/18830268
-fn apply_line_smoothing(points: &mut [f32], smoothing_range: usize) {
-    if smoothing_range == 0 {
-        return;
-    }
+        // hardness attrib
+        let hardness_attr = gl::GetAttribLocation(
+            program,
+            CStr::from_bytes_with_nul(b"vHardness\0").unwrap().as_ptr(),
+        );
+        gl::EnableVertexAttribArray(hardness_attr as GLuint);
+        gl::VertexAttribPointer(
+            hardness_attr as GLuint, // index of attribute
+            1,                       // the number of components
+            gl::FLOAT,               // data type
+            gl::FALSE as GLboolean,  // normalized
+            (VERTEX_SIZE * std::mem::size_of::<f32>()) as gl::types::GLint, // stride (byte offset)
+            (7 * std::mem::size_of::<f32>()) as *const gl::types::GLvoid, // offset of the first component
+        );
 
-    // Number of line endings to parse
-    let line_segment_len = 3 * 2 * 6; // 3 (points per triangle) * 2 (triangle) * 6 (properties x,y,z,r,g,b)
-    let n_points = (points.len() / line_segment_len) - 1; // -1 to skip last
+        // opacity attrib
+        let opacity_attr = gl::GetAttribLocation(
+            program,
+            CStr::from_bytes_with_nul(b"vOpacity\0").unwrap().as_ptr(),
+        );
+        gl::EnableVertexAttribArray(opacity_attr as GLuint);
+        gl::VertexAttribPointer(
+            opacity_attr as GLuint, // index of attribute
+            1,                      // the number of components
+            gl::FLOAT,              // data type
+            gl::FALSE as GLboolean, // normalized
+            (VERTEX_SIZE * std::mem::size_of::<f32>()) as gl::types::GLint, // stride (byte offset)
+            (8 * std::mem::size_of::<f32>()) as *const gl::types::GLvoid, // offset of the first component
+        );
 
-    // skip first
-    for i in 1..n_points {
-        let start = if i >= smoothing_range {
-            i - smoothing_range
-        } else {
-            0
-        };
-        let end = if i + smoothing_range < n_points {
-            i + smoothing_range
+        // Feathered line edges are blended with what's under them. Premultiplied colors
+        // already carry their alpha
+        gl::Enable(gl::BLEND);
+        if config.premultiplied_alpha {
+            gl::BlendFunc(gl::ONE, gl::ONE_MINUS_SRC_ALPHA);
         } else {
-            n_points
-        };
+            gl::BlendFuncSeparate(
+                gl::SRC_ALPHA,
+                gl::ONE_MINUS_SRC_ALPHA,
+                gl::ONE,
+                gl::ONE_MINUS_SRC_ALPHA,
+            );
+        }
+    };
 
-        // sums for left side of line (p3, p1)
-        let mut sum_x1 = 0.0_f32;
-        let mut sum_y1 = 0.0_f32;
-        // sums for right side of line (p4, p2)
-        let mut sum_x2 = 0.0_f32;
-        let mut sum_y2 = 0.0_f32;
-
-        for j in start..end {
-            // x,y of p3 for that line segment
-            sum_x1 += points[j * line_segment_len];
-            sum_y1 += points[j * line_segment_len + 1];
-            sum_x2 += points[j * line_segment_len + 30];
-            sum_y2 += points[j * line_segment_len + 30 + 1];
-        }
-
-        let avg_x1 = sum_x1 / ((end - start) as f32);
-        let avg_y1 = sum_y1 / ((end - start) as f32);
-        let avg_x2 = sum_x2 / ((end - start) as f32);
-        let avg_y2 = sum_y2 / ((end - start) as f32);
-
-        /*
-          0  18__ 30
-           |\­  \ |
-           | \  \|
-          12¯¯6   24
-
-         36  54__ 66
-           |\­  \ |
-           | \  \|
-          48¯¯42  60
-        */
-
-        // LEFT SIDE
-        // p3 - 24 = p1 of last segment
-        points[i * line_segment_len - 24] = avg_x1;
-        points[i * line_segment_len - 24 + 1] = avg_y1;
-
-        // p3
-        points[i * line_segment_len] = avg_x1;
-        points[i * line_segment_len + 1] = avg_y1;
-
-        // p3 (second triange)
-        points[i * line_segment_len + 18] = avg_x1;
-        points[i * line_segment_len + 18 + 1] = avg_y1;
-
-        // RIGHT SIDE
-        // p4 - 60 = p2 of last segment (first triangle)
-        points[i * line_segment_len + 30 - 60] = avg_x2;
-        points[i * line_segment_len + 30 - 60 + 1] = avg_y2;
-        // p4 - 42 = p2 of last segment (second triangle)
-        points[i * line_segment_len + 30 - 42] = avg_x2;
-        points[i * line_segment_len + 30 - 42 + 1] = avg_y2;
-        // p4
-        points[i * line_segment_len + 30] = avg_x2;
-        points[i * line_segment_len + 30 + 1] = avg_y2;
+    GLState {
+        window_context: gl_window,
+        program,
+        vs,
+        fs,
+        vbo,
+        vao,
+        time_uniform,
+        resolution_uniform,
+        start_time: Instant::now(),
     }
 }
 
@@ -413,14 +1273,15 @@ fn get_overlay_rect(monitors: impl Iterator<Item = MonitorHandle>) -> Rect2D {
     let mut max_y: i32 = 0;
 
     for monitor in monitors {
-        // println!(
-        //     "Monitor {} = x: {}, y: {}, w: {}, h: {}",
-        //     monitor.name().unwrap(),
-        //     monitor.position().x,
-        //     monitor.position().y,
-        //     monitor.size().width,
-        //     monitor.size().height
-        // );
+        log::debug!(
+            "Monitor {} = x: {}, y: {}, w: {}, h: {}, scale: {}",
+            monitor.name().unwrap_or_default(),
+            monitor.position().x,
+            monitor.position().y,
+            monitor.size().width,
+            monitor.size().height,
+            monitor.scale_factor()
+        );
         if monitor.position().x < min_x {
             min_x = monitor.position().x;
         }
@@ -435,486 +1296,4277 @@ fn get_overlay_rect(monitors: impl Iterator<Item = MonitorHandle>) -> Rect2D {
         }
     }
 
-    Rect2D {
+    let rect = Rect2D {
         x: min_x as f32,
         y: min_y as f32,
         width: (max_x - min_x) as f32,
         height: (max_y - min_y) as f32,
+    };
+    if rect.width <= 0.0 || rect.height <= 0.0 {
+        log::error!("No monitor found, the overlay has no size");
     }
+    log::debug!(
+        "Overlay = x: {}, y: {}, w: {}, h: {}",
+        rect.x,
+        rect.y,
+        rect.width,
+        rect.height
+    );
+    rect
 }
 
-fn handle_event(
-    event: Event<()>,
-    control_flow: &mut ControlFlow,
-    drawing: &mut DrawingState,
-    input: &mut Input,
-) -> () {
-    *control_flow = ControlFlow::Wait;
+/// Brush color of the config bound to a color key, if there are enough colors
+fn color_of_key(drawing: &DrawingState, input: &Input, key: VirtualKeyCode) -> Option<[f32; 3]> {
+    let mut index = COLOR_KEYS.iter().position(|&k| k == key)?;
+    if input.modifiers.shift {
+        index += COLOR_KEYS.len();
+    }
+    drawing
+        .config
+        .brush_colors
+        .get(index)
+        .map(|&color| color_to_gl(color))
+}
 
-    match event {
-        Event::LoopDestroyed => return,
-        Event::WindowEvent { event, .. } => match event {
-            // Alt-tab in and out
-            WindowEvent::Focused(has_focus) => {
-                if has_focus {
-                    // unhide
-                    drawing.is_window_hidden = false;
-                } else {
-                    // force window to minimize
-                    drawing
-                        .gl_context
-                        .window_context
-                        .window()
-                        .set_minimized(true);
-                }
-            }
-            WindowEvent::ModifiersChanged(modifier) => {
-                input.modifiers.logo = modifier.logo();
-                input.modifiers.alt = modifier.alt();
-                input.modifiers.shift = modifier.shift();
-                input.modifiers.ctrl = modifier.ctrl();
-            }
-            WindowEvent::KeyboardInput {
-                device_id: _,
-                input: keyboard_input,
-                is_synthetic: _,
-            } => {
-                if keyboard_input.state == glutin::event::ElementState::Released {
-                    match keyboard_input.virtual_keycode {
-                        None => (),
-                        Some(key) => {
-                            match key {
-                                // escape
-                                VirtualKeyCode::Escape => {
-                                    // Todo: Request close event
-                                    unsafe {
-                                        gl::DeleteProgram(drawing.gl_context.program);
-                                        gl::DeleteShader(drawing.gl_context.fs);
-                                        gl::DeleteShader(drawing.gl_context.vs);
-                                        gl::DeleteBuffers(1, &drawing.gl_context.vbo);
-                                        gl::DeleteVertexArrays(1, &drawing.gl_context.vao);
-                                    }
-                                    *control_flow = ControlFlow::Exit
-                                }
-                                VirtualKeyCode::H => {
-                                    drawing.need_redraw = true;
-                                    // TODO: Show help
-                                }
-                                VirtualKeyCode::B => {
-                                    // Toggle background
-                                    drawing.need_redraw = true;
-                                    drawing.is_background_visible = !drawing.is_background_visible;
-                                }
-                                VirtualKeyCode::Space => {
-                                    // Clear drawings
-                                    drawing.need_redraw = true;
-                                    drawing.vertices.clear(); //resize(0, 0.0);
-                                    drawing.undo_steps.clear();
-                                    drawing.n_points_current_line = 0;
-                                    drawing.smooth_index = 0;
-                                }
-                                VirtualKeyCode::Z => {
-                                    // ctrl-z or cmd-z
-                                    if input.modifiers.ctrl || input.modifiers.logo {
-                                        // Undo (if any undo steps are available)
-                                        match drawing.undo_steps.pop() {
-                                            Some(n) => {
-                                                drawing.vertices.resize(n, 0.0);
-                                                drawing.need_redraw = true;
-                                                drawing.n_points_current_line = 0;
-                                                drawing.smooth_index = drawing.vertices.len();
-                                            }
-                                            None => (),
-                                        }
-                                    }
-                                }
+/// Change the size, hardness and tool as the brush preset of a color key says, and its color
+/// when the preset has its own
+fn apply_brush_preset(drawing: &mut DrawingState, input: &Input, key: VirtualKeyCode) {
+    let mut index = match COLOR_KEYS.iter().position(|&k| k == key) {
+        Some(index) => index,
+        None => return,
+    };
+    if input.modifiers.shift {
+        index += COLOR_KEYS.len();
+    }
+    let preset = match drawing.config.brush_presets.get(index) {
+        Some(preset) => preset.clone(),
+        None => return,
+    };
 
-                                // q,w,e,r,... for line colors
+    if let Some(color) = preset.color {
+        set_color(drawing, color_to_gl(color));
+    }
+    if let Some(size) = preset.size {
+        drawing.line_style.width = size.max(1.0);
+    }
+    if let Some(hardness) = preset.hardness {
+        drawing.line_style.hardness = hardness.clamp(0.0, 1.0);
+    }
+    if let Some(tool) = preset.tool {
+        end_line(drawing, input);
+        drawing.selection.clear();
+        drawing.tool = tool;
+    }
+    drawing.need_redraw = true;
+}
 
-                                // q (white)
-                                VirtualKeyCode::Q => {
-                                    drawing.line_style.color =
-                                        color_to_gl(drawing.config.brush_colors[0]);
-                                    drawing.need_redraw = true;
-                                }
-                                // w (black)
-                                VirtualKeyCode::W => {
-                                    drawing.line_style.color =
-                                        color_to_gl(drawing.config.brush_colors[1]);
-                                    drawing.need_redraw = true;
-                                }
-                                // e (orange)
-                                VirtualKeyCode::E => {
-                                    drawing.line_style.color =
-                                        color_to_gl(drawing.config.brush_colors[2]);
-                                    drawing.need_redraw = true;
-                                }
-                                // r (pink)
-                                VirtualKeyCode::R => {
-                                    drawing.line_style.color =
-                                        color_to_gl(drawing.config.brush_colors[3]);
-                                    drawing.need_redraw = true;
-                                }
-                                // t (red)
-                                VirtualKeyCode::T => {
-                                    drawing.line_style.color =
-                                        color_to_gl(drawing.config.brush_colors[4]);
-                                    drawing.need_redraw = true;
-                                }
-                                // y (green)
-                                VirtualKeyCode::Y => {
-                                    drawing.line_style.color =
-                                        color_to_gl(drawing.config.brush_colors[5]);
-                                    drawing.need_redraw = true;
-                                }
-                                // u (blue)
-                                VirtualKeyCode::U => {
-                                    drawing.line_style.color =
-                                        color_to_gl(drawing.config.brush_colors[6]);
-                                    drawing.need_redraw = true;
-                                }
-                                // i (yellow)
-                                VirtualKeyCode::I => {
-                                    drawing.line_style.color =
-                                        color_to_gl(drawing.config.brush_colors[7]);
-                                    drawing.need_redraw = true;
-                                }
+/// Change the brush color and move it first in the recent colors
+fn set_color(drawing: &mut DrawingState, color: [f32; 3]) {
+    drawing.line_style.color = color;
+    drawing.recent_colors.retain(|&c| c != color);
+    drawing.recent_colors.insert(0, color);
+    drawing.recent_colors.truncate(N_RECENT_COLORS);
+}
 
-                                // 1,2,3,... for size
-                                VirtualKeyCode::Key1 => {
-                                    drawing.line_style.width = drawing.config.brush_sizes[0];
-                                    drawing.need_redraw = true;
-                                }
-                                VirtualKeyCode::Key2 => {
-                                    drawing.line_style.width = drawing.config.brush_sizes[1];
-                                    drawing.need_redraw = true;
-                                }
-                                VirtualKeyCode::Key3 => {
-                                    drawing.line_style.width = drawing.config.brush_sizes[2];
-                                    drawing.need_redraw = true;
-                                }
-                                VirtualKeyCode::Key4 => {
-                                    drawing.line_style.width = drawing.config.brush_sizes[3];
-                                    drawing.need_redraw = true;
-                                }
-                                VirtualKeyCode::Key5 => {
-                                    drawing.line_style.width = drawing.config.brush_sizes[4];
-                                    drawing.need_redraw = true;
-                                }
+/// Save the current strokes so the next change can be undone
+fn push_undo_step(drawing: &mut DrawingState) {
+    drawing.undo_steps.push(drawing.strokes.clone());
+}
 
-                                _ => (),
-                            }
-                        }
+/// Finish the line, erasing or selection gesture in progress when the cursor is released
+fn end_line(drawing: &mut DrawingState, input: &Input) {
+    if drawing.n_points_current_line > 0 {
+        let n_strokes = drawing.strokes.len();
+        // Both ends of a line snap to the grid. Only shapes snap to other lines, handwriting
+        // would keep jumping to the letters next to it
+        let grid_size = grid_snap_size(drawing);
+        let snapped_ends = drawing
+            .strokes
+            .last()
+            .and_then(|stroke| Some((*stroke.points.first()?, *stroke.points.last()?)))
+            .map(|(first, last)| {
+                (
+                    snap_to_grid(grid_size, first.x, first.y),
+                    snap_to_grid(grid_size, last.x, last.y),
+                )
+            });
+        let straighten_tolerance = mm_to_pixels(drawing, drawing.config.straighten_tolerance_mm);
+        let close_distance = mm_to_pixels(drawing, drawing.config.close_distance_mm);
+        if let Some(stroke) = drawing.strokes.last_mut() {
+            let stroke = Rc::make_mut(stroke);
+            if stroke.shape == Shape::Freehand {
+                stroke.smooth(
+                    drawing.line_style.smoothing_mode,
+                    drawing.line_style.smoothing_range,
+                    drawing.line_style.smoothing_intensity,
+                );
+                if let Some(((first_x, first_y), (last_x, last_y))) = snapped_ends {
+                    let n_points = stroke.points.len();
+                    stroke.points[0].x = first_x;
+                    stroke.points[0].y = first_y;
+                    stroke.points[n_points - 1].x = last_x;
+                    stroke.points[n_points - 1].y = last_y;
+                }
+                // Nearly straight lines become straight, between their snapped ends
+                if straighten_tolerance > 0.0 {
+                    if let Some(points) = straighten_polyline(&stroke.points, straighten_tolerance)
+                    {
+                        stroke.points = points;
                     }
                 }
+                // Loops with ends close together are closed, straightened lines never are
+                if close_distance > 0.0 {
+                    close_polyline(&mut stroke.points, close_distance);
+                }
+                stroke.tessellate(&drawing.rect, drawing.lines.is_some());
+            } else if stroke.bounds().width == 0.0 && stroke.bounds().height == 0.0 {
+                // Shapes need a drag, forget shapes created by a simple click
+                drawing.strokes.pop();
+                drawing.undo_steps.pop();
             }
-            WindowEvent::Touch(touch_event) => {
-                drawing.need_redraw = true;
-
-                if touch_event.phase == TouchPhase::Started {
-                    input.cursor.pressed = true;
+        }
+        if drawing.strokes.len() == n_strokes {
+            count_stroke(drawing);
+            // Out of the drawing, they are never saved, exported or undone
+            if drawing.is_ephemeral_line {
+                let fade_time =
+                    Instant::now() + Duration::from_secs_f32(drawing.config.ephemeral_stroke_s);
+                if let Some(stroke) = drawing.strokes.pop() {
+                    drawing.ephemeral_strokes.push((stroke, fade_time));
                 }
-                if touch_event.phase == TouchPhase::Ended
-                    || touch_event.phase == TouchPhase::Cancelled
-                {
-                    input.cursor.pressed = false;
-                    input.cursor.released_time = Some(SystemTime::now());
-
-                    for _ in 0..drawing.line_style.smoothing_intensity {
-                        apply_line_smoothing(
-                            &mut drawing.vertices[drawing.smooth_index..],
-                            drawing.line_style.smoothing_range,
-                        );
-                    }
-                    drawing.smooth_index = drawing.vertices.len();
+            }
+        }
+        drawing.is_ephemeral_line = false;
+        drawing.strokes_changed = true;
+    }
+    if let Some((x, y)) = drawing.selection_marquee.take() {
+        let region = Rect2D {
+            x: x.min(input.cursor.x),
+            y: y.min(input.cursor.y),
+            width: (input.cursor.x - x).abs(),
+            height: (input.cursor.y - y).abs(),
+        };
+        tools::handler(drawing.tool).select_region(drawing, input, &region);
+    }
+    drawing.n_points_current_line = 0;
+    drawing.line_style.smoothed_pressure = None;
+    drawing.is_erasing = false;
+    drawing.stroke_drag = None;
+}
 
-                    drawing.need_redraw = true;
+/// Save the screen region below the overlay, with the background and lines drawn over it
+///
+/// The capture and the PNG encoding happen on a thread of their own, the overlay isn't drawn
+/// until the screen is captured.
+fn take_screenshot(drawing: &mut DrawingState, region: &Rect2D) {
+    if drawing.is_capturing {
+        return;
+    }
+    let (x, y, width, height) = match pixel_region(drawing, region) {
+        Some(region) => region,
+        None => return,
+    };
+
+    let annotations = read_annotations(drawing, x, y, width, height);
+
+    clear_overlay(drawing);
+    drawing.is_capturing = true;
+    let screen_x = drawing.rect.x as i32 + x;
+    let screen_y = drawing.rect.y as i32 + y;
+    let scale_factor = drawing.gl_context.window_context.window().scale_factor();
+    let proxy = drawing.proxy.clone();
+    let watermark = drawing.watermark.clone();
+    std::thread::spawn(move || {
+        std::thread::sleep(SCREENSHOT_DELAY);
+        let pixels = screenshot::capture(screen_x, screen_y, width, height, scale_factor);
+        // The event loop is gone when Inke is closing, the screenshot is lost then
+        let _ = proxy.send_event(instance::Command::ScreenCaptured);
+        let result = match pixels {
+            Some(pixels) => {
+                save_screenshot(pixels, &annotations, watermark.as_deref(), width, height)
+            }
+            None => Err(String::from("Failed to capture the screen")),
+        };
+        let _ = proxy.send_event(instance::Command::Saved(result));
+    });
+}
+
+/// Draw the annotations and the watermark over the captured screen and write them to a new PNG
+/// file
+fn save_screenshot(
+    mut pixels: Vec<u8>,
+    annotations: &[u8],
+    watermark: Option<&watermark::Watermark>,
+    width: u32,
+    height: u32,
+) -> Result<String, String> {
+    for (pixel, annotation) in pixels.chunks_mut(3).zip(annotations.chunks(4)) {
+        let alpha = annotation[3] as f32 / 255.0;
+        for (channel, &color) in pixel.iter_mut().zip(annotation.iter()) {
+            *channel = (color as f32 * alpha + *channel as f32 * (1.0 - alpha)).round() as u8;
+        }
+    }
+    if let Some(watermark) = watermark {
+        watermark.composite(&mut pixels, width, height);
+    }
+
+    let dir = Path::new("screenshots");
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_millis())
+        .unwrap_or_default();
+    let path = dir.join(format!("inke-{}.png", time));
+    match fs::create_dir_all(dir).and_then(|_| screenshot::save_png(&path, width, height, &pixels))
+    {
+        Ok(()) => Ok(format!("Screenshot saved to {}", path.display())),
+        Err(e) => Err(format!("Failed to save the screenshot: {}", e)),
+    }
+}
+
+/// Write the strokes to a new JSON file of the interchange format, see inke_core::interchange
+fn export_strokes(drawing: &DrawingState) {
+    spawn_export(drawing, "strokes", "", |strokes, width, height| {
+        interchange::export_json(strokes, width, height)
+    });
+}
+
+/// Write the strokes with when they were drawn to a new JSON file, see inke_core::timeline
+fn export_timeline(drawing: &DrawingState) {
+    spawn_export(
+        drawing,
+        "timeline",
+        "-timeline",
+        |strokes, width, height| timeline::export_json(strokes, width, height),
+    );
+}
+
+/// Encode a copy of the strokes and write it to a new file of the exports directory, on a
+/// thread of its own like the screenshots
+fn spawn_export(
+    drawing: &DrawingState,
+    what: &'static str,
+    file_suffix: &'static str,
+    encode: fn(&[Stroke], f32, f32) -> String,
+) {
+    let strokes: Vec<Stroke> = drawing
+        .strokes
+        .iter()
+        .map(|stroke| (**stroke).clone())
+        .collect();
+    let (width, height) = (drawing.rect.width, drawing.rect.height);
+    let proxy = drawing.proxy.clone();
+    std::thread::spawn(move || {
+        let json = encode(&strokes, width, height);
+        let dir = Path::new("exports");
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|time| time.as_millis())
+            .unwrap_or_default();
+        let path = dir.join(format!("inke-{}{}.json", time, file_suffix));
+        let result = match fs::create_dir_all(dir).and_then(|_| fs::write(&path, json)) {
+            Ok(()) => Ok(format!("Exported the {} to {}", what, path.display())),
+            Err(e) => Err(format!("Failed to export the {}: {}", what, e)),
+        };
+        // The event loop is gone when Inke is closing, the message is lost then
+        let _ = proxy.send_event(instance::Command::Saved(result));
+    });
+}
+
+/// Add the strokes of a JSON file of the interchange format over the drawing, selected
+fn import_strokes(drawing: &mut DrawingState, path: &Path) {
+    let strokes = match fs::read_to_string(path)
+        .map_err(|e| e.to_string())
+        .and_then(|json| interchange::import_json(&json))
+    {
+        Ok(strokes) => strokes,
+        Err(e) => {
+            log::error!("Failed to import the strokes of {}: {}", path.display(), e);
+            return;
+        }
+    };
+    if strokes.is_empty() {
+        return;
+    }
+
+    end_text_edit(drawing);
+    push_undo_step(drawing);
+
+    // The groups of the file become new groups, apart from the ones already drawn
+    let mut new_groups: Vec<(u32, u32)> = Vec::new();
+    drawing.selection.clear();
+    for mut stroke in strokes {
+        if let Some(group) = stroke.group {
+            let new_group = match new_groups.iter().find(|(old, _)| *old == group) {
+                Some(&(_, new_group)) => new_group,
+                None => {
+                    let new_group = drawing.next_group_id;
+                    drawing.next_group_id += 1;
+                    new_groups.push((group, new_group));
+                    new_group
+                }
+            };
+            stroke.group = Some(new_group);
+        }
+        stroke.tessellate(&drawing.rect, drawing.lines.is_some());
+        drawing.selection.push(drawing.strokes.len());
+        drawing.strokes.push(Rc::new(stroke));
+    }
+
+    log::info!("Strokes imported from {}", path.display());
+    drawing.strokes_changed = true;
+    drawing.need_redraw = true;
+}
+
+/// Place a PNG image file dropped on the overlay
+fn drop_image(drawing: &mut DrawingState, input: &Input, path: &Path) {
+    let image = fs::read(path).map_err(|e| e.to_string()).and_then(|png| {
+        let (width, height, _) = images::decode_png(&png)?;
+        Ok((png, width, height))
+    });
+    match image {
+        Ok((png, width, height)) => {
+            place_image(drawing, input, png, width, height);
+            log::info!("Image placed from {}", path.display());
+        }
+        Err(e) => log::error!("Failed to open the image {}: {}", path.display(), e),
+    }
+}
+
+/// Load the background image and fit it to each monitor, if one is configured
+fn load_background_image(
+    config: &Config,
+    monitors: impl Iterator<Item = MonitorHandle>,
+    overlay_rect: &Rect2D,
+) -> Option<BackgroundImage> {
+    let path = config.background_image_path.as_deref()?;
+    let image = fs::read(path).map_err(|e| e.to_string()).and_then(|png| {
+        let (width, height, _) = images::decode_png(&png)?;
+        Ok((png, width as f32, height as f32))
+    });
+    let (png, width, height) = match image {
+        Ok(image) => image,
+        Err(e) => {
+            log::error!("Failed to open the background image {}: {}", path, e);
+            return None;
+        }
+    };
+
+    let png = Arc::new(png);
+    let placements = monitors
+        .map(|monitor| {
+            let monitor = Rect2D {
+                x: monitor.position().x as f32 - overlay_rect.x,
+                y: monitor.position().y as f32 - overlay_rect.y,
+                width: monitor.size().width as f32,
+                height: monitor.size().height as f32,
+            };
+            let scale_x = monitor.width / width;
+            let scale_y = monitor.height / height;
+            let (image_width, image_height) = match config.background_image_fit {
+                BackgroundFit::Contain => {
+                    let scale = scale_x.min(scale_y);
+                    (width * scale, height * scale)
+                }
+                BackgroundFit::Cover => {
+                    let scale = scale_x.max(scale_y);
+                    (width * scale, height * scale)
+                }
+                BackgroundFit::Stretch => (monitor.width, monitor.height),
+            };
+            // Centered on the monitor
+            let x = monitor.x + (monitor.width - image_width) / 2.0;
+            let y = monitor.y + (monitor.height - image_height) / 2.0;
+            let corner = |x: f32, y: f32| StrokePoint {
+                x,
+                y,
+                pressure: 1.0,
+                time: 0.0,
+            };
+            let stroke = Stroke {
+                points: vec![corner(x, y), corner(x + image_width, y + image_height)],
+                shape: Shape::Image,
+                image: Some(png.clone()),
+                ..Default::default()
+            };
+            (monitor, stroke)
+        })
+        .collect();
+    Some(BackgroundImage { placements })
+}
+
+/// Load the image replacing the reticle, if one is configured
+fn load_cursor_image(config: &Config) -> Option<CursorImage> {
+    let path = config.cursor_image_path.as_deref()?;
+    let image = fs::read(path).map_err(|e| e.to_string()).and_then(|png| {
+        let (width, height, _) = images::decode_png(&png)?;
+        Ok((png, width, height))
+    });
+    match image {
+        Ok((png, width, height)) => Some(CursorImage {
+            png: Arc::new(png),
+            width: width as f32,
+            height: height as f32,
+            hotspot: config.cursor_hotspot,
+        }),
+        Err(e) => {
+            log::error!("Failed to open the cursor image {}: {}", path, e);
+            None
+        }
+    }
+}
+
+/// Image stroke of the cursor image at the pointer, at its size for the default brush size and
+/// scaled with the brush. Erasers keep their reticle, showing what gets erased
+fn cursor_image_stroke(drawing: &DrawingState, input: &Input) -> Option<Stroke> {
+    let image = drawing.cursor_image.as_ref()?;
+    if is_eraser(drawing.tool) {
+        return None;
+    }
+    let scale = drawing.line_style.width / drawing.config.default_brush_size.max(0.1);
+    let x = input.cursor.x - image.hotspot[0] * scale;
+    let y = input.cursor.y - image.hotspot[1] * scale;
+    let corner = |x: f32, y: f32| StrokePoint {
+        x,
+        y,
+        pressure: 1.0,
+        time: 0.0,
+    };
+    Some(Stroke {
+        points: vec![
+            corner(x, y),
+            corner(x + image.width * scale, y + image.height * scale),
+        ],
+        shape: Shape::Image,
+        image: Some(image.png.clone()),
+        ..Default::default()
+    })
+}
+
+/// Place a QR code of a link or text, so the audience can scan it off the screen
+fn place_qr_code(drawing: &mut DrawingState, input: &Input, text: &str) {
+    let code = match qr::QrCode::encode(text) {
+        Some(code) => code,
+        None => {
+            log::error!("The text is too long for a QR code");
+            return;
+        }
+    };
+
+    // Light quiet zone around the code, modules drawn as squares of pixels
+    let side = ((code.size() + QR_QUIET_ZONE * 2) * QR_MODULE_PIXELS) as u32;
+    let mut pixels = vec![255u8; (side * side) as usize];
+    for (i, pixel) in pixels.iter_mut().enumerate() {
+        let x = i % side as usize / QR_MODULE_PIXELS;
+        let y = i / side as usize / QR_MODULE_PIXELS;
+        let is_code = (QR_QUIET_ZONE..QR_QUIET_ZONE + code.size()).contains(&x)
+            && (QR_QUIET_ZONE..QR_QUIET_ZONE + code.size()).contains(&y);
+        if is_code && code.is_dark(x - QR_QUIET_ZONE, y - QR_QUIET_ZONE) {
+            *pixel = 0;
+        }
+    }
+
+    let mut png = Vec::new();
+    let mut encoder = png::Encoder::new(&mut png, side, side);
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::Eight);
+    let result = encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(&pixels));
+    if let Err(e) = result {
+        log::error!("Failed to create the QR code: {}", e);
+        return;
+    }
+    place_image(drawing, input, png, side, side);
+}
+
+/// Place a PNG image centered on the cursor, scaled down to fit in half of the overlay, and
+/// select it so it can be moved
+fn place_image(drawing: &mut DrawingState, input: &Input, png: Vec<u8>, width: u32, height: u32) {
+    let (width, height) = (width as f32, height as f32);
+    let scale = (drawing.rect.width / 2.0 / width)
+        .min(drawing.rect.height / 2.0 / height)
+        .min(1.0);
+    let (half_width, half_height) = (width * scale / 2.0, height * scale / 2.0);
+    let corner = |x: f32, y: f32| StrokePoint {
+        x,
+        y,
+        pressure: 1.0,
+        time: 0.0,
+    };
+    let stroke = Stroke {
+        points: vec![
+            corner(input.cursor.x - half_width, input.cursor.y - half_height),
+            corner(input.cursor.x + half_width, input.cursor.y + half_height),
+        ],
+        hardness: 1.0,
+        shape: Shape::Image,
+        image: Some(Arc::new(png)),
+        ..Default::default()
+    };
+
+    end_text_edit(drawing);
+    push_undo_step(drawing);
+    drawing.selection = vec![drawing.strokes.len()];
+    drawing.strokes.push(Rc::new(stroke));
+
+    drawing.strokes_changed = true;
+    drawing.need_redraw = true;
+}
+
+/// Show the screen below the overlay until the next frame
+fn clear_overlay(drawing: &mut DrawingState) {
+    unsafe {
+        gl::ClearColor(0.0, 0.0, 0.0, 0.0);
+        gl::Clear(gl::COLOR_BUFFER_BIT);
+    }
+    if let Err(glutin::ContextError::ContextLost) = drawing.gl_context.window_context.swap_buffers()
+    {
+        drawing.is_context_lost = true;
+    }
+    drawing.need_redraw = true;
+}
+
+/// Whole pixels of a region inside the overlay, None if it's too small to be used
+fn pixel_region(drawing: &DrawingState, region: &Rect2D) -> Option<(i32, i32, u32, u32)> {
+    let x = region.x.max(0.0).round() as i32;
+    let y = region.y.max(0.0).round() as i32;
+    let width = (region.x + region.width).min(drawing.rect.width).round() as i32 - x;
+    let height = (region.y + region.height).min(drawing.rect.height).round() as i32 - y;
+    if width < 2 || height < 2 {
+        return None;
+    }
+    Some((x, y, width as u32, height as u32))
+}
+
+/// Capture the screen below a region of the overlay, as rgb rows from the top
+fn capture_below_overlay(
+    drawing: &mut DrawingState,
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+) -> Option<Vec<u8>> {
+    // Clear the overlay so the capture only has the screen below it
+    clear_overlay(drawing);
+    std::thread::sleep(SCREENSHOT_DELAY);
+
+    screenshot::capture(
+        drawing.rect.x as i32 + x,
+        drawing.rect.y as i32 + y,
+        width,
+        height,
+        drawing.gl_context.window_context.window().scale_factor(),
+    )
+}
+
+/// Cover a region with a pixelated copy of the screen below it, or with the brush color
+fn add_redaction(drawing: &mut DrawingState, region: &Rect2D) {
+    let (x, y, width, height) = match pixel_region(drawing, region) {
+        Some(region) => region,
+        None => return,
+    };
+
+    let block_size = drawing.config.redaction_block_size.max(1.0);
+    let columns = (width as f32 / block_size).ceil() as usize;
+    let rows = (height as f32 / block_size).ceil() as usize;
+
+    // Average color of each block, row by row
+    let mut blocks = Vec::new();
+    if drawing.config.redaction_style == RedactionStyle::Pixelate {
+        match capture_below_overlay(drawing, x, y, width, height) {
+            Some(pixels) => {
+                for row in 0..rows {
+                    for column in 0..columns {
+                        let mut sum = [0.0; 3];
+                        let mut count = 0.0;
+                        let min_y = (row as f32 * block_size) as u32;
+                        let max_y = (((row + 1) as f32 * block_size) as u32).min(height);
+                        let min_x = (column as f32 * block_size) as u32;
+                        let max_x = (((column + 1) as f32 * block_size) as u32).min(width);
+                        for py in min_y..max_y {
+                            for px in min_x..max_x {
+                                let i = ((py * width + px) * 3) as usize;
+                                for (channel, sum) in sum.iter_mut().enumerate() {
+                                    *sum += pixels[i + channel] as f32 / 255.0;
+                                }
+                                count += 1.0;
+                            }
+                        }
+                        blocks.push([sum[0] / count, sum[1] / count, sum[2] / count]);
+                    }
+                }
+            }
+            // Still hide what's below, with a solid block
+            None => log::error!("Failed to capture the screen, using a solid block"),
+        }
+    }
+
+    let corner = |x: f32, y: f32| StrokePoint {
+        x,
+        y,
+        pressure: 1.0,
+        time: 0.0,
+    };
+    let mut stroke = Stroke {
+        points: vec![
+            corner(x as f32, y as f32),
+            corner((x + width as i32) as f32, (y + height as i32) as f32),
+        ],
+        color: drawing.line_style.color,
+        shape: Shape::Redaction { columns },
+        blocks,
+        ..Default::default()
+    };
+    stroke.tessellate(&drawing.rect, drawing.lines.is_some());
+
+    push_undo_step(drawing);
+    drawing.strokes.push(Rc::new(stroke));
+    drawing.strokes_changed = true;
+    drawing.need_redraw = true;
+}
+
+/// Draw the background and lines of a region of the overlay off screen, as rgba rows from the top
+/// of straight alpha
+fn read_annotations(
+    drawing: &mut DrawingState,
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+) -> Vec<u8> {
+    let mut buffers = lines::StrokeBuffers::default();
+    let mut text_vertices = Vec::new();
+    for stroke in &drawing.strokes {
+        buffers.push(stroke, 1.0, drawing.lines.is_some());
+        if let (Shape::Text { size }, Some(origin)) = (stroke.shape, stroke.points.first()) {
+            let line_height = drawing.text.line_height(size);
+            let color = [stroke.color[0], stroke.color[1], stroke.color[2], 1.0];
+            for (i, line) in stroke.text.split('\n').enumerate() {
+                drawing.text.push_text(
+                    &mut text_vertices,
+                    line,
+                    origin.x,
+                    origin.y + i as f32 * line_height,
+                    size,
+                    color,
+                    &drawing.rect,
+                );
+            }
+        }
+    }
+    let mut pixels = vec![0u8; (width * height * 4) as usize];
+
+    unsafe {
+        if drawing.is_background_visible {
+            let bg_color_gl = color_to_gl(drawing.config.background_color);
+            clear_to(bg_color_gl, drawing.config.background_color_opacity);
+        } else {
+            clear_to([0.0, 0.0, 0.0], 0.0);
+        }
+
+        // Images are under the ink, like on the overlay
+        let image_strokes = drawing
+            .strokes
+            .iter()
+            .filter(|stroke| stroke.shape == Shape::Image)
+            .map(|stroke| (stroke.as_ref(), 1.0));
+        drawing.images.draw(image_strokes, &drawing.rect);
+
+        let time = drawing.gl_context.start_time.elapsed().as_secs_f32();
+        buffers.draw(drawing.lines.as_ref(), &drawing.rect, time);
+        drawing.text.draw(&text_vertices);
+
+        // Read from the back buffer, before it's ever shown
+        gl::ReadPixels(
+            x,
+            drawing.rect.height as i32 - y - height as i32,
+            width as i32,
+            height as i32,
+            gl::RGBA,
+            gl::UNSIGNED_BYTE,
+            pixels.as_mut_ptr() as *mut GLvoid,
+        );
+    }
+
+    if is_premultiplied_alpha() {
+        for pixel in pixels.chunks_mut(4) {
+            let alpha = pixel[3] as u32;
+            for channel in &mut pixel[..3] {
+                if let Some(color) = (*channel as u32 * 255).checked_div(alpha) {
+                    *channel = color.min(255) as u8;
+                }
+            }
+        }
+    }
+
+    // GL rows start from the bottom
+    pixels
+        .chunks((width * 4) as usize)
+        .rev()
+        .flatten()
+        .copied()
+        .collect()
+}
+
+/// Index of the topmost unlocked stroke under the cursor
+fn stroke_at_cursor(drawing: &DrawingState, input: &Input) -> Option<usize> {
+    // A few pixels of tolerance so thin lines can be grabbed
+    let tolerance = 4.0;
+
+    drawing.strokes.iter().rposition(|stroke| {
+        !stroke.locked && stroke.hit_test(input.cursor.x, input.cursor.y, tolerance)
+    })
+}
+
+/// Color of the topmost stroke under the cursor, locked ones included. Images and pixelated
+/// redactions have no color of their own
+fn stroke_color_at_cursor(drawing: &DrawingState, input: &Input) -> Option<[f32; 3]> {
+    let tolerance = 4.0;
+    drawing
+        .strokes
+        .iter()
+        .rev()
+        .filter(|stroke| stroke.shape != Shape::Image && stroke.blocks.is_empty())
+        .find(|stroke| stroke.hit_test(input.cursor.x, input.cursor.y, tolerance))
+        .map(|stroke| stroke.color)
+}
+
+/// Remember an alt-press over a stroke with a drawing tool, which may be a click taking its
+/// color. The press still draws, alt being the ephemeral modifier of the brush by default
+fn start_color_pick(drawing: &mut DrawingState, input: &Input) {
+    let is_drawing_tool = matches!(drawing.tool, Tool::Brush | Tool::Arrow | Tool::HighlightBox);
+    drawing.color_pick = if input.modifiers.alt
+        && is_drawing_tool
+        && stroke_color_at_cursor(drawing, input).is_some()
+    {
+        Some(ColorPick {
+            x: input.cursor.x,
+            y: input.cursor.y,
+            strokes: drawing.strokes.clone(),
+            n_undo_steps: drawing.undo_steps.len(),
+        })
+    } else {
+        None
+    };
+}
+
+/// Make the color of the stroke under an alt-click the brush color when the cursor is released
+/// where it was pressed, dropping what the press drew. Alt-drags keep their line
+fn end_color_pick(drawing: &mut DrawingState, input: &Input) {
+    let pick = match drawing.color_pick.take() {
+        Some(pick) => pick,
+        None => return,
+    };
+    if (input.cursor.x - pick.x).hypot(input.cursor.y - pick.y) > COLOR_PICK_DISTANCE {
+        return;
+    }
+    drawing.strokes = pick.strokes;
+    drawing.undo_steps.truncate(pick.n_undo_steps);
+    drawing.strokes_changed = true;
+    drawing.n_points_current_line = 0;
+    drawing.is_ephemeral_line = false;
+    if let Some(color) = stroke_color_at_cursor(drawing, input) {
+        set_color(drawing, color);
+    }
+}
+
+/// Start moving strokes with the cursor until it's released
+fn start_stroke_drag(drawing: &mut DrawingState, input: &Input, indices: Vec<usize>) {
+    drawing.stroke_drag = Some(StrokeDrag {
+        indices,
+        x: input.cursor.x,
+        y: input.cursor.y,
+        is_moved: false,
+    });
+}
+
+/// Handle a click with the select tool
+///
+/// Clicking a line selects it and starts moving the selection, shift-click adds or removes it
+/// from the selection and clicking elsewhere starts a selection rectangle.
+fn start_selection(drawing: &mut DrawingState, input: &Input) {
+    match stroke_at_cursor(drawing, input) {
+        Some(index) if input.modifiers.shift => {
+            let grouped = with_groups(drawing, vec![index]);
+            if drawing.selection.contains(&index) {
+                drawing.selection.retain(|i| !grouped.contains(i));
+            } else {
+                drawing.selection.extend(grouped);
+            }
+        }
+        Some(index) => {
+            if !drawing.selection.contains(&index) {
+                drawing.selection = with_groups(drawing, vec![index]);
+            }
+            start_stroke_drag(drawing, input, drawing.selection.clone());
+        }
+        None => drawing.selection_marquee = Some((input.cursor.x, input.cursor.y)),
+    }
+}
+
+/// Add the other strokes of the groups the given strokes belong to
+fn with_groups(drawing: &DrawingState, mut indices: Vec<usize>) -> Vec<usize> {
+    let groups: Vec<u32> = indices
+        .iter()
+        .filter_map(|&index| drawing.strokes[index].group)
+        .collect();
+
+    for (index, stroke) in drawing.strokes.iter().enumerate() {
+        let is_grouped = stroke.group.is_some_and(|group| groups.contains(&group));
+        if is_grouped && !indices.contains(&index) {
+            indices.push(index);
+        }
+    }
+    indices
+}
+
+/// Group the selected strokes so they are selected, moved and erased as one
+fn group_selection(drawing: &mut DrawingState) {
+    if drawing.selection.len() < 2 {
+        return;
+    }
+
+    push_undo_step(drawing);
+
+    let group = drawing.next_group_id;
+    drawing.next_group_id += 1;
+
+    for &index in &drawing.selection {
+        Rc::make_mut(&mut drawing.strokes[index]).group = Some(group);
+    }
+}
+
+/// Split the groups of the selected strokes back into individual strokes
+fn ungroup_selection(drawing: &mut DrawingState) {
+    let is_grouped = drawing
+        .selection
+        .iter()
+        .any(|&index| drawing.strokes[index].group.is_some());
+    if !is_grouped {
+        return;
+    }
+
+    push_undo_step(drawing);
+
+    for &index in &drawing.selection {
+        Rc::make_mut(&mut drawing.strokes[index]).group = None;
+    }
+}
+
+/// Select the strokes with a point inside a rectangle, adding them to the selection if `extend`
+fn select_in_rect(drawing: &mut DrawingState, x1: f32, y1: f32, x2: f32, y2: f32, extend: bool) {
+    let (min_x, max_x) = (x1.min(x2), x1.max(x2));
+    let (min_y, max_y) = (y1.min(y2), y1.max(y2));
+
+    if !extend {
+        drawing.selection.clear();
+    }
+
+    for (index, stroke) in drawing.strokes.iter().enumerate() {
+        let is_inside = stroke
+            .points
+            .iter()
+            .any(|p| p.x >= min_x && p.x <= max_x && p.y >= min_y && p.y <= max_y);
+
+        if is_inside && !stroke.locked && !drawing.selection.contains(&index) {
+            drawing.selection.push(index);
+        }
+    }
+
+    let selection = mem::take(&mut drawing.selection);
+    drawing.selection = with_groups(drawing, selection);
+}
+
+/// Bounding box of all the selected strokes
+fn selection_bounds(drawing: &DrawingState) -> Option<Rect2D> {
+    drawing
+        .selection
+        .iter()
+        .map(|&index| drawing.strokes[index].bounds())
+        .reduce(|a, b| {
+            let min_x = a.x.min(b.x);
+            let min_y = a.y.min(b.y);
+            Rect2D {
+                x: min_x,
+                y: min_y,
+                width: (a.x + a.width).max(b.x + b.width) - min_x,
+                height: (a.y + a.height).max(b.y + b.height) - min_y,
+            }
+        })
+}
+
+/// Save an undo step for a scroll transform, unless it continues the previous one
+fn push_transform_undo_step(drawing: &mut DrawingState) {
+    let is_new_transform = drawing
+        .last_transform_time
+        .is_none_or(|time| time.elapsed() > TRANSFORM_UNDO_DELAY);
+
+    if is_new_transform {
+        push_undo_step(drawing);
+    }
+    drawing.last_transform_time = Some(Instant::now());
+}
+
+/// Scale the selection around its center
+fn scale_selection(drawing: &mut DrawingState, factor: f32) {
+    let bounds = match selection_bounds(drawing) {
+        Some(bounds) => bounds,
+        None => return,
+    };
+    let center_x = bounds.x + bounds.width / 2.0;
+    let center_y = bounds.y + bounds.height / 2.0;
+
+    push_transform_undo_step(drawing);
+
+    for &index in &drawing.selection {
+        let stroke = Rc::make_mut(&mut drawing.strokes[index]);
+        stroke.scale(
+            center_x,
+            center_y,
+            factor,
+            drawing.config.scale_stroke_width,
+        );
+        stroke.tessellate(&drawing.rect, drawing.lines.is_some());
+    }
+    drawing.strokes_changed = true;
+}
+
+/// Change the width of the selected lines, arrows and boxes, tessellated again from their
+/// centerline
+fn widen_selection(drawing: &mut DrawingState, amount: f32) {
+    let has_width = |stroke: &Stroke| {
+        matches!(
+            stroke.shape,
+            Shape::Freehand | Shape::Arrow { .. } | Shape::HighlightBox { .. }
+        )
+    };
+    if !drawing
+        .selection
+        .iter()
+        .any(|&index| has_width(&drawing.strokes[index]))
+    {
+        return;
+    }
+
+    push_transform_undo_step(drawing);
+
+    for &index in &drawing.selection {
+        if !has_width(&drawing.strokes[index]) {
+            continue;
+        }
+        let stroke = Rc::make_mut(&mut drawing.strokes[index]);
+        stroke.width = (stroke.width + amount).max(1.0);
+        stroke.tessellate(&drawing.rect, drawing.lines.is_some());
+    }
+    drawing.strokes_changed = true;
+}
+
+/// Rotate the selection around the centroid of its points
+///
+/// The centroid doesn't move when rotating, so successive rotations don't drift like they would
+/// around the center of the bounding box.
+fn rotate_selection(drawing: &mut DrawingState, angle: f32) {
+    let mut sum_x = 0.0;
+    let mut sum_y = 0.0;
+    let mut n_points = 0;
+    for &index in &drawing.selection {
+        for p in &drawing.strokes[index].points {
+            sum_x += p.x;
+            sum_y += p.y;
+            n_points += 1;
+        }
+    }
+    if n_points == 0 {
+        return;
+    }
+    let centroid_x = sum_x / n_points as f32;
+    let centroid_y = sum_y / n_points as f32;
+
+    push_transform_undo_step(drawing);
+
+    for &index in &drawing.selection {
+        let stroke = Rc::make_mut(&mut drawing.strokes[index]);
+        stroke.rotate(centroid_x, centroid_y, angle);
+        stroke.tessellate(&drawing.rect, drawing.lines.is_some());
+    }
+    drawing.strokes_changed = true;
+}
+
+/// Copy the selected strokes on top of the others, offset by `config.duplicate_offset`
+///
+/// The copies become the new selection so the duplication can be repeated.
+fn duplicate_selection(drawing: &mut DrawingState) {
+    if drawing.selection.is_empty() {
+        return;
+    }
+
+    push_undo_step(drawing);
+
+    let [dx, dy] = drawing.config.duplicate_offset;
+    let mut new_groups: Vec<(u32, u32)> = Vec::new();
+    let mut new_selection = Vec::with_capacity(drawing.selection.len());
+
+    for &index in &drawing.selection {
+        let mut stroke = (*drawing.strokes[index]).clone();
+        for point in stroke.points.iter_mut() {
+            point.x += dx;
+            point.y += dy;
+        }
+
+        // Copies of a group form a new group
+        if let Some(group) = stroke.group {
+            let new_group = match new_groups.iter().find(|(old, _)| *old == group) {
+                Some(&(_, new_group)) => new_group,
+                None => {
+                    let new_group = drawing.next_group_id;
+                    drawing.next_group_id += 1;
+                    new_groups.push((group, new_group));
+                    new_group
+                }
+            };
+            stroke.group = Some(new_group);
+        }
+
+        stroke.tessellate(&drawing.rect, drawing.lines.is_some());
+        new_selection.push(drawing.strokes.len());
+        drawing.strokes.push(Rc::new(stroke));
+    }
+
+    drawing.selection = new_selection;
+    drawing.strokes_changed = true;
+}
+
+/// Change the color of the selected strokes
+fn recolor_selection(drawing: &mut DrawingState, color: [f32; 3]) {
+    if drawing
+        .selection
+        .iter()
+        .all(|&index| drawing.strokes[index].color == color)
+    {
+        return;
+    }
+
+    push_undo_step(drawing);
+
+    for &index in &drawing.selection {
+        let stroke = Rc::make_mut(&mut drawing.strokes[index]);
+        stroke.color = color;
+        // The color is in each vertex
+        stroke.tessellate(&drawing.rect, drawing.lines.is_some());
+    }
+    drawing.strokes_changed = true;
+}
+
+/// Lock the selected strokes so they can't be erased, cleared or selected until unlocked
+fn lock_selection(drawing: &mut DrawingState) {
+    if drawing.selection.is_empty() {
+        return;
+    }
+
+    push_undo_step(drawing);
+
+    for &index in &drawing.selection {
+        Rc::make_mut(&mut drawing.strokes[index]).locked = true;
+    }
+    drawing.selection.clear();
+}
+
+/// Unlock every locked stroke
+fn unlock_all(drawing: &mut DrawingState) {
+    if !drawing.strokes.iter().any(|stroke| stroke.locked) {
+        return;
+    }
+
+    push_undo_step(drawing);
+
+    for stroke in drawing.strokes.iter_mut() {
+        if stroke.locked {
+            Rc::make_mut(stroke).locked = false;
+        }
+    }
+}
+
+/// Move the selected strokes above all the others, or below them when `to_back`
+fn reorder_selection(drawing: &mut DrawingState, to_back: bool) {
+    if drawing.selection.is_empty() {
+        return;
+    }
+
+    push_undo_step(drawing);
+
+    let selection = &drawing.selection;
+    let (selected, others): (Vec<_>, Vec<_>) = drawing
+        .strokes
+        .drain(..)
+        .enumerate()
+        .partition(|(index, _)| selection.contains(index));
+
+    let n_selected = selected.len();
+    let n_others = others.len();
+    let selected = selected.into_iter().map(|(_, stroke)| stroke);
+    let others = others.into_iter().map(|(_, stroke)| stroke);
+
+    // Relative order is kept inside both parts
+    if to_back {
+        drawing.strokes = selected.chain(others).collect();
+        drawing.selection = (0..n_selected).collect();
+    } else {
+        drawing.strokes = others.chain(selected).collect();
+        drawing.selection = (n_others..n_others + n_selected).collect();
+    }
+    drawing.strokes_changed = true;
+}
+
+/// Move the dragged strokes by the distance travelled since the last update
+fn drag_strokes(drawing: &mut DrawingState, input: &Input) {
+    let drag = match drawing.stroke_drag.as_mut() {
+        Some(drag) => drag,
+        None => return,
+    };
+
+    let dx = input.cursor.x - drag.x;
+    let dy = input.cursor.y - drag.y;
+    if dx == 0.0 && dy == 0.0 {
+        return;
+    }
+
+    drag.x = input.cursor.x;
+    drag.y = input.cursor.y;
+
+    // A single undo step for the whole move
+    if !drag.is_moved {
+        drag.is_moved = true;
+        drawing.undo_steps.push(drawing.strokes.clone());
+    }
+
+    for &index in &drag.indices {
+        let stroke = Rc::make_mut(&mut drawing.strokes[index]);
+        for point in stroke.points.iter_mut() {
+            point.x += dx;
+            point.y += dy;
+        }
+        stroke.tessellate(&drawing.rect, drawing.lines.is_some());
+    }
+    drawing.strokes_changed = true;
+}
+
+/// Both erasers share the eraser size and reticle
+fn is_eraser(tool: Tool) -> bool {
+    tool == Tool::StrokeEraser || tool == Tool::SegmentEraser
+}
+
+/// Erase the strokes under the eraser moving from the last to the current cursor position
+fn erase(drawing: &mut DrawingState, input: &Input) {
+    let radius = drawing.eraser_size;
+
+    // Sample the cursor movement so fast gestures don't skip over thin strokes
+    let dx = input.cursor.x - input.cursor.last_x;
+    let dy = input.cursor.y - input.cursor.last_y;
+    let n_samples = ((dx * dx + dy * dy).sqrt() / radius).ceil().max(1.0) as usize;
+    let samples: Vec<(f32, f32)> = (0..=n_samples)
+        .map(|i| {
+            let t = i as f32 / n_samples as f32;
+            (input.cursor.last_x + dx * t, input.cursor.last_y + dy * t)
+        })
+        .collect();
+
+    let mut is_touched = false;
+    let mut erased_groups = Vec::new();
+    let mut strokes = Vec::with_capacity(drawing.strokes.len());
+
+    for stroke in &drawing.strokes {
+        if stroke.locked {
+            strokes.push(stroke.clone());
+            continue;
+        }
+
+        match drawing.tool {
+            Tool::StrokeEraser => {
+                if samples.iter().any(|&(x, y)| stroke.hit_test(x, y, radius)) {
+                    is_touched = true;
+                    erased_groups.extend(stroke.group);
+                    continue;
+                }
+            }
+            Tool::SegmentEraser => {
+                let mut pieces: Option<Vec<Stroke>> = None;
+                for &(x, y) in &samples {
+                    pieces = match pieces.take() {
+                        None => stroke.erase_circle(x, y, radius),
+                        Some(pieces) => Some(
+                            pieces
+                                .into_iter()
+                                .flat_map(|piece| {
+                                    piece
+                                        .erase_circle(x, y, radius)
+                                        .unwrap_or_else(|| vec![piece])
+                                })
+                                .collect(),
+                        ),
+                    };
+                }
+
+                if let Some(pieces) = pieces {
+                    is_touched = true;
+                    for mut piece in pieces {
+                        piece.tessellate(&drawing.rect, drawing.lines.is_some());
+                        strokes.push(Rc::new(piece));
+                    }
+                    continue;
+                }
+            }
+            Tool::Brush
+            | Tool::Select
+            | Tool::Arrow
+            | Tool::Screenshot
+            | Tool::Redaction
+            | Tool::HighlightBox
+            | Tool::Text => (),
+        }
+        strokes.push(stroke.clone());
+    }
+
+    if !is_touched {
+        return;
+    }
+
+    // Grouped strokes are erased together
+    if !erased_groups.is_empty() {
+        strokes.retain(|stroke| {
+            stroke.locked
+                || stroke
+                    .group
+                    .is_none_or(|group| !erased_groups.contains(&group))
+        });
+    }
+
+    // A single undo step for the whole erasing gesture
+    if !drawing.is_erasing {
+        drawing.is_erasing = true;
+        push_undo_step(drawing);
+    }
+
+    drawing.strokes = strokes;
+    drawing.strokes_changed = true;
+}
+
+/// Let the clicks go to the windows below the overlay, or catch them again
+fn set_passing_through(drawing: &mut DrawingState, input: &Input, is_passing_through: bool) {
+    end_line(drawing, input);
+    drawing.is_passing_through = is_passing_through;
+    update_click_through(drawing);
+}
+
+/// Switch between drawing and interacting with the windows below
+fn set_interacting(drawing: &mut DrawingState, input: &Input, is_interacting: bool) {
+    end_line(drawing, input);
+    if drawing.is_interacting && !is_interacting {
+        // Coming back counts as activity, the overlay isn't idle again right away
+        drawing.last_activity = Instant::now();
+    }
+    drawing.is_interacting = is_interacting;
+    drawing.need_redraw = true;
+    update_click_through(drawing);
+}
+
+fn update_click_through(drawing: &DrawingState) {
+    passthrough::set_click_through(
+        drawing.gl_context.window_context.window(),
+        drawing.is_passing_through || drawing.is_interacting,
+    );
+}
+
+/// Get the overlay out of the way, keeping its drawing for the next time it's shown
+fn hide_window(drawing: &DrawingState) {
+    let window = drawing.gl_context.window_context.window();
+    if drawing.config.hide_from_taskbar {
+        // A minimized window out of the taskbar would be left in a corner of the screen
+        window.set_visible(false);
+    } else {
+        window.set_minimized(true);
+    }
+}
+
+fn show_window(drawing: &mut DrawingState) {
+    let window = drawing.gl_context.window_context.window();
+    window.set_visible(true);
+    window.set_minimized(false);
+    drawing.need_redraw = true;
+}
+
+fn quit(drawing: &DrawingState, control_flow: &mut ControlFlow) {
+    unsafe {
+        gl::DeleteProgram(drawing.gl_context.program);
+        gl::DeleteShader(drawing.gl_context.fs);
+        gl::DeleteShader(drawing.gl_context.vs);
+        gl::DeleteBuffers(1, &drawing.gl_context.vbo);
+        gl::DeleteVertexArrays(1, &drawing.gl_context.vao);
+    }
+    drawing.text.delete();
+    drawing.images.delete();
+    drawing.patterns.delete();
+    if let Some(lines) = &drawing.lines {
+        lines.delete();
+    }
+    log::info!("{}", session_report(&drawing.session_stats).join(", "));
+    save_state(drawing);
+    *control_flow = ControlFlow::Exit
+}
+
+/// Clear drawings, except the locked ones
+fn clear(drawing: &mut DrawingState) {
+    end_text_edit(drawing);
+    drawing.need_redraw = true;
+    drawing.strokes.retain(|stroke| stroke.locked);
+    drawing.strokes_changed = true;
+    drawing.undo_steps.clear();
+    drawing.ink_clear_time = None;
+    drawing.ephemeral_strokes.clear();
+    drawing
+        .session_stats
+        .drawn_boards
+        .remove(&drawing.workspace);
+    drawing.n_points_current_line = 0;
+    drawing.stroke_drag = None;
+    drawing.selection.clear();
+}
+
+fn toggle_background(drawing: &mut DrawingState) {
+    drawing.need_redraw = true;
+    drawing.is_background_visible = !drawing.is_background_visible;
+}
+
+/// Show the grid with the first pattern of the config, go to the next pattern, or hide the grid
+/// after the last one
+fn cycle_grid(drawing: &mut DrawingState) {
+    drawing.need_redraw = true;
+    if !drawing.is_grid_visible {
+        drawing.is_grid_visible = true;
+        drawing.grid_pattern_index = 0;
+    } else if drawing.grid_pattern_index + 1 < drawing.config.grid_patterns.len() {
+        drawing.grid_pattern_index += 1;
+    } else {
+        drawing.is_grid_visible = false;
+        drawing.grid_pattern_index = 0;
+    }
+}
+
+/// Pattern of the visible grid, lines when the config has none
+fn grid_pattern(drawing: &DrawingState) -> Option<patterns::GridPattern> {
+    if !drawing.is_grid_visible {
+        return None;
+    }
+    let pattern = drawing.config.grid_patterns.get(drawing.grid_pattern_index);
+    Some(pattern.copied().unwrap_or(patterns::GridPattern::Lines))
+}
+
+/// Show the rectangles of the guide template file of the config, read again each time so
+/// changes to it show, or hide them
+fn toggle_guide_template(drawing: &mut DrawingState) {
+    drawing.need_redraw = true;
+    if drawing.guide_template.take().is_some() {
+        return;
+    }
+    let path = match &drawing.config.guide_template {
+        Some(path) => path,
+        None => {
+            log::warn!("No guide_template file in the config");
+            return;
+        }
+    };
+    match guides::load(path) {
+        Ok(rects) => drawing.guide_template = Some(rects),
+        Err(e) => log::error!("Failed to load the guide template {}: {}", path, e),
+    }
+}
+
+/// Closest end of another line (in screen pixels) within snapping distance of a point
+///
+/// The line being drawn is skipped, so it doesn't snap to itself.
+fn nearest_endpoint(drawing: &DrawingState, x: f32, y: f32) -> Option<(f32, f32)> {
+    if !drawing.config.snap_to_endpoints {
+        return None;
+    }
+    let n_strokes = if drawing.n_points_current_line > 0 {
+        drawing.strokes.len().saturating_sub(1)
+    } else {
+        drawing.strokes.len()
+    };
+
+    drawing.strokes[..n_strokes]
+        .iter()
+        .flat_map(|stroke| stroke.endpoints())
+        .map(|(end_x, end_y)| ((end_x, end_y), (end_x - x).hypot(end_y - y)))
+        .filter(|&(_, distance)| distance <= ENDPOINT_SNAP_DISTANCE)
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(end, _)| end)
+}
+
+/// Move a point (in screen pixels) to the end of a nearby line, or else to the grid
+fn snap_point(drawing: &DrawingState, x: f32, y: f32) -> (f32, f32) {
+    nearest_endpoint(drawing, x, y).unwrap_or_else(|| snap_to_grid(grid_snap_size(drawing), x, y))
+}
+
+/// Spacing of the grid points are snapped to, None when snapping is disabled or the pattern
+/// has no square intersections
+fn grid_snap_size(drawing: &DrawingState) -> Option<f32> {
+    let size = drawing.config.grid_size;
+    let is_square = matches!(
+        grid_pattern(drawing),
+        Some(patterns::GridPattern::Lines | patterns::GridPattern::Dots)
+    );
+    if is_square && drawing.config.snap_to_grid && size >= 1.0 {
+        Some(size)
+    } else {
+        None
+    }
+}
+
+/// Move a point (in screen pixels) to the closest grid intersection
+fn snap_to_grid(grid_size: Option<f32>, x: f32, y: f32) -> (f32, f32) {
+    match grid_size {
+        Some(size) => ((x / size).round() * size, (y / size).round() * size),
+        None => (x, y),
+    }
+}
+
+/// Undo the last step (if any undo steps are available)
+fn undo(drawing: &mut DrawingState) {
+    // The text being typed may not be there anymore
+    end_text_edit(drawing);
+    if let Some(strokes) = drawing.undo_steps.pop() {
+        drawing.session_stats.undos += 1;
+        drawing.strokes = strokes;
+        drawing.strokes_changed = true;
+        drawing.need_redraw = true;
+        drawing.n_points_current_line = 0;
+        drawing.stroke_drag = None;
+        drawing.selection.clear();
+    }
+}
+
+/// Undo again while ctrl-z is held, each repeat coming sooner than the last
+fn repeat_undo(drawing: &mut DrawingState, input: &mut Input, control_flow: &mut ControlFlow) {
+    let repeat = match input.undo_repeat.as_mut() {
+        Some(repeat) => repeat,
+        None => return,
+    };
+
+    let now = Instant::now();
+    if now >= repeat.next_time {
+        undo(drawing);
+        repeat.count += 1;
+        let interval = (UNDO_REPEAT_DELAY / (repeat.count + 1)).max(UNDO_REPEAT_MIN_INTERVAL);
+        repeat.next_time = now + interval;
+    }
+
+    if drawing.undo_steps.is_empty() {
+        input.undo_repeat = None;
+    } else if *control_flow == ControlFlow::Wait {
+        *control_flow = ControlFlow::WaitUntil(repeat.next_time);
+    }
+}
+
+/// Make the event loop wake up at a time, unless it already wakes up earlier
+fn wake_up_at(control_flow: &mut ControlFlow, time: Instant) {
+    match *control_flow {
+        ControlFlow::WaitUntil(other) if other <= time => (),
+        ControlFlow::Wait | ControlFlow::WaitUntil(_) => {
+            *control_flow = ControlFlow::WaitUntil(time);
+        }
+        _ => (),
+    }
+}
+
+/// Host or join the shared whiteboard of the config
+fn start_session(
+    config: &Config,
+    proxy: EventLoopProxy<instance::Command>,
+) -> Option<session::Session> {
+    let token = match &config.session_token {
+        Some(token) if !token.is_empty() => token.clone(),
+        _ => {
+            if config.session_port.is_some() || config.session_join.is_some() {
+                log::warn!("Shared sessions need a session_token in the config");
+            }
+            return None;
+        }
+    };
+    if let Some(address) = &config.session_join {
+        session::join(address, token, config.session_mirror, proxy)
+    } else if let Some(port) = config.session_port {
+        session::host(port, token, proxy)
+    } else {
+        None
+    }
+}
+
+/// Whether this instance only shows the strokes of a session, without drawing
+fn is_mirroring(drawing: &DrawingState) -> bool {
+    drawing.config.session_mirror && drawing.session.is_some()
+}
+
+/// Send the strokes to the other peers of the session and to the viewer page when they changed
+///
+/// The line being drawn is sent a few times per second, the others see it grow.
+fn share_strokes(drawing: &mut DrawingState, control_flow: &mut ControlFlow) {
+    if drawing.session.is_none() && drawing.viewer.is_none() {
+        return;
+    }
+    let is_unchanged = drawing.strokes.len() == drawing.shared_strokes.len()
+        && drawing
+            .strokes
+            .iter()
+            .zip(&drawing.shared_strokes)
+            .all(|(stroke, shared)| Rc::ptr_eq(stroke, shared));
+    if !is_unchanged {
+        let next_share_time = drawing.last_share_time + SESSION_SHARE_INTERVAL;
+        if drawing.n_points_current_line > 0 && Instant::now() < next_share_time {
+            wake_up_at(control_flow, next_share_time);
+            return;
+        }
+        if let Some(session) = drawing.session.as_ref().filter(|_| !is_mirroring(drawing)) {
+            session.send_strokes(
+                drawing
+                    .strokes
+                    .iter()
+                    .map(|stroke| interchange::StrokeData::from(stroke.as_ref()))
+                    .collect(),
+            );
+        }
+        drawing.shared_strokes = drawing.strokes.clone();
+        drawing.last_share_time = Instant::now();
+        drawing.is_viewer_outdated = true;
+    }
+
+    if let (Some(viewer), true) = (&drawing.viewer, drawing.is_viewer_outdated) {
+        // The viewer shows the strokes of the session too, over the local ones as here
+        let strokes = drawing
+            .strokes
+            .iter()
+            .map(|stroke| stroke.as_ref())
+            .chain(drawing.remote_strokes.values().flatten());
+        viewer.send_board(interchange::export_json(
+            strokes,
+            drawing.rect.width,
+            drawing.rect.height,
+        ));
+        drawing.is_viewer_outdated = false;
+    }
+}
+
+fn handle_session_message(drawing: &mut DrawingState, message: session::Message) {
+    match message {
+        session::Message::Welcome { .. } if is_mirroring(drawing) => {
+            log::info!("Mirroring the session");
+        }
+        session::Message::Welcome { peer } => {
+            // Each peer starts with a color of its own
+            let colors = &drawing.config.brush_colors;
+            if !colors.is_empty() {
+                let color = color_to_gl(colors[peer as usize % colors.len()]);
+                set_color(drawing, color);
+            }
+            log::info!("Joined the session as peer {}", peer);
+        }
+        session::Message::Strokes { peer, strokes } => {
+            let strokes = strokes
+                .into_iter()
+                .map(|stroke| {
+                    let mut stroke = Stroke::from(stroke);
+                    stroke.tessellate(&drawing.rect, drawing.lines.is_some());
+                    stroke
+                })
+                .collect();
+            drawing.remote_strokes.insert(peer, strokes);
+        }
+        session::Message::Leave { peer } => {
+            drawing.remote_strokes.remove(&peer);
+        }
+        session::Message::Join { .. } => (),
+    }
+    drawing.is_viewer_outdated = true;
+    drawing.strokes_changed = true;
+    drawing.need_redraw = true;
+}
+
+/// Fade out the released ephemeral lines once their delay is over, and forget the faded ones
+fn fade_ephemeral_strokes(drawing: &mut DrawingState, control_flow: &mut ControlFlow) {
+    let now = Instant::now();
+    let n_strokes = drawing.ephemeral_strokes.len();
+    drawing
+        .ephemeral_strokes
+        .retain(|(_, fade_time)| now < *fade_time + EPHEMERAL_FADE);
+    if drawing.ephemeral_strokes.len() != n_strokes {
+        drawing.strokes_changed = true;
+        drawing.need_redraw = true;
+    }
+
+    let next_fade = drawing
+        .ephemeral_strokes
+        .iter()
+        .map(|&(_, time)| time)
+        .min();
+    match next_fade {
+        Some(fade_time) if now >= fade_time => {
+            drawing.strokes_changed = true;
+            drawing.need_redraw = true;
+            wake_up_at(control_flow, now + FRAME_INTERVAL);
+        }
+        Some(fade_time) => wake_up_at(control_flow, fade_time),
+        None => (),
+    }
+}
+
+/// Keep drawing frames while custom shaders animate the strokes with their time
+fn animate_shaders(drawing: &mut DrawingState, control_flow: &mut ControlFlow) {
+    if drawing.gl_context.time_uniform == -1
+        || drawing.is_window_hidden
+        || drawing.strokes.is_empty()
+    {
+        return;
+    }
+    drawing.need_redraw = true;
+    wake_up_at(control_flow, Instant::now() + FRAME_INTERVAL);
+}
+
+/// Time left before the drawing is cleared for inactivity or after push-to-draw, None when it
+/// won't be
+fn auto_clear_remaining(drawing: &DrawingState) -> Option<Duration> {
+    let delay = drawing.config.auto_clear_delay_s;
+    let has_unlocked = drawing.strokes.iter().any(|stroke| !stroke.locked);
+    if !has_unlocked || drawing.text_edit.is_some() {
+        return None;
+    }
+    let idle_clear_time = if delay == 0 {
+        None
+    } else {
+        Some(drawing.last_activity + Duration::from_secs(delay))
+    };
+    let clear_time = idle_clear_time
+        .into_iter()
+        .chain(drawing.ink_clear_time)
+        .min()?;
+    Some(clear_time.saturating_duration_since(Instant::now()))
+}
+
+/// Opacity of the lines fading out before being cleared for inactivity
+fn auto_clear_fade(drawing: &DrawingState) -> f32 {
+    match auto_clear_remaining(drawing) {
+        Some(remaining) if remaining < AUTO_CLEAR_FADE => {
+            remaining.as_secs_f32() / AUTO_CLEAR_FADE.as_secs_f32()
+        }
+        _ => 1.0,
+    }
+}
+
+/// Clear the drawing after a while without input, fading it out first
+fn auto_clear(drawing: &mut DrawingState, input: &Input, control_flow: &mut ControlFlow) {
+    // Holding a line counts as activity
+    if input.cursor.pressed {
+        drawing.last_activity = Instant::now();
+    }
+
+    let remaining = match auto_clear_remaining(drawing) {
+        Some(remaining) => remaining,
+        None => return,
+    };
+    let now = Instant::now();
+    if remaining.is_zero() {
+        clear(drawing);
+    } else if remaining <= AUTO_CLEAR_FADE {
+        drawing.strokes_changed = true;
+        drawing.need_redraw = true;
+        wake_up_at(control_flow, now + FRAME_INTERVAL.min(remaining));
+    } else {
+        wake_up_at(control_flow, now + remaining - AUTO_CLEAR_FADE);
+    }
+}
+
+/// Show the overlay and catch the input while the push-to-draw key is held
+fn start_push_to_draw(drawing: &mut DrawingState, input: &Input) {
+    if drawing.is_pushing_to_draw {
+        return;
+    }
+    drawing.is_pushing_to_draw = true;
+    // Ink still fading out from the last time comes back
+    drawing.ink_clear_time = None;
+    drawing.strokes_changed = true;
+    set_interacting(drawing, input, false);
+    show_window(drawing);
+}
+
+/// Let the clicks go to the windows below again once the push-to-draw key is released, or hide
+/// the overlay where they can't, fading the ink out if configured
+fn end_push_to_draw(drawing: &mut DrawingState, input: &Input) {
+    if !drawing.is_pushing_to_draw {
+        return;
+    }
+    drawing.is_pushing_to_draw = false;
+    end_line(drawing, input);
+    if drawing.config.push_to_draw_ink == PushToDrawInk::Fade {
+        drawing.ink_clear_time = Some(Instant::now() + AUTO_CLEAR_FADE);
+        drawing.need_redraw = true;
+    }
+    if cfg!(any(target_os = "windows", target_os = "macos")) {
+        set_interacting(drawing, input, true);
+    } else {
+        hide_window(drawing);
+    }
+}
+
+/// Get the overlay out of the way after a while without input, so a forgotten Inke doesn't
+/// block the windows below: the clicks go through the ink, or the overlay hides where they
+/// can't. The mode toggle key or activating Inke brings it back
+fn auto_hide(drawing: &mut DrawingState, input: &Input, control_flow: &mut ControlFlow) {
+    let delay = drawing.config.idle_hide_delay_s;
+    if delay == 0
+        || drawing.is_interacting
+        || !input.has_focus
+        || input.cursor.pressed
+        || drawing.text_edit.is_some()
+    {
+        return;
+    }
+
+    let hide_time = drawing.last_activity + Duration::from_secs(delay);
+    if Instant::now() < hide_time {
+        wake_up_at(control_flow, hide_time);
+    } else if cfg!(any(target_os = "windows", target_os = "macos")) {
+        log::info!(
+            "Idle for {} s, the clicks now go to the windows below",
+            delay
+        );
+        set_interacting(drawing, input, true);
+    } else {
+        log::info!("Idle for {} s, hiding the overlay", delay);
+        end_line(drawing, input);
+        hide_window(drawing);
+    }
+}
+
+/// Start the work and break timer with a work phase, or stop it
+fn toggle_timer(drawing: &mut DrawingState) {
+    drawing.need_redraw = true;
+    if drawing.timer.take().is_some() {
+        return;
+    }
+    let now = Instant::now();
+    drawing.timer = Some(Timer {
+        phase: TimerPhase::Work,
+        phase_end: now + Duration::from_secs(drawing.config.timer_work_min * 60),
+        next_redraw: now,
+    });
+}
+
+/// Switch the timer phase when its time is up, flashing the background, and wake up in time
+/// for the next change of the remaining time shown
+fn update_timer(drawing: &mut DrawingState, control_flow: &mut ControlFlow) {
+    let now = Instant::now();
+
+    if let Some(flash_end) = drawing.flash_end {
+        if now >= flash_end {
+            drawing.flash_end = None;
+            drawing.need_redraw = true;
+        } else {
+            wake_up_at(control_flow, flash_end);
+        }
+    }
+
+    let timer = match &mut drawing.timer {
+        Some(timer) => timer,
+        None => return,
+    };
+    if now >= timer.phase_end {
+        let (phase, minutes) = match timer.phase {
+            TimerPhase::Work => (TimerPhase::Break, drawing.config.timer_break_min),
+            TimerPhase::Break => (TimerPhase::Work, drawing.config.timer_work_min),
+        };
+        timer.phase = phase;
+        timer.phase_end = now + Duration::from_secs(minutes * 60);
+        timer.next_redraw = now;
+        drawing.flash_end = Some(now + TIMER_FLASH);
+        wake_up_at(control_flow, now + TIMER_FLASH);
+    }
+    if now >= timer.next_redraw {
+        drawing.need_redraw = true;
+        let remaining = timer.phase_end - now;
+        let to_next_second = Duration::from_nanos(u64::from(remaining.subsec_nanos()));
+        timer.next_redraw = if to_next_second.is_zero() {
+            now + Duration::from_secs(1)
+        } else {
+            now + to_next_second
+        };
+    }
+    wake_up_at(control_flow, timer.next_redraw.min(timer.phase_end));
+}
+
+/// Time left in a timer phase, as minutes and seconds rounded up
+fn format_timer(remaining: Duration) -> String {
+    let seconds = remaining.as_secs() + u64::from(remaining.subsec_nanos() > 0);
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+/// Start the stopwatch, or pause and resume it
+fn toggle_stopwatch(drawing: &mut DrawingState) {
+    drawing.need_redraw = true;
+    let now = Instant::now();
+    match &mut drawing.stopwatch {
+        Some(stopwatch) => match stopwatch.start.take() {
+            Some(start) => stopwatch.elapsed += now - start,
+            None => {
+                stopwatch.start = Some(now);
+                stopwatch.next_redraw = now;
+            }
+        },
+        None => {
+            drawing.stopwatch = Some(Stopwatch {
+                elapsed: Duration::from_secs(0),
+                start: Some(now),
+                next_redraw: now,
+            })
+        }
+    }
+}
+
+/// Stop the stopwatch and hide it
+fn reset_stopwatch(drawing: &mut DrawingState) {
+    drawing.need_redraw = true;
+    drawing.stopwatch = None;
+}
+
+/// Time counted by the stopwatch, including the time since it was last resumed
+fn stopwatch_elapsed(stopwatch: &Stopwatch) -> Duration {
+    match stopwatch.start {
+        Some(start) => stopwatch.elapsed + start.elapsed(),
+        None => stopwatch.elapsed,
+    }
+}
+
+/// Count a line or shape that was drawn, and the page it was drawn on
+fn count_stroke(drawing: &mut DrawingState) {
+    let stats = &mut drawing.session_stats;
+    stats.strokes += 1;
+    if let Some(start) = stats.line_start.take() {
+        stats.drawing_time += start.elapsed();
+    }
+    if stats.drawn_boards.insert(drawing.workspace.clone()) {
+        stats.pages += 1;
+    }
+}
+
+/// Session length, strokes, undos, drawing time and pages, one per line
+fn session_report(stats: &SessionStats) -> Vec<String> {
+    let minutes = |duration: Duration| {
+        let seconds = duration.as_secs();
+        format!("{}:{:02}", seconds / 60, seconds % 60)
+    };
+    vec![
+        format!("Session {}", minutes(stats.start.elapsed())),
+        format!("{} strokes, {} undos", stats.strokes, stats.undos),
+        format!("Drawing {}", minutes(stats.drawing_time)),
+        format!("{} pages", stats.pages),
+    ]
+}
+
+/// Redraw the session report each second while it is shown, for its session length
+fn update_session_report(drawing: &mut DrawingState, control_flow: &mut ControlFlow) {
+    if !drawing.is_session_report_visible {
+        return;
+    }
+    let stats = &mut drawing.session_stats;
+    let now = Instant::now();
+    if now >= stats.next_redraw {
+        drawing.need_redraw = true;
+        let subsec = stats.start.elapsed().subsec_nanos();
+        stats.next_redraw = now + Duration::from_nanos(u64::from(1_000_000_000 - subsec));
+    }
+    wake_up_at(control_flow, stats.next_redraw);
+}
+
+/// Redraw a running stopwatch each time the seconds shown change
+fn update_stopwatch(drawing: &mut DrawingState, control_flow: &mut ControlFlow) {
+    let stopwatch = match &mut drawing.stopwatch {
+        Some(stopwatch) if stopwatch.start.is_some() => stopwatch,
+        _ => return,
+    };
+    let now = Instant::now();
+    if now >= stopwatch.next_redraw {
+        drawing.need_redraw = true;
+        let subsec = stopwatch_elapsed(stopwatch).subsec_nanos();
+        stopwatch.next_redraw = now + Duration::from_nanos(u64::from(1_000_000_000 - subsec));
+    }
+    wake_up_at(control_flow, stopwatch.next_redraw);
+}
+
+/// Add to the value of a counter of the config, ignored if there is no such counter
+fn add_to_counter(drawing: &mut DrawingState, index: usize, amount: i64) {
+    if let Some(value) = drawing.counter_values.get_mut(index) {
+        *value += amount;
+        drawing.need_redraw = true;
+    }
+}
+
+/// Change the brush size or hardness continuously with the knobs of a MIDI controller
+fn handle_midi_control(drawing: &mut DrawingState, control: u8, value: u8) {
+    let amount = f32::from(value) / 127.0;
+    if drawing.config.midi_size_control == Some(control) {
+        // From 1 pixel to the largest size of the config
+        let max_size = drawing
+            .config
+            .brush_sizes
+            .iter()
+            .copied()
+            .fold(1.0, f32::max);
+        drawing.line_style.width = 1.0 + (max_size - 1.0) * amount;
+        drawing.need_redraw = true;
+    }
+    if drawing.config.midi_hardness_control == Some(control) {
+        drawing.line_style.hardness = amount;
+        drawing.need_redraw = true;
+    }
+}
+
+/// Put every counter back to 0
+fn reset_counters(drawing: &mut DrawingState) {
+    drawing.counter_values = vec![0; drawing.config.counters.len()];
+    drawing.need_redraw = true;
+}
+
+/// Show a label in the top right corner of the overlay, on a row below the previous ones
+fn push_corner_label(drawing: &mut DrawingState, label: &str, row: usize) {
+    let width = drawing.text.text_width(label, TIMER_TEXT_SIZE);
+    let height = drawing.text.line_height(TIMER_TEXT_SIZE) + CORNER_LABEL_SPACING;
+    let x = drawing.rect.width - TIMER_TEXT_MARGIN - width;
+    let y = TIMER_TEXT_MARGIN + row as f32 * height;
+
+    for &(offset, color) in [(1.0, CROSSHAIR_SHADOW_COLOR), (0.0, CROSSHAIR_COLOR)].iter() {
+        drawing.text.push_text(
+            &mut drawing.hud_text_vertices,
+            label,
+            x + offset,
+            y + offset,
+            TIMER_TEXT_SIZE,
+            [color[0], color[1], color[2], 1.0],
+            &drawing.rect,
+        );
+    }
+}
+
+/// Show the timer phase and its remaining time, the stopwatch, then the counters, in the top
+/// right corner
+fn push_timers(drawing: &mut DrawingState) {
+    let mut row = 0;
+    if let Some(timer) = &drawing.timer {
+        let phase = match timer.phase {
+            TimerPhase::Work => "Work",
+            TimerPhase::Break => "Break",
+        };
+        let remaining = timer.phase_end.saturating_duration_since(Instant::now());
+        let label = format!("{} {}", phase, format_timer(remaining));
+        push_corner_label(drawing, &label, row);
+        row += 1;
+    }
+    if let Some(stopwatch) = &drawing.stopwatch {
+        let elapsed = stopwatch_elapsed(stopwatch).as_secs();
+        let label = format!("{}:{:02}", elapsed / 60, elapsed % 60);
+        push_corner_label(drawing, &label, row);
+        row += 1;
+    }
+    for i in 0..drawing.counter_values.len() {
+        let label = format!(
+            "{} {}",
+            drawing.config.counters[i], drawing.counter_values[i]
+        );
+        push_corner_label(drawing, &label, row);
+        row += 1;
+    }
+}
+
+/// Put the window back over the whole overlay area if it was moved or resized
+fn restore_overlay_geometry(drawing: &DrawingState) {
+    let window = drawing.gl_context.window_context.window();
+    let rect = &drawing.rect;
+
+    let size = window.inner_size();
+    if size.width as f32 != rect.width || size.height as f32 != rect.height {
+        window.set_inner_size(PhysicalSize::new(rect.width, rect.height));
+    }
+    if let Ok(position) = window.outer_position() {
+        if position.x as f32 != rect.x || position.y as f32 != rect.y {
+            window.set_outer_position(PhysicalPosition::new(rect.x, rect.y));
+        }
+    }
+}
+
+/// Swap to the drawing of the current virtual desktop if it changed since the last time
+fn switch_workspace(drawing: &mut DrawingState, input: &Input) {
+    let workspace = match workspace::current() {
+        Some(workspace) => workspace,
+        None => return,
+    };
+
+    // The first desktop Inke is shown on keeps the drawing
+    let previous = match drawing.workspace.replace(workspace.clone()) {
+        Some(previous) if previous != workspace => previous,
+        _ => return,
+    };
+
+    end_line(drawing, input);
+    end_text_edit(drawing);
+    let board = drawing.boards.remove(&workspace).unwrap_or_default();
+    drawing.boards.insert(
+        previous,
+        Board {
+            strokes: mem::replace(&mut drawing.strokes, board.strokes),
+            undo_steps: mem::replace(&mut drawing.undo_steps, board.undo_steps),
+        },
+    );
+    drawing.stroke_drag = None;
+    drawing.selection.clear();
+    drawing.strokes_changed = true;
+    drawing.need_redraw = true;
+}
+
+/// Update the corner of a text annotation after its content or size changed
+fn layout_text(renderer: &text::TextRenderer, stroke: &mut Stroke) {
+    let size = match stroke.shape {
+        Shape::Text { size } => size,
+        _ => return,
+    };
+    let (x, y) = match stroke.points.first() {
+        Some(p) => (p.x, p.y),
+        None => return,
+    };
+
+    let width = stroke
+        .text
+        .split('\n')
+        .map(|line| renderer.text_width(line, size))
+        .fold(0.0, f32::max);
+    let height = renderer.line_height(size) * stroke.text.split('\n').count() as f32;
+    stroke.points.truncate(1);
+    stroke.points.push(StrokePoint {
+        x: x + width,
+        y: y + height,
+        pressure: 1.0,
+        time: 0.0,
+    });
+}
+
+/// Byte index of a position in characters in a text
+fn byte_index(text: &str, caret: usize) -> usize {
+    text.char_indices()
+        .nth(caret)
+        .map_or(text.len(), |(i, _)| i)
+}
+
+/// Line and column of a position in characters in a text
+fn caret_line(text: &str, caret: usize) -> (usize, usize) {
+    let before = &text[..byte_index(text, caret)];
+    let line = before.matches('\n').count();
+    let column = before
+        .rsplit('\n')
+        .next()
+        .map_or(0, |line| line.chars().count());
+    (line, column)
+}
+
+/// Closest position in characters to a point (in screen pixels) in a text annotation
+fn caret_at(renderer: &text::TextRenderer, stroke: &Stroke, x: f32, y: f32) -> usize {
+    let (size, origin) = match (stroke.shape, stroke.points.first()) {
+        (Shape::Text { size }, Some(&origin)) => (size, origin),
+        _ => return 0,
+    };
+
+    let lines: Vec<&str> = stroke.text.split('\n').collect();
+    let line = ((y - origin.y) / renderer.line_height(size)).max(0.0) as usize;
+    let line = line.min(lines.len() - 1);
+
+    let chars: Vec<char> = lines[line].chars().collect();
+    let distance = |column: usize| {
+        let prefix: String = chars[..column].iter().collect();
+        (origin.x + renderer.text_width(&prefix, size) - x).abs()
+    };
+    let column = (0..=chars.len())
+        .min_by(|&a, &b| distance(a).total_cmp(&distance(b)))
+        .unwrap_or(0);
+
+    let line_start: usize = lines[..line].iter().map(|l| l.chars().count() + 1).sum();
+    line_start + column
+}
+
+/// Topmost text annotation under a point (in screen pixels)
+fn text_at(drawing: &DrawingState, x: f32, y: f32) -> Option<usize> {
+    drawing.strokes.iter().rposition(|stroke| {
+        !stroke.locked && matches!(stroke.shape, Shape::Text { .. }) && stroke.hit_test(x, y, 2.0)
+    })
+}
+
+/// Place a new empty text annotation at the cursor and start typing in it
+fn new_text(drawing: &mut DrawingState, input: &Input) {
+    push_undo_step(drawing);
+
+    // The middle of the first line is on the cursor
+    let size = drawing.text_size;
+    let mut stroke = Stroke {
+        points: vec![StrokePoint {
+            x: input.cursor.x,
+            y: input.cursor.y - drawing.text.line_height(size) / 2.0,
+            pressure: 1.0,
+            time: 0.0,
+        }],
+        color: drawing.line_style.color,
+        hardness: 1.0,
+        shape: Shape::Text { size },
+        ..Default::default()
+    };
+    layout_text(&drawing.text, &mut stroke);
+    drawing.strokes.push(Rc::new(stroke));
+
+    drawing.selection.clear();
+    drawing.text_edit = Some(TextEdit {
+        index: drawing.strokes.len() - 1,
+        caret: 0,
+        is_new: true,
+    });
+    drawing.strokes_changed = true;
+    drawing.need_redraw = true;
+}
+
+/// Play the steps of a macro of the config, its strokes are undone together
+fn play_macro(drawing: &mut DrawingState, input: &Input, index: usize) {
+    end_line(drawing, input);
+    end_text_edit(drawing);
+    drawing.selection.clear();
+
+    let steps = drawing.config.macros[index].steps.clone();
+    let (offset_x, offset_y) = if drawing.config.macros[index].at_cursor {
+        (input.cursor.x, input.cursor.y)
+    } else {
+        (0.0, 0.0)
+    };
+    let to_point = |[x, y]: [f32; 2]| StrokePoint {
+        x: x + offset_x,
+        y: y + offset_y,
+        pressure: 1.0,
+        time: 0.0,
+    };
+
+    // A single undo step for the strokes added, unless the macro undoes or clears them itself
+    let mut has_undo_step = false;
+    for step in steps {
+        let stroke = match step {
+            macros::Step::Line(points) => Some(Stroke {
+                points: points.into_iter().map(to_point).collect(),
+                shape: Shape::Freehand,
+                ..Default::default()
+            }),
+            macros::Step::Arrow(start, end) => Some(Stroke {
+                points: vec![to_point(start), to_point(end)],
+                shape: Shape::Arrow {
+                    head: drawing.arrow_head,
+                    both_ends: drawing.config.arrow_both_ends,
+                },
+                ..Default::default()
+            }),
+            macros::Step::Box(start, end) => Some(Stroke {
+                points: vec![to_point(start), to_point(end)],
+                shape: Shape::HighlightBox {
+                    fill_opacity: drawing.config.highlight_box_fill_opacity.clamp(0.0, 1.0),
+                },
+                ..Default::default()
+            }),
+            macros::Step::Text { at, text } => {
+                let mut stroke = Stroke {
+                    points: vec![to_point(at)],
+                    shape: Shape::Text {
+                        size: drawing.text_size,
+                    },
+                    text,
+                    ..Default::default()
+                };
+                layout_text(&drawing.text, &mut stroke);
+                Some(stroke)
+            }
+            macros::Step::Color(index) => {
+                if let Some(&color) = drawing.config.brush_colors.get(index) {
+                    set_color(drawing, color_to_gl(color));
+                }
+                None
+            }
+            macros::Step::Size(size) => {
+                drawing.line_style.width = size.max(1.0);
+                None
+            }
+            macros::Step::Tool(tool) => {
+                drawing.tool = tool;
+                None
+            }
+            macros::Step::Clear => {
+                clear(drawing);
+                has_undo_step = false;
+                None
+            }
+            macros::Step::Undo => {
+                undo(drawing);
+                has_undo_step = false;
+                None
+            }
+        };
+
+        if let Some(mut stroke) = stroke {
+            if stroke.points.is_empty() {
+                continue;
+            }
+            if !has_undo_step {
+                push_undo_step(drawing);
+                has_undo_step = true;
+            }
+            stroke.color = drawing.line_style.color;
+            stroke.width = drawing.line_style.width;
+            stroke.hardness = if let Shape::Text { .. } = stroke.shape {
+                1.0
+            } else {
+                drawing.line_style.hardness
+            };
+            stroke.tessellate(&drawing.rect, drawing.lines.is_some());
+            drawing.strokes.push(Rc::new(stroke));
+        }
+    }
+
+    drawing.strokes_changed = true;
+    drawing.need_redraw = true;
+}
+
+/// Place the text of the clipboard as a text annotation, the middle of its first line on the
+/// cursor
+fn paste_text(drawing: &mut DrawingState, input: &Input) {
+    let text = match clipboard::read_text() {
+        Some(text) => text,
+        None => {
+            log::warn!("The clipboard has no text to paste");
+            return;
+        }
+    };
+    end_line(drawing, input);
+    end_text_edit(drawing);
+    push_undo_step(drawing);
+
+    let size = drawing.text_size;
+    let mut stroke = Stroke {
+        points: vec![StrokePoint {
+            x: input.cursor.x,
+            y: input.cursor.y - drawing.text.line_height(size) / 2.0,
+            pressure: 1.0,
+            time: 0.0,
+        }],
+        color: drawing.line_style.color,
+        hardness: 1.0,
+        shape: Shape::Text { size },
+        text,
+        ..Default::default()
+    };
+    layout_text(&drawing.text, &mut stroke);
+    drawing.strokes.push(Rc::new(stroke));
+
+    drawing.selection.clear();
+    drawing.strokes_changed = true;
+    drawing.need_redraw = true;
+}
+
+/// Start typing in a text annotation again, with the caret closest to the cursor
+fn start_text_edit(drawing: &mut DrawingState, input: &Input, index: usize) {
+    push_undo_step(drawing);
+
+    // Its style becomes the current one, so the next changes start from it
+    if let Shape::Text { size } = drawing.strokes[index].shape {
+        drawing.text_size = size;
+    }
+    set_color(drawing, drawing.strokes[index].color);
+
+    let caret = caret_at(
+        &drawing.text,
+        &drawing.strokes[index],
+        input.cursor.x,
+        input.cursor.y,
+    );
+    drawing.selection.clear();
+    drawing.text_edit = Some(TextEdit {
+        index,
+        caret,
+        is_new: false,
+    });
+    drawing.need_redraw = true;
+}
+
+/// Stop typing, a text annotation left empty is removed
+fn end_text_edit(drawing: &mut DrawingState) {
+    if let Some(edit) = drawing.text_edit.take() {
+        if drawing
+            .strokes
+            .get(edit.index)
+            .is_some_and(|stroke| stroke.text.trim().is_empty())
+        {
+            drawing.strokes.remove(edit.index);
+            drawing.strokes_changed = true;
+            // Nothing is left to undo of a new text
+            if edit.is_new {
+                drawing.undo_steps.pop();
+            }
+        }
+        drawing.need_redraw = true;
+    }
+}
+
+/// Change the text annotation being typed and its caret, then lay it out again
+fn edit_text<F: FnOnce(&mut Stroke, &mut usize)>(drawing: &mut DrawingState, f: F) {
+    let (edit, stroke) = match &mut drawing.text_edit {
+        Some(edit) => match drawing.strokes.get_mut(edit.index) {
+            Some(stroke) => (edit, Rc::make_mut(stroke)),
+            None => return,
+        },
+        None => return,
+    };
+    f(stroke, &mut edit.caret);
+    layout_text(&drawing.text, stroke);
+    drawing.strokes_changed = true;
+    drawing.need_redraw = true;
+}
+
+/// Type a character at the caret, completed notation sequences become their symbol
+fn insert_text(drawing: &mut DrawingState, c: char) {
+    let is_notation_enabled = drawing.config.text_notation;
+    edit_text(drawing, |stroke, caret| {
+        let index = byte_index(&stroke.text, *caret);
+        stroke.text.insert(index, c);
+        *caret += 1;
+
+        if !is_notation_enabled {
+            return;
+        }
+        let end = byte_index(&stroke.text, *caret);
+        if let Some((length, symbol)) = notation::completed_sequence(&stroke.text[..end]) {
+            let start = byte_index(&stroke.text, *caret - length);
+            stroke.text.replace_range(start..end, &symbol.to_string());
+            *caret = *caret + 1 - length;
+        }
+    });
+}
+
+/// Keys moving the caret and deleting text while typing
+fn handle_text_key(drawing: &mut DrawingState, input: &Input, key: VirtualKeyCode) {
+    match key {
+        // ctrl with a color key recolors the text, the letters are typed otherwise
+        // (ctrl on Mac too, cmd-q quits)
+        _ if input.modifiers.ctrl && COLOR_KEYS.contains(&key) => {
+            if let Some(color) = color_of_key(drawing, input, key) {
+                set_color(drawing, color);
+                edit_text(drawing, |stroke, _| stroke.color = color);
+            }
+        }
+        VirtualKeyCode::Escape => end_text_edit(drawing),
+        VirtualKeyCode::Return | VirtualKeyCode::NumpadEnter => insert_text(drawing, '\n'),
+        VirtualKeyCode::Back => edit_text(drawing, |stroke, caret| {
+            if *caret > 0 {
+                *caret -= 1;
+                let index = byte_index(&stroke.text, *caret);
+                stroke.text.remove(index);
+            }
+        }),
+        VirtualKeyCode::Delete => edit_text(drawing, |stroke, caret| {
+            if *caret < stroke.text.chars().count() {
+                let index = byte_index(&stroke.text, *caret);
+                stroke.text.remove(index);
+            }
+        }),
+        VirtualKeyCode::Left => edit_text(drawing, |_, caret| *caret = caret.saturating_sub(1)),
+        VirtualKeyCode::Right => edit_text(drawing, |stroke, caret| {
+            *caret = (*caret + 1).min(stroke.text.chars().count());
+        }),
+        VirtualKeyCode::Home => edit_text(drawing, |stroke, caret| {
+            *caret -= caret_line(&stroke.text, *caret).1;
+        }),
+        VirtualKeyCode::End => edit_text(drawing, |stroke, caret| {
+            let (line, column) = caret_line(&stroke.text, *caret);
+            let line_length = stroke
+                .text
+                .split('\n')
+                .nth(line)
+                .map_or(0, |l| l.chars().count());
+            *caret += line_length - column;
+        }),
+        _ => (),
+    }
+}
+
+/// Handle a click for typing text, returns true when the click was used
+///
+/// Clicks place or edit text with the text tool, double-clicks edit text with the other
+/// tools, and clicking away from the text being typed finishes it.
+fn click_text(drawing: &mut DrawingState, input: &mut Input) -> bool {
+    let (x, y) = (input.cursor.x, input.cursor.y);
+    let is_double_click = input.last_click.is_some_and(|(time, last_x, last_y)| {
+        time.elapsed() <= Duration::from_millis(DOUBLE_CLICK_MS)
+            && (last_x - x).hypot(last_y - y) <= ENDPOINT_SNAP_DISTANCE
+    });
+    input.last_click = Some((Instant::now(), x, y));
+
+    if let Some(edit) = &mut drawing.text_edit {
+        // Clicks in the text being typed move the caret
+        if let Some(stroke) = drawing.strokes.get(edit.index) {
+            if stroke.hit_test(x, y, 2.0) {
+                edit.caret = caret_at(&drawing.text, stroke, x, y);
+                drawing.need_redraw = true;
+                return true;
+            }
+        }
+        end_text_edit(drawing);
+        if drawing.tool != Tool::Text {
+            return true;
+        }
+    }
+
+    if drawing.tool == Tool::Text {
+        match text_at(drawing, x, y) {
+            Some(index) => start_text_edit(drawing, input, index),
+            None => new_text(drawing, input),
+        }
+        return true;
+    }
+
+    if !is_double_click || is_eraser(drawing.tool) {
+        return false;
+    }
+    let index = match text_at(drawing, x, y) {
+        Some(index) => index,
+        None => return false,
+    };
+
+    // The first click of the double-click drew a dot over the text with the brush
+    let is_dot = |stroke: &Stroke| {
+        stroke.shape == Shape::Freehand
+            && stroke.points.len() == 1
+            && (stroke.points[0].x - x).hypot(stroke.points[0].y - y) <= ENDPOINT_SNAP_DISTANCE
+    };
+    if drawing.tool == Tool::Brush && drawing.strokes.last().is_some_and(|s| is_dot(s)) {
+        drawing.strokes.pop();
+        drawing.strokes_changed = true;
+    }
+
+    start_text_edit(drawing, input, index);
+    true
+}
+
+fn handle_key_pressed(
+    key: VirtualKeyCode,
+    control_flow: &mut ControlFlow,
+    drawing: &mut DrawingState,
+    input: &mut Input,
+) {
+    match key {
+        // escape
+        VirtualKeyCode::Escape => {
+            // Todo: Request close event
+            quit(drawing, control_flow);
+        }
+        VirtualKeyCode::H => {
+            drawing.need_redraw = true;
+            // TODO: Show help
+        }
+        VirtualKeyCode::B => toggle_background(drawing),
+        VirtualKeyCode::Space => clear(drawing),
+        // ctrl-g or cmd-g, with shift to ungroup
+        VirtualKeyCode::G if input.modifiers.ctrl || input.modifiers.logo => {
+            if input.modifiers.shift {
+                ungroup_selection(drawing);
+            } else {
+                group_selection(drawing);
+            }
+        }
+        // ctrl-l or cmd-l to lock the selection, with shift to unlock everything
+        VirtualKeyCode::L if input.modifiers.ctrl || input.modifiers.logo => {
+            if input.modifiers.shift {
+                unlock_all(drawing);
+            } else {
+                lock_selection(drawing);
+            }
+            drawing.need_redraw = true;
+        }
+        VirtualKeyCode::G if input.modifiers.shift => toggle_guide_template(drawing),
+        VirtualKeyCode::G => cycle_grid(drawing),
+        VirtualKeyCode::L => {
+            drawing.need_redraw = true;
+            drawing.is_crosshair_visible = !drawing.is_crosshair_visible;
+        }
+        VirtualKeyCode::M => {
+            drawing.need_redraw = true;
+            drawing.beacon_start = Some(Instant::now());
+        }
+        // ctrl-] or cmd-] to bring to front, ctrl-[ or cmd-[ to send to back
+        VirtualKeyCode::RBracket if input.modifiers.ctrl || input.modifiers.logo => {
+            reorder_selection(drawing, false);
+            drawing.need_redraw = true;
+        }
+        VirtualKeyCode::LBracket if input.modifiers.ctrl || input.modifiers.logo => {
+            reorder_selection(drawing, true);
+            drawing.need_redraw = true;
+        }
+        // ctrl-z or cmd-z, repeats while held
+        VirtualKeyCode::Z if input.modifiers.ctrl || input.modifiers.logo => {
+            undo(drawing);
+            input.undo_repeat = Some(KeyRepeat {
+                next_time: Instant::now() + UNDO_REPEAT_DELAY,
+                count: 0,
+            });
+        }
+        VirtualKeyCode::A => {
+            // Toggle the arrow tool, shift cycles the arrow heads
+            end_line(drawing, input);
+            drawing.need_redraw = true;
+            drawing.selection.clear();
+            if input.modifiers.shift {
+                drawing.arrow_head = match drawing.arrow_head {
+                    ArrowHead::Triangle => ArrowHead::Open,
+                    ArrowHead::Open => ArrowHead::Dot,
+                    ArrowHead::Dot => ArrowHead::Triangle,
+                };
+                drawing.tool = Tool::Arrow;
+            } else if drawing.tool == Tool::Arrow {
+                drawing.tool = Tool::Brush;
+            } else {
+                drawing.tool = Tool::Arrow;
+            }
+        }
+        VirtualKeyCode::F => {
+            // Toggle the highlight box tool
+            end_line(drawing, input);
+            drawing.need_redraw = true;
+            drawing.selection.clear();
+            drawing.tool = if drawing.tool == Tool::HighlightBox {
+                Tool::Brush
+            } else {
+                Tool::HighlightBox
+            };
+        }
+        VirtualKeyCode::N => {
+            // Toggle the text tool
+            end_line(drawing, input);
+            drawing.need_redraw = true;
+            drawing.selection.clear();
+            drawing.tool = if drawing.tool == Tool::Text {
+                Tool::Brush
+            } else {
+                Tool::Text
+            };
+        }
+        // Draw with the keyboard, the arrow keys moving the cursor
+        VirtualKeyCode::Return | VirtualKeyCode::NumpadEnter => toggle_cursor_press(drawing, input),
+        VirtualKeyCode::F3 if input.modifiers.shift => {
+            drawing.is_session_report_visible = !drawing.is_session_report_visible;
+            drawing.need_redraw = true;
+        }
+        VirtualKeyCode::F3 => {
+            drawing.is_stats_visible = !drawing.is_stats_visible;
+            drawing.need_redraw = true;
+        }
+        VirtualKeyCode::O => toggle_timer(drawing),
+        // ctrl-shift-k or cmd-shift-k
+        VirtualKeyCode::K
+            if (input.modifiers.ctrl || input.modifiers.logo) && input.modifiers.shift =>
+        {
+            match clipboard::read_text() {
+                Some(text) => place_qr_code(drawing, input, text.trim()),
+                None => log::warn!("The clipboard has no link or text for a QR code"),
+            }
+        }
+        VirtualKeyCode::K => {
+            if input.modifiers.shift {
+                reset_stopwatch(drawing);
+            } else {
+                toggle_stopwatch(drawing);
+            }
+        }
+        VirtualKeyCode::P => {
+            // Toggle the redaction tool
+            end_line(drawing, input);
+            drawing.need_redraw = true;
+            drawing.selection.clear();
+            drawing.tool = if drawing.tool == Tool::Redaction {
+                Tool::Brush
+            } else {
+                Tool::Redaction
+            };
+        }
+        VirtualKeyCode::C => {
+            // Toggle the screenshot tool
+            end_line(drawing, input);
+            drawing.need_redraw = true;
+            drawing.selection.clear();
+            drawing.tool = if drawing.tool == Tool::Screenshot {
+                Tool::Brush
+            } else {
+                Tool::Screenshot
+            };
+        }
+        // ctrl-shift-v or cmd-shift-v
+        VirtualKeyCode::V
+            if (input.modifiers.ctrl || input.modifiers.logo) && input.modifiers.shift =>
+        {
+            paste_text(drawing, input);
+        }
+        // ctrl-shift-s or cmd-shift-s
+        VirtualKeyCode::S
+            if (input.modifiers.ctrl || input.modifiers.logo) && input.modifiers.shift =>
+        {
+            export_timeline(drawing);
+        }
+        // ctrl-s or cmd-s
+        VirtualKeyCode::S if input.modifiers.ctrl || input.modifiers.logo => {
+            export_strokes(drawing);
+        }
+        VirtualKeyCode::S => {
+            // Toggle the select tool
+            end_line(drawing, input);
+            drawing.need_redraw = true;
+            drawing.selection.clear();
+            drawing.tool = if drawing.tool == Tool::Select {
+                Tool::Brush
+            } else {
+                Tool::Select
+            };
+        }
+        // ctrl-d or cmd-d
+        VirtualKeyCode::D if input.modifiers.ctrl || input.modifiers.logo => {
+            duplicate_selection(drawing);
+            drawing.need_redraw = true;
+        }
+        VirtualKeyCode::D => {
+            // Toggle the whole-stroke eraser, or the segment eraser with shift
+            let eraser = if input.modifiers.shift {
+                Tool::SegmentEraser
+            } else {
+                Tool::StrokeEraser
+            };
+
+            end_line(drawing, input);
+            drawing.need_redraw = true;
+            drawing.selection.clear();
+            drawing.tool = if drawing.tool == eraser {
+                Tool::Brush
+            } else {
+                eraser
+            };
+        }
+
+        // q,w,e,r,... for line colors, with shift for the colors after the eighth, which also
+        // recolor the selection
+        _ if COLOR_KEYS.contains(&key) => {
+            if let Some(color) = color_of_key(drawing, input, key) {
+                set_color(drawing, color);
+                recolor_selection(drawing, color);
+                drawing.need_redraw = true;
+            }
+            apply_brush_preset(drawing, input, key);
+        }
+        VirtualKeyCode::X => {
+            // Swap the brush and secondary colors
+            let secondary = drawing.secondary_color;
+            drawing.secondary_color = drawing.line_style.color;
+            set_color(drawing, secondary);
+            drawing.need_redraw = true;
+        }
+        VirtualKeyCode::Tab => {
+            // Cycle through the recent colors, backward with shift
+            if input.modifiers.shift {
+                drawing.recent_colors.rotate_right(1);
+            } else {
+                drawing.recent_colors.rotate_left(1);
+            }
+            drawing.line_style.color = drawing.recent_colors[0];
+            drawing.need_redraw = true;
+        }
+
+        // ctrl-1,2,3,... or cmd-1,2,3,... to count up the counters, with shift to count down
+        _ if SIZE_KEYS.contains(&key) && (input.modifiers.ctrl || input.modifiers.logo) => {
+            let index = SIZE_KEYS.iter().position(|&k| k == key).unwrap();
+            let amount = if input.modifiers.shift { -1 } else { 1 };
+            add_to_counter(drawing, index, amount);
+        }
+        // ctrl-0 or cmd-0 to reset the counters
+        VirtualKeyCode::Key0 if input.modifiers.ctrl || input.modifiers.logo => {
+            reset_counters(drawing)
+        }
+
+        // 1,2,3,... for size, as many keys as there are sizes in the config
+        _ if SIZE_KEYS.contains(&key) => {
+            let index = SIZE_KEYS.iter().position(|&k| k == key).unwrap();
+            if let Some(&size) = drawing.config.brush_sizes.get(index) {
+                drawing.line_style.width = size;
+                drawing.need_redraw = true;
+            }
+        }
+
+        _ => (),
+    }
+}
+
+fn handle_event(
+    event: Event<instance::Command>,
+    control_flow: &mut ControlFlow,
+    drawing: &mut DrawingState,
+    input: &mut Input,
+) {
+    *control_flow = ControlFlow::Wait;
+
+    let is_input = matches!(
+        event,
+        Event::WindowEvent {
+            event: WindowEvent::KeyboardInput { .. }
+                | WindowEvent::ReceivedCharacter(_)
+                | WindowEvent::MouseInput { .. }
+                | WindowEvent::MouseWheel { .. }
+                | WindowEvent::Touch(_),
+            ..
+        }
+    );
+    if is_input {
+        // The drawing fading out comes back
+        if auto_clear_fade(drawing) < 1.0 {
+            drawing.strokes_changed = true;
+            drawing.need_redraw = true;
+        }
+        drawing.last_activity = Instant::now();
+    }
+
+    match event {
+        Event::LoopDestroyed => (),
+        // The pass-through key is usually released over another window
+        Event::DeviceEvent {
+            event:
+                DeviceEvent::Key(KeyboardInput {
+                    state: ElementState::Released,
+                    virtual_keycode: Some(key),
+                    ..
+                }),
+            ..
+        } if drawing.is_passing_through && Some(key) == drawing.config.pass_through_key => {
+            set_passing_through(drawing, input, false);
+        }
+        // The push-to-draw key works from the other windows, where the platform reports it
+        Event::DeviceEvent {
+            event:
+                DeviceEvent::Key(KeyboardInput {
+                    state,
+                    virtual_keycode: Some(key),
+                    ..
+                }),
+            ..
+        } if Some(key) == drawing.config.push_to_draw_key => match state {
+            ElementState::Pressed => start_push_to_draw(drawing, input),
+            ElementState::Released => end_push_to_draw(drawing, input),
+        },
+        // The mode toggle key works from the other windows too, where the platform reports it
+        Event::DeviceEvent {
+            event:
+                DeviceEvent::Key(KeyboardInput {
+                    state,
+                    virtual_keycode: Some(key),
+                    ..
+                }),
+            ..
+        } if !input.has_focus && Some(key) == drawing.config.mode_toggle_key => {
+            let was_pressed = input.pressed_keys.contains(&key);
+            input.pressed_keys.retain(|&k| k != key);
+            if state == ElementState::Pressed {
+                input.pressed_keys.push(key);
+                if !was_pressed {
+                    set_interacting(drawing, input, !drawing.is_interacting);
+                    if !drawing.is_interacting {
+                        show_window(drawing);
+                    }
+                }
+            }
+        }
+        // Inke was launched again or shown from the menu bar
+        Event::UserEvent(instance::Command::Activate) => {
+            set_interacting(drawing, input, false);
+            show_window(drawing);
+        }
+        Event::UserEvent(instance::Command::Hide) => hide_window(drawing),
+        Event::UserEvent(instance::Command::Clear) => clear(drawing),
+        Event::UserEvent(instance::Command::ToggleBackground) => toggle_background(drawing),
+        Event::UserEvent(instance::Command::Quit) => quit(drawing, control_flow),
+        Event::UserEvent(instance::Command::ScreenCaptured) => {
+            drawing.is_capturing = false;
+            drawing.need_redraw = true;
+        }
+        Event::UserEvent(instance::Command::Saved(result)) => match result {
+            Ok(message) => log::info!("{}", message),
+            Err(message) => log::error!("{}", message),
+        },
+        Event::UserEvent(instance::Command::Session(message)) => {
+            handle_session_message(drawing, message)
+        }
+        Event::UserEvent(instance::Command::ExportStrokes) => export_strokes(drawing),
+        Event::UserEvent(instance::Command::ExportTimeline) => export_timeline(drawing),
+        Event::UserEvent(instance::Command::ImportStrokes(path)) => {
+            import_strokes(drawing, Path::new(&path))
+        }
+        Event::UserEvent(instance::Command::QrCode(text)) => place_qr_code(drawing, input, &text),
+        Event::UserEvent(instance::Command::ToggleStopwatch) => toggle_stopwatch(drawing),
+        Event::UserEvent(instance::Command::ResetStopwatch) => reset_stopwatch(drawing),
+        Event::UserEvent(instance::Command::AddToCounter(index, amount)) => {
+            add_to_counter(drawing, index, amount)
+        }
+        Event::UserEvent(instance::Command::ResetCounters) => reset_counters(drawing),
+        Event::UserEvent(instance::Command::Undo) => undo(drawing),
+        Event::UserEvent(instance::Command::SetTool(tool)) => {
+            end_text_edit(drawing);
+            end_line(drawing, input);
+            drawing.need_redraw = true;
+            drawing.selection.clear();
+            drawing.tool = tool;
+        }
+        Event::UserEvent(instance::Command::SetColor(index)) => {
+            if let Some(&color) = drawing.config.brush_colors.get(index) {
+                set_color(drawing, color_to_gl(color));
+                drawing.need_redraw = true;
+            }
+        }
+        Event::UserEvent(instance::Command::MidiControl(control, value)) => {
+            handle_midi_control(drawing, control, value)
+        }
+        Event::UserEvent(instance::Command::MidiNote(note)) => {
+            let index = drawing
+                .config
+                .midi_color_notes
+                .iter()
+                .position(|&n| n == note);
+            if let Some(&color) = index.and_then(|i| drawing.config.brush_colors.get(i)) {
+                set_color(drawing, color_to_gl(color));
+                drawing.need_redraw = true;
+            }
+        }
+        Event::UserEvent(instance::Command::Gamepad(state)) => {
+            handle_gamepad(drawing, input, state)
+        }
+        Event::UserEvent(instance::Command::SetSize(index)) => {
+            if let Some(&size) = drawing.config.brush_sizes.get(index) {
+                drawing.line_style.width = size;
+                drawing.need_redraw = true;
+            }
+        }
+        Event::WindowEvent { event, .. } => match event {
+            // Alt-tab in and out
+            WindowEvent::Focused(has_focus) => {
+                input.has_focus = has_focus;
+                if has_focus {
+                    // unhide
+                    drawing.is_window_hidden = false;
+                    drawing.last_activity = Instant::now();
+                    if drawing.config.per_workspace_boards {
+                        switch_workspace(drawing, input);
+                    }
+                } else {
+                    // Keys released while unfocused are never reported
+                    input.pressed_keys.clear();
+                    input.undo_repeat = None;
+                    // Stay visible while clicking the windows below
+                    if !drawing.is_passing_through && !drawing.is_interacting {
+                        hide_window(drawing);
+                    }
+                }
+            }
+            // Undo the window manager moving or resizing the overlay
+            WindowEvent::Resized(_) | WindowEvent::Moved(_) if drawing.config.tiling_wm_compat => {
+                restore_overlay_geometry(drawing);
+            }
+            WindowEvent::ModifiersChanged(state) => {
+                input.modifiers = Modifiers::from(state);
+                if !input.modifiers.ctrl && !input.modifiers.logo {
+                    input.undo_repeat = None;
+                }
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state,
+                        virtual_keycode: Some(key),
+                        ..
+                    },
+                ..
+            } => {
+                let was_pressed = input.pressed_keys.contains(&key);
+                input.pressed_keys.retain(|&k| k != key);
+
+                if state == ElementState::Pressed {
+                    input.pressed_keys.push(key);
+                    if drawing.text_edit.is_some() {
+                        // Typing takes over the shortcuts, held keys repeat
+                        handle_text_key(drawing, input, layout_key(drawing, key));
+                    } else if !was_pressed {
+                        // The OS repeats held keys, only act on the first press
+                        if Some(key) == drawing.config.pass_through_key {
+                            set_passing_through(drawing, input, true);
+                        } else if Some(key) == drawing.config.mode_toggle_key {
+                            set_interacting(drawing, input, !drawing.is_interacting);
+                        } else if Some(key) == drawing.config.push_to_draw_key {
+                            start_push_to_draw(drawing, input);
+                        } else if Some(key) == drawing.config.switch_key {
+                            toggle_cursor_press(drawing, input);
+                        } else if let Some(index) =
+                            drawing.config.macros.iter().position(|m| m.key == key)
+                        {
+                            play_macro(drawing, input, index);
+                        } else {
+                            let key = layout_key(drawing, key);
+                            handle_key_pressed(key, control_flow, drawing, input);
+                        }
+                    }
+                } else if Some(key) == drawing.config.pass_through_key {
+                    set_passing_through(drawing, input, false);
+                } else if Some(key) == drawing.config.push_to_draw_key {
+                    end_push_to_draw(drawing, input);
+                } else if key == VirtualKeyCode::Z {
+                    input.undo_repeat = None;
+                }
+            }
+            WindowEvent::DroppedFile(path) => {
+                let extension = path
+                    .extension()
+                    .map(|extension| extension.to_string_lossy().to_lowercase());
+                match extension.as_deref() {
+                    Some("json") => import_strokes(drawing, &path),
+                    Some("png") => drop_image(drawing, input, &path),
+                    Some("jpg") | Some("jpeg") | Some("svg") => log::error!(
+                        "Failed to open {}: only PNG images are supported",
+                        path.display()
+                    ),
+                    _ => (),
+                }
+            }
+            WindowEvent::Touch(touch_event) => {
+                drawing.need_redraw = true;
+
+                if touch_event.phase == TouchPhase::Started {
+                    input.cursor.pressed = true;
+                }
+                if touch_event.phase == TouchPhase::Ended
+                    || touch_event.phase == TouchPhase::Cancelled
+                {
+                    input.cursor.pressed = false;
+                    input.cursor.released_time = Some(SystemTime::now());
+                    end_line(drawing, input);
+
+                    drawing.need_redraw = true;
+                }
+
+                input.cursor.last_x = input.cursor.x;
+                input.cursor.last_y = input.cursor.y;
+                input.cursor.x = touch_event.location.x as f32;
+                input.cursor.y = touch_event.location.y as f32;
+                if input.cursor.pressed {
+                    let sample = (input.cursor.x, input.cursor.y, SystemTime::now());
+                    input.cursor.samples.push(sample);
+                }
+
+                if let Some(force_type) = touch_event.force {
+                    match force_type {
+                        glutin::event::Force::Calibrated {
+                            force,
+                            max_possible_force,
+                            altitude_angle: _,
+                        } => {
+                            drawing.line_style.pressure = (force / max_possible_force) as f32;
+                        }
+                        glutin::event::Force::Normalized(force) => {
+                            drawing.line_style.pressure = force as f32;
+                        }
+                    }
+                }
+            }
+            WindowEvent::CloseRequested => quit(drawing, control_flow),
+            // Mouse pressed, the modifiers come from ModifiersChanged
+            WindowEvent::MouseInput {
+                state,
+                button: MouseButton::Left,
+                ..
+            } => {
+                if state == ElementState::Pressed {
+                    press_cursor(drawing, input);
+                } else {
+                    release_cursor(drawing, input);
+                }
+            }
+            // Mousewheel
+            WindowEvent::MouseWheel {
+                delta: MouseScrollDelta::LineDelta(_x, y),
+                phase: TouchPhase::Moved,
+                ..
+            } => {
+                drawing.need_redraw = true;
+
+                if drawing.text_edit.is_some() || drawing.tool == Tool::Text {
+                    // Resize the text being typed and the next ones
+                    let factor = 1.1_f32.powf(y);
+                    let mut text_size = (drawing.text_size * factor).max(MIN_TEXT_SIZE);
+                    edit_text(drawing, |stroke, _| {
+                        if let Shape::Text { size } = &mut stroke.shape {
+                            *size = (*size * factor).max(MIN_TEXT_SIZE);
+                            text_size = *size;
+                        }
+                    });
+                    drawing.text_size = text_size;
+                } else if (input.modifiers.ctrl || input.modifiers.logo)
+                    && !drawing.selection.is_empty()
+                {
+                    // ctrl-scroll or cmd-scroll scales the selection
+                    scale_selection(drawing, 1.1_f32.powf(y));
+                } else if input.modifiers.alt && !drawing.selection.is_empty() {
+                    // alt-scroll rotates the selection
+                    rotate_selection(drawing, (y * ROTATION_STEP).to_radians());
+                } else if !drawing.selection.is_empty() {
+                    // scrolling alone changes the width of the selection, as of the brush
+                    widen_selection(drawing, -y);
+                } else if is_eraser(drawing.tool) {
+                    // The eraser has its own size so erasing doesn't change the brush
+                    drawing.eraser_size = (drawing.eraser_size - y).max(1.0);
+                } else {
+                    drawing.line_style.width -= y;
+                    if drawing.line_style.width < 1.0 {
+                        drawing.line_style.width = 1.0;
+                    }
+                }
+            }
+            WindowEvent::ReceivedCharacter(c)
+                if drawing.text_edit.is_some()
+                    && !c.is_control()
+                    && !input.modifiers.ctrl
+                    && !input.modifiers.logo =>
+            {
+                insert_text(drawing, c);
+            }
+            // Mouse moved
+            WindowEvent::CursorMoved { position, .. } => {
+                input.cursor.last_x = input.cursor.x;
+                input.cursor.last_y = input.cursor.y;
+                input.cursor.x = position.x as f32;
+                input.cursor.y = position.y as f32;
+                if input.cursor.pressed {
+                    let sample = (input.cursor.x, input.cursor.y, SystemTime::now());
+                    input.cursor.samples.push(sample);
                 }
+                drawing.need_redraw = true;
+            }
+            _ => (),
+        },
+        _ => (),
+    }
+
+    repeat_undo(drawing, input, control_flow);
+    auto_clear(drawing, input, control_flow);
+    auto_hide(drawing, input, control_flow);
+    fade_ephemeral_strokes(drawing, control_flow);
+    update_beacon(drawing, control_flow);
+    update_timer(drawing, control_flow);
+    update_stopwatch(drawing, control_flow);
+    update_session_report(drawing, control_flow);
+    update_dwell(drawing, input, control_flow);
+    move_cursor_with_keys(drawing, input, control_flow);
+    announce_changes(drawing, control_flow);
+    share_strokes(drawing, control_flow);
+    animate_shaders(drawing, control_flow);
+}
+
+/// Start a line, a drag or a selection where the cursor is pressed
+fn press_cursor(drawing: &mut DrawingState, input: &mut Input) {
+    if is_mirroring(drawing) {
+        return;
+    }
+    input.cursor.pressed = true;
+
+    if click_text(drawing, input) {
+        // Typing doesn't draw
+        input.cursor.pressed = false;
+    } else if input.modifiers.ctrl || input.modifiers.logo {
+        // ctrl-drag or cmd-drag moves a single line
+        if let Some(index) = stroke_at_cursor(drawing, input) {
+            let indices = with_groups(drawing, vec![index]);
+            start_stroke_drag(drawing, input, indices);
+        }
+    } else {
+        start_color_pick(drawing, input);
+        tools::handler(drawing.tool).press(drawing, input);
+    }
+}
+
+/// Finish what the cursor press started
+fn release_cursor(drawing: &mut DrawingState, input: &mut Input) {
+    input.cursor.pressed = false;
+    input.cursor.released_time = Some(SystemTime::now());
+    end_color_pick(drawing, input);
+    end_line(drawing, input);
+
+    drawing.need_redraw = true;
+}
+
+/// Key a shortcut is bound to, swapping the top row and using the numpad for the sizes with
+/// the left-handed layout
+fn layout_key(drawing: &DrawingState, key: VirtualKeyCode) -> VirtualKeyCode {
+    if !drawing.config.left_handed_keys {
+        return key;
+    }
+    for &(left, right) in MIRRORED_KEYS.iter() {
+        if key == left {
+            return right;
+        } else if key == right {
+            return left;
+        }
+    }
+    match NUMPAD_SIZE_KEYS.iter().position(|&k| k == key) {
+        Some(index) => SIZE_KEYS[index],
+        None => key,
+    }
+}
+
+/// Spoken name of a tool
+fn tool_name(tool: Tool) -> &'static str {
+    match tool {
+        Tool::Brush => "Brush",
+        Tool::StrokeEraser => "Stroke eraser",
+        Tool::SegmentEraser => "Segment eraser",
+        Tool::Select => "Select",
+        Tool::Arrow => "Arrow",
+        Tool::Screenshot => "Screenshot",
+        Tool::Redaction => "Redaction",
+        Tool::HighlightBox => "Highlight box",
+        Tool::Text => "Text",
+    }
+}
+
+/// Speak the changes of tool, color and size once they settle
+fn announce_changes(drawing: &mut DrawingState, control_flow: &mut ControlFlow) {
+    if drawing.config.announce_command.is_empty() {
+        return;
+    }
+    let current = (
+        drawing.tool,
+        drawing.line_style.color,
+        drawing.line_style.width.round(),
+    );
+    if current == drawing.announced {
+        drawing.announce_time = None;
+        return;
+    }
+    let now = Instant::now();
+    let announce_time = *drawing.announce_time.get_or_insert(now + ANNOUNCE_DELAY);
+    if now < announce_time {
+        wake_up_at(control_flow, announce_time);
+        return;
+    }
+
+    let (tool, color, size) = current;
+    let mut changes = Vec::new();
+    if tool != drawing.announced.0 {
+        changes.push(format!("{} tool", tool_name(tool)));
+    }
+    if color != drawing.announced.1 {
+        // Colors are named by their shortcut number, the config only has their values
+        let index = drawing
+            .config
+            .brush_colors
+            .iter()
+            .position(|&c| color_to_gl(c) == color);
+        changes.push(match index {
+            Some(index) => format!("Color {}", index + 1),
+            None => String::from("Recent color"),
+        });
+    }
+    if size != drawing.announced.2 {
+        changes.push(format!("Size {}", size));
+    }
+    announce::speak(&drawing.config.announce_command, &changes.join(", "));
+    drawing.announced = current;
+    drawing.announce_time = None;
+}
+
+/// Press the cursor, or release it if it was pressed, for switch access and dwell-clicking
+fn toggle_cursor_press(drawing: &mut DrawingState, input: &mut Input) {
+    if input.cursor.pressed {
+        release_cursor(drawing, input);
+    } else {
+        press_cursor(drawing, input);
+    }
+}
+
+/// Part of the dwell time the cursor has been resting for, None when it isn't dwelling
+fn dwell_progress(drawing: &DrawingState, input: &Input) -> Option<f32> {
+    let delay = Duration::from_millis(drawing.config.dwell_time_ms);
+    match &input.dwell {
+        Some(dwell) if !dwell.is_done && !delay.is_zero() => {
+            Some((dwell.since.elapsed().as_secs_f32() / delay.as_secs_f32()).min(1.0))
+        }
+        _ => None,
+    }
+}
+
+/// Toggle the press of the cursor once it rests in place for the dwell time of the config
+fn update_dwell(drawing: &mut DrawingState, input: &mut Input, control_flow: &mut ControlFlow) {
+    let delay = Duration::from_millis(drawing.config.dwell_time_ms);
+    let is_blocked =
+        drawing.is_window_hidden || drawing.is_interacting || drawing.text_edit.is_some();
+    if delay.is_zero() || is_blocked {
+        input.dwell = None;
+        return;
+    }
+
+    let (x, y) = (input.cursor.x, input.cursor.y);
+    let now = Instant::now();
+    let has_moved = match &input.dwell {
+        Some(dwell) => (x - dwell.x).hypot(y - dwell.y) > DWELL_RADIUS,
+        None => true,
+    };
+    if has_moved {
+        input.dwell = Some(Dwell {
+            x,
+            y,
+            since: now,
+            is_done: false,
+        });
+    }
+
+    let dwell = input.dwell.as_mut().unwrap();
+    if dwell.is_done {
+        return;
+    }
+    drawing.need_redraw = true;
+    let end = dwell.since + delay;
+    if now >= end {
+        dwell.is_done = true;
+        toggle_cursor_press(drawing, input);
+    } else {
+        // The indicator fills up every frame
+        wake_up_at(control_flow, end.min(now + FRAME_INTERVAL));
+    }
+}
+
+/// Move the cursor without the mouse, staying inside the overlay
+fn move_cursor_by(drawing: &mut DrawingState, input: &mut Input, dx: f32, dy: f32) {
+    input.cursor.last_x = input.cursor.x;
+    input.cursor.last_y = input.cursor.y;
+    input.cursor.x = (input.cursor.x + dx).clamp(0.0, drawing.rect.width - 1.0);
+    input.cursor.y = (input.cursor.y + dy).clamp(0.0, drawing.rect.height - 1.0);
+    // The system cursor follows, so the mouse carries on from there
+    let window = drawing.gl_context.window_context.window();
+    let _ = window.set_cursor_position(PhysicalPosition::new(input.cursor.x, input.cursor.y));
+    drawing.need_redraw = true;
+}
+
+/// Move the cursor with the arrow keys while they are held, a pixel at first then faster and
+/// faster
+fn move_cursor_with_keys(
+    drawing: &mut DrawingState,
+    input: &mut Input,
+    control_flow: &mut ControlFlow,
+) {
+    let mut direction = (0.0, 0.0);
+    for &key in &input.pressed_keys {
+        match key {
+            VirtualKeyCode::Left => direction.0 -= 1.0,
+            VirtualKeyCode::Right => direction.0 += 1.0,
+            VirtualKeyCode::Up => direction.1 -= 1.0,
+            VirtualKeyCode::Down => direction.1 += 1.0,
+            _ => (),
+        }
+    }
+    if direction == (0.0, 0.0) || drawing.text_edit.is_some() {
+        input.arrow_move = None;
+        return;
+    }
+
+    let now = Instant::now();
+    let (start, last_step) = match input.arrow_move {
+        Some(times) => times,
+        None => {
+            // A tap moves a single pixel, for precise placement
+            input.arrow_move = Some((now, now + KEYBOARD_CURSOR_DELAY));
+            move_cursor_by(drawing, input, direction.0, direction.1);
+            wake_up_at(control_flow, now + KEYBOARD_CURSOR_DELAY);
+            return;
+        }
+    };
+    if now < last_step {
+        wake_up_at(control_flow, last_step);
+        return;
+    }
+
+    let held = (now - start).as_secs_f32();
+    let speed = (KEYBOARD_CURSOR_SPEED + KEYBOARD_CURSOR_ACCELERATION * held)
+        .min(KEYBOARD_CURSOR_MAX_SPEED);
+    let distance = speed * (now - last_step).as_secs_f32();
+    move_cursor_by(
+        drawing,
+        input,
+        direction.0 * distance,
+        direction.1 * distance,
+    );
+    input.arrow_move = Some((start, now));
+    wake_up_at(control_flow, now + FRAME_INTERVAL);
+}
+
+/// Move the cursor with the stick, draw while the trigger is pressed, and act on the buttons
+/// pressed since the last state
+fn handle_gamepad(drawing: &mut DrawingState, input: &mut Input, state: gamepad::GamepadState) {
+    let (dx, dy) = state.stick;
+    if dx != 0.0 || dy != 0.0 {
+        let speed = drawing.config.gamepad_cursor_speed;
+        move_cursor_by(drawing, input, dx * speed, dy * speed);
+    }
+
+    let was_drawing = input.gamepad.trigger > GAMEPAD_TRIGGER_THRESHOLD;
+    let is_drawing = state.trigger > GAMEPAD_TRIGGER_THRESHOLD;
+    if is_drawing {
+        drawing.line_style.pressure = state.trigger;
+    }
+    if is_drawing && !was_drawing {
+        press_cursor(drawing, input);
+    } else if was_drawing && !is_drawing {
+        release_cursor(drawing, input);
+        drawing.line_style.pressure = 1.0;
+    }
+
+    let pressed = state.buttons & !input.gamepad.buttons;
+    input.gamepad = state;
+    if pressed & gamepad::BUTTON_UNDO != 0 {
+        undo(drawing);
+    }
+    for &(button, step) in [
+        (gamepad::BUTTON_NEXT_COLOR, 1),
+        (gamepad::BUTTON_PREVIOUS_COLOR, -1),
+    ]
+    .iter()
+    {
+        let colors = &drawing.config.brush_colors;
+        if pressed & button == 0 || colors.is_empty() {
+            continue;
+        }
+        // From the current color, or the first one if it isn't in the config
+        let current = colors
+            .iter()
+            .position(|&color| color_to_gl(color) == drawing.line_style.color);
+        let index = match current {
+            Some(index) => (index as i64 + step).rem_euclid(colors.len() as i64) as usize,
+            None => 0,
+        };
+        let color = color_to_gl(colors[index]);
+        set_color(drawing, color);
+        drawing.need_redraw = true;
+    }
+    let width = drawing.line_style.width;
+    let sizes = &drawing.config.brush_sizes;
+    let next_size = if pressed & gamepad::BUTTON_LARGER_SIZE != 0 {
+        sizes
+            .iter()
+            .copied()
+            .filter(|&s| s > width)
+            .reduce(f32::min)
+    } else if pressed & gamepad::BUTTON_SMALLER_SIZE != 0 {
+        sizes
+            .iter()
+            .copied()
+            .filter(|&s| s < width)
+            .reduce(f32::max)
+    } else {
+        None
+    };
+    if let Some(size) = next_size {
+        drawing.line_style.width = size;
+        drawing.need_redraw = true;
+    }
+}
+
+/// Milliseconds since the Unix epoch, for the times of the strokes
+fn unix_millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_millis() as u64)
+}
+
+/// Pen pressure through a low-pass filter, apart from the position smoothing, so its jitter
+/// doesn't make the width of the line pulse
+fn filtered_pressure(line_style: &mut LineStyle) -> f32 {
+    let pressure = match line_style.smoothed_pressure {
+        Some(last) => {
+            last * line_style.pressure_smoothing
+                + line_style.pressure * (1.0 - line_style.pressure_smoothing)
+        }
+        None => line_style.pressure,
+    };
+    line_style.smoothed_pressure = Some(pressure);
+    pressure
+}
+
+/// Length in pixels over which new lines narrow at their ends, from the millimetres of the config
+fn taper_length(drawing: &DrawingState) -> f32 {
+    mm_to_pixels(drawing, drawing.config.stroke_taper_mm)
+}
+
+/// Pixels of the overlay for a length of the config in millimetres, negative ones being 0
+fn mm_to_pixels(drawing: &DrawingState, mm: f32) -> f32 {
+    const PIXELS_PER_MM: f64 = 96.0 / 25.4;
+    let scale_factor = drawing.gl_context.window_context.window().scale_factor();
+    (mm.max(0.0) as f64 * PIXELS_PER_MM * scale_factor) as f32
+}
+
+/// Add a point to the line being drawn with the brush, keeping it on its axis while the axis
+/// lock modifier is held. Returns whether the point was added
+fn add_brush_point(
+    drawing: &mut DrawingState,
+    input: &Input,
+    (x, y, time): (f32, f32, SystemTime),
+) -> bool {
+    let pressure = filtered_pressure(&mut drawing.line_style);
+    let stroke = Rc::make_mut(drawing.strokes.last_mut().unwrap());
+    let mut point = StrokePoint {
+        x,
+        y,
+        pressure,
+        time: unix_millis(time).saturating_sub(stroke.start_time) as f32,
+    };
+
+    // The lock starts from where the line was when the modifier got pressed
+    let is_axis_locked = drawing
+        .config
+        .axis_lock_modifier
+        .is_some_and(|key| input.modifiers.is_held(key));
+    if !is_axis_locked {
+        drawing.axis_lock = None;
+    } else if drawing.axis_lock.is_none() {
+        let (x, y) = stroke
+            .points
+            .last()
+            .map_or((point.x, point.y), |last| (last.x, last.y));
+        drawing.axis_lock = Some(AxisLock { x, y, axis: None });
+    }
+
+    if let Some(lock) = &mut drawing.axis_lock {
+        let (dx, dy) = (point.x - lock.x, point.y - lock.y);
+        if lock.axis.is_none() && dx.abs().max(dy.abs()) >= AXIS_LOCK_THRESHOLD {
+            lock.axis = Some(if dx.abs() >= dy.abs() {
+                Axis::Horizontal
+            } else {
+                Axis::Vertical
+            });
+        }
+        match lock.axis {
+            Some(Axis::Horizontal) => point.y = lock.y,
+            Some(Axis::Vertical) => point.x = lock.x,
+            // Hold still until the axis is known, not to leave a stray tick
+            None => {
+                point.x = lock.x;
+                point.y = lock.y;
+            }
+        }
+    }
+
+    // Skip positions where the cursor didn't move, they would add empty segments
+    let is_same_point = stroke
+        .points
+        .last()
+        .is_some_and(|last| last.x == point.x && last.y == point.y);
+    if !is_same_point {
+        stroke.points.push(point);
+    }
+    !is_same_point
+}
+
+/// Tessellate the line being drawn with an extra segment to where the cursor should be on the
+/// next frame, moving as it did since the last one, so the ink keeps up with a pen. The segments
+/// ending on the points from `from` on are tessellated again, the last predicted one with them
+fn predict_brush_point(drawing: &mut DrawingState, (x, y): (f32, f32), from: usize) {
+    // A new line starts without a previous frame
+    let last_position = if drawing.n_points_current_line > 0 {
+        drawing.last_frame_cursor.replace((x, y))
+    } else {
+        drawing.last_frame_cursor = Some((x, y));
+        None
+    };
+    let stroke = Rc::make_mut(drawing.strokes.last_mut().unwrap());
+
+    let prediction = match last_position {
+        // Axis-locked lines stay on their axis
+        Some((last_x, last_y)) if drawing.axis_lock.is_none() => {
+            let (dx, dy) = (x - last_x, y - last_y);
+            let length = dx.hypot(dy);
+            // Far jumps are more likely to overshoot
+            let scale = (PREDICTION_MAX_DISTANCE / length).min(1.0);
+            stroke
+                .points
+                .last()
+                .filter(|_| length > 0.0)
+                .map(|last| StrokePoint {
+                    x: last.x + dx * scale,
+                    y: last.y + dy * scale,
+                    pressure: last.pressure,
+                    time: last.time,
+                })
+        }
+        _ => None,
+    };
+
+    match prediction {
+        Some(point) => {
+            stroke.points.push(point);
+            stroke.tessellate_from(from, &drawing.rect, drawing.lines.is_some());
+            stroke.points.pop();
+        }
+        None => stroke.tessellate_from(from, &drawing.rect, drawing.lines.is_some()),
+    }
+    drawing.line_changed = true;
+}
+
+/// Create or update the shape being dragged from the press position to the cursor
+fn draw_shape(drawing: &mut DrawingState, input: &Input, shape: Shape) {
+    let (x, y) = snap_point(drawing, input.cursor.x, input.cursor.y);
+    let point = StrokePoint {
+        x,
+        y,
+        pressure: 1.0,
+        time: 0.0,
+    };
+
+    if drawing.n_points_current_line == 0 {
+        push_undo_step(drawing);
+        drawing.strokes.push(Rc::new(Stroke {
+            points: vec![point, point],
+            color: drawing.line_style.color,
+            width: drawing.line_style.width,
+            hardness: drawing.line_style.hardness,
+            shape,
+            taper: taper_length(drawing),
+            ..Default::default()
+        }));
+    }
+
+    let stroke = Rc::make_mut(drawing.strokes.last_mut().unwrap());
+    stroke.points[1] = point;
+    stroke.shape = shape;
+    stroke.tessellate(&drawing.rect, drawing.lines.is_some());
+
+    drawing.n_points_current_line += 1;
+    drawing.strokes_changed = true;
+}
+
+/// Add the 4 sides of a rectangle (in screen pixels) to a list of line vertices
+fn push_rect_outline(
+    vertices: &mut Vec<f32>,
+    rect: &Rect2D,
+    color: [f32; 3],
+    overlay_rect: &Rect2D,
+) {
+    let top_left = screen_to_gl(rect.x, rect.y, overlay_rect);
+    let top_right = screen_to_gl(rect.x + rect.width, rect.y, overlay_rect);
+    let bottom_right = screen_to_gl(rect.x + rect.width, rect.y + rect.height, overlay_rect);
+    let bottom_left = screen_to_gl(rect.x, rect.y + rect.height, overlay_rect);
+
+    for p in [
+        top_left,
+        top_right,
+        top_right,
+        bottom_right,
+        bottom_right,
+        bottom_left,
+        bottom_left,
+        top_left,
+    ]
+    .iter()
+    {
+        push_vertex(vertices, *p, color, 0.0, 1.0);
+    }
+}
+
+/// Add a line (in screen pixels) to a list of line vertices
+fn push_line(
+    vertices: &mut Vec<f32>,
+    (x0, y0): (f32, f32),
+    (x1, y1): (f32, f32),
+    color: [f32; 3],
+    overlay_rect: &Rect2D,
+) {
+    push_vertex(
+        vertices,
+        screen_to_gl(x0, y0, overlay_rect),
+        color,
+        0.0,
+        1.0,
+    );
+    push_vertex(
+        vertices,
+        screen_to_gl(x1, y1, overlay_rect),
+        color,
+        0.0,
+        1.0,
+    );
+}
+
+/// Add the rings expanding from the cursor, each fading out as it grows, in the brush color
+/// with a dark outline so they show on any background
+fn push_beacon(drawing: &mut DrawingState, input: &Input) {
+    drawing.beacon_vertices.clear();
+    let elapsed = match drawing.beacon_start {
+        Some(start) => start.elapsed(),
+        None => return,
+    };
+    let (x, y) = (input.cursor.x, input.cursor.y);
+    for i in 0..N_BEACON_RINGS {
+        let ring_elapsed = match elapsed.checked_sub(BEACON_RING_INTERVAL * i) {
+            Some(ring_elapsed) if ring_elapsed < BEACON_RING_DURATION => ring_elapsed,
+            _ => continue,
+        };
+        let progress = ring_elapsed.as_secs_f32() / BEACON_RING_DURATION.as_secs_f32();
+        // Fast at first, slowing down as it reaches its size
+        let radius = BEACON_RADIUS * (1.0 - (1.0 - progress).powi(2));
+        let opacity = 1.0 - progress;
+        for &(width, color) in [
+            (BEACON_RING_WIDTH + 4.0, CROSSHAIR_SHADOW_COLOR),
+            (BEACON_RING_WIDTH, drawing.line_style.color),
+        ]
+        .iter()
+        {
+            push_ring(
+                &mut drawing.beacon_vertices,
+                (x, y),
+                radius,
+                width,
+                color,
+                opacity,
+                &drawing.rect,
+            );
+        }
+    }
+}
 
-                input.cursor.last_x = input.cursor.x;
-                input.cursor.last_y = input.cursor.y;
-                input.cursor.x = touch_event.location.x as f32;
-                input.cursor.y = touch_event.location.y as f32;
+/// Add a ring (in screen pixels) to a list of triangle vertices, its edges smoothed
+fn push_ring(
+    vertices: &mut Vec<f32>,
+    (x, y): (f32, f32),
+    radius: f32,
+    width: f32,
+    color: [f32; 3],
+    opacity: f32,
+    overlay_rect: &Rect2D,
+) {
+    let inner = (radius - width / 2.0).max(0.0);
+    let outer = radius + width / 2.0;
+    let corner = |angle: f32, radius: f32| {
+        screen_to_gl(
+            x + angle.cos() * radius,
+            y + angle.sin() * radius,
+            overlay_rect,
+        )
+    };
+    for i in 0..N_BEACON_RING_SEGMENTS {
+        let a0 = i as f32 / N_BEACON_RING_SEGMENTS as f32 * 2.0 * PI;
+        let a1 = (i + 1) as f32 / N_BEACON_RING_SEGMENTS as f32 * 2.0 * PI;
+        let quad = [
+            (corner(a0, inner), -1.0),
+            (corner(a0, outer), 1.0),
+            (corner(a1, outer), 1.0),
+            (corner(a0, inner), -1.0),
+            (corner(a1, outer), 1.0),
+            (corner(a1, inner), -1.0),
+        ];
+        for &(p, edge) in quad.iter() {
+            push_vertex(vertices, p, color, edge, 0.5);
+            let n = vertices.len();
+            vertices[n - 1] = opacity;
+        }
+    }
+}
 
-                match touch_event.force {
-                    Some(force_type) => match force_type {
-                        glutin::event::Force::Calibrated {
-                            force,
-                            max_possible_force,
-                            altitude_angle: _,
-                        } => {
-                            drawing.line_style.pressure = (force / max_possible_force) as f32;
-                        }
-                        glutin::event::Force::Normalized(force) => {
-                            drawing.line_style.pressure = force as f32;
-                        }
-                    },
-                    None => (),
-                }
-            }
-            WindowEvent::CloseRequested => {
-                unsafe {
-                    gl::DeleteProgram(drawing.gl_context.program);
-                    gl::DeleteShader(drawing.gl_context.fs);
-                    gl::DeleteShader(drawing.gl_context.vs);
-                    gl::DeleteBuffers(1, &drawing.gl_context.vbo);
-                    gl::DeleteVertexArrays(1, &drawing.gl_context.vao);
-                }
-                *control_flow = ControlFlow::Exit
-            }
-            // Mouse pressed
-            // deprecated is for modifiers
-            #[allow(deprecated)]
-            WindowEvent::MouseInput {
-                device_id: _,
-                state,
-                button,
-                modifiers: _,
-            } => {
-                if button == MouseButton::Left {
-                    input.cursor.pressed = state == ElementState::Pressed;
+/// Redraw the rings expanding from the cursor until the last one is done
+fn update_beacon(drawing: &mut DrawingState, control_flow: &mut ControlFlow) {
+    let start = match drawing.beacon_start {
+        Some(start) => start,
+        None => return,
+    };
+    drawing.need_redraw = true;
+    let end = start + BEACON_RING_INTERVAL * (N_BEACON_RINGS - 1) + BEACON_RING_DURATION;
+    if Instant::now() >= end {
+        drawing.beacon_start = None;
+    } else {
+        wake_up_at(control_flow, Instant::now() + FRAME_INTERVAL);
+    }
+}
 
-                    if input.cursor.pressed == false {
-                        input.cursor.released_time = Some(SystemTime::now());
+/// Add horizontal and vertical guides through the cursor across the whole overlay, and the
+/// position of the cursor on the screen next to it
+fn push_crosshair(drawing: &mut DrawingState, input: &Input) {
+    let rect = &drawing.rect;
+    let vertices = &mut drawing.overlay_vertices;
+    let (x, y) = (input.cursor.x.round(), input.cursor.y.round());
 
-                        for _ in 0..drawing.line_style.smoothing_intensity {
-                            apply_line_smoothing(
-                                &mut drawing.vertices[drawing.smooth_index..],
-                                drawing.line_style.smoothing_range,
-                            );
-                        }
-                        drawing.smooth_index = drawing.vertices.len();
+    // Each line has a shadow so it shows over light and dark windows
+    for &(offset, color) in [(1.0, CROSSHAIR_SHADOW_COLOR), (0.0, CROSSHAIR_COLOR)].iter() {
+        push_line(
+            vertices,
+            (0.0, y + offset),
+            (rect.width, y + offset),
+            color,
+            rect,
+        );
+        push_line(
+            vertices,
+            (x + offset, 0.0),
+            (x + offset, rect.height),
+            color,
+            rect,
+        );
+    }
 
-                        drawing.need_redraw = true;
-                    }
-                }
-            }
-            // Mousewheel
-            // deprecated is for modifiers
-            #[allow(deprecated)]
-            WindowEvent::MouseWheel {
-                device_id: _,
-                delta,
-                phase,
-                modifiers: _,
-            } => {
-                if phase == TouchPhase::Moved {
-                    match delta {
-                        MouseScrollDelta::LineDelta(_x, y) => {
-                            drawing.need_redraw = true;
-
-                            drawing.line_style.width -= y;
-                            if drawing.line_style.width < 1.0 {
-                                drawing.line_style.width = 1.0;
-                            }
-                        }
-                        _ => (),
-                    }
-                }
-            }
-            // Mouse moved
-            // deprecated is for modifiers
-            #[allow(deprecated)]
-            WindowEvent::CursorMoved {
-                device_id: _,
-                position,
-                modifiers: _,
-            } => {
-                input.cursor.last_x = input.cursor.x;
-                input.cursor.last_y = input.cursor.y;
-                input.cursor.x = position.x as f32;
-                input.cursor.y = position.y as f32;
-                drawing.need_redraw = true;
-            }
-            _ => (),
-        },
-        _ => (),
+    if !drawing.config.crosshair_coordinates {
+        return;
+    }
+
+    // Coordinates from the top left of the screens, not of the overlay
+    let text = format!("{}, {}", (rect.x + x) as i64, (rect.y + y) as i64);
+    let width = drawing.text.text_width(&text, CROSSHAIR_TEXT_SIZE);
+    let height = drawing.text.line_height(CROSSHAIR_TEXT_SIZE);
+
+    // Below right of the cursor, flipped to stay inside the overlay near its edges
+    let mut text_x = x + CROSSHAIR_TEXT_OFFSET;
+    if text_x + width > rect.width {
+        text_x = x - CROSSHAIR_TEXT_OFFSET - width;
+    }
+    let mut text_y = y + CROSSHAIR_TEXT_OFFSET;
+    if text_y + height > rect.height {
+        text_y = y - CROSSHAIR_TEXT_OFFSET - height;
+    }
+
+    for &(offset, color) in [(1.0, CROSSHAIR_SHADOW_COLOR), (0.0, CROSSHAIR_COLOR)].iter() {
+        drawing.text.push_text(
+            &mut drawing.hud_text_vertices,
+            &text,
+            text_x + offset,
+            text_y + offset,
+            CROSSHAIR_TEXT_SIZE,
+            [color[0], color[1], color[2], 1.0],
+            rect,
+        );
+    }
+}
+
+/// Add the see-through lines of a grid covering the overlay to a list of line vertices
+fn push_grid(vertices: &mut Vec<f32>, size: f32, overlay_rect: &Rect2D) {
+    if size < 1.0 {
+        return;
+    }
+    let start = vertices.len();
+    let mut x = 0.0;
+    while x <= overlay_rect.width {
+        push_line(
+            vertices,
+            (x, 0.0),
+            (x, overlay_rect.height),
+            GRID_COLOR,
+            overlay_rect,
+        );
+        x += size;
+    }
+    let mut y = 0.0;
+    while y <= overlay_rect.height {
+        push_line(
+            vertices,
+            (0.0, y),
+            (overlay_rect.width, y),
+            GRID_COLOR,
+            overlay_rect,
+        );
+        y += size;
+    }
+    for vertex in vertices[start..].chunks_mut(VERTEX_SIZE) {
+        vertex[8] = GRID_OPACITY;
+    }
+}
+
+/// Show the size of the text next to the cursor, in the color of the text
+fn push_text_hud(drawing: &mut DrawingState, input: &Input) {
+    let label = format!("{} px", drawing.text_size.round());
+    let height = drawing.text.line_height(HUD_TEXT_SIZE);
+    let x = input.cursor.x + HUD_TEXT_OFFSET;
+    let y = input.cursor.y - HUD_TEXT_OFFSET - height;
+    let color = drawing.line_style.color;
+
+    for &(offset, color) in [(1.0, CROSSHAIR_SHADOW_COLOR), (0.0, color)].iter() {
+        drawing.text.push_text(
+            &mut drawing.hud_text_vertices,
+            &label,
+            x + offset,
+            y + offset,
+            HUD_TEXT_SIZE,
+            [color[0], color[1], color[2], 1.0],
+            &drawing.rect,
+        );
     }
 }
 
-fn redraw(drawing: &mut DrawingState, input: &Input, cursor_vertices: &mut Vec<f32>) {
-    let prev_cursor_gl_pos = Point {
-        x: input.cursor.last_x / drawing.rect.width * 2.0 - 1.0,
-        y: input.cursor.last_y / drawing.rect.height * -2.0 + 1.0,
-        z: 0.0,
+/// Show the frame rate, latency and GPU uploads of the last frames in the top left corner
+fn push_stats_hud(drawing: &mut DrawingState) {
+    let stats = &drawing.stats;
+    let (buffers, line) = (&drawing.stroke_buffers, &drawing.line_buffers);
+    let n_vertices = (buffers.vertices.len()
+        + line.vertices.len()
+        + drawing.overlay_vertices.len()
+        + drawing.beacon_vertices.len()
+        + drawing.grid_vertices.len())
+        / VERTEX_SIZE
+        + N_CURSOR_RETICLE_POINTS * 2;
+    let n_line_points = (buffers.line_points.len() + line.line_points.len()) / LINE_POINT_SIZE;
+    let lines: [String; N_STATS_HUD_LINES] = [
+        format!("{} fps", stats.frame_times.len()),
+        format!("Latency {:.1} ms", stats.latency.as_secs_f32() * 1000.0),
+        format!("{} vertices, {} line points", n_vertices, n_line_points),
+        format!(
+            "Stroke buffer {} KB",
+            mem::size_of_val(&buffers.vertices[..]) / 1024
+                + mem::size_of_val(&buffers.line_points[..]) / 1024
+        ),
+        format!("Uploaded {} KB per frame", stats.upload_bytes / 1024),
+    ];
+    push_hud_lines(drawing, &lines, 0);
+}
+
+/// Show the session statistics in the top left corner, below the diagnostics if shown
+fn push_session_report(drawing: &mut DrawingState) {
+    let lines = session_report(&drawing.session_stats);
+    let first_row = if drawing.is_stats_visible {
+        N_STATS_HUD_LINES + 1
+    } else {
+        0
     };
-    let cursor_gl_pos = Point {
-        x: input.cursor.x / drawing.rect.width * 2.0 - 1.0,
-        y: input.cursor.y / drawing.rect.height * -2.0 + 1.0,
-        z: 0.0,
+    push_hud_lines(drawing, &lines, first_row);
+}
+
+/// Lines of text in the top left corner, from a row of HUD text
+fn push_hud_lines(drawing: &mut DrawingState, lines: &[String], first_row: usize) {
+    let line_height = drawing.text.line_height(HUD_TEXT_SIZE);
+    for (i, line) in lines.iter().enumerate() {
+        let y = TIMER_TEXT_MARGIN + (first_row + i) as f32 * line_height;
+        for &(offset, color) in [(1.0, CROSSHAIR_SHADOW_COLOR), (0.0, [1.0, 1.0, 1.0])].iter() {
+            drawing.text.push_text(
+                &mut drawing.hud_text_vertices,
+                line,
+                TIMER_TEXT_MARGIN + offset,
+                y + offset,
+                HUD_TEXT_SIZE,
+                [color[0], color[1], color[2], 1.0],
+                &drawing.rect,
+            );
+        }
+    }
+}
+
+/// Remember when a frame was presented and how much it sent to the GPU
+fn record_frame(drawing: &mut DrawingState, event_time: Instant, cursor_vertices: &[f32]) {
+    let now = Instant::now();
+    let stats = &mut drawing.stats;
+    stats.frame_times.push_back(now);
+    while stats
+        .frame_times
+        .front()
+        .is_some_and(|&time| now - time > Duration::from_secs(1))
+    {
+        stats.frame_times.pop_front();
+    }
+    stats.latency = now - event_time;
+    stats.upload_bytes = if drawing.is_window_hidden {
+        0
+    } else {
+        mem::size_of_val(cursor_vertices)
+            + mem::size_of_val(&drawing.stroke_buffers.vertices[..])
+            + mem::size_of_val(&drawing.stroke_buffers.line_points[..])
+            + mem::size_of_val(&drawing.line_buffers.vertices[..])
+            + mem::size_of_val(&drawing.line_buffers.line_points[..])
+            + mem::size_of_val(&drawing.overlay_vertices[..])
+            + mem::size_of_val(&drawing.beacon_vertices[..])
+            + mem::size_of_val(&drawing.grid_vertices[..])
+            + mem::size_of_val(&drawing.text_vertices[..])
+            + mem::size_of_val(&drawing.hud_text_vertices[..])
     };
+}
 
-    let cursor_gl_size = screen_size_to_gl(
-        drawing.line_style.width,
-        drawing.line_style.width,
-        &drawing.rect,
-    );
-    let cursor_outline_gl_size = screen_size_to_gl(
-        drawing.line_style.width + 1.0,
-        drawing.line_style.width + 1.0,
-        &drawing.rect,
+/// Whether the GPU was reset since the context was created, after a driver crash or a resume
+/// from sleep
+fn is_gl_context_reset() -> bool {
+    // Without the robustness extension, resets can't be detected before the buffers are swapped
+    gl::GetGraphicsResetStatus::is_loaded()
+        && unsafe { gl::GetGraphicsResetStatus() } != gl::NO_ERROR
+}
+
+/// Replace a lost OpenGL context by a new window and context. Every buffer is uploaded again at
+/// each frame from the strokes, so only the GL objects need to be created again.
+fn rebuild_gl_context(
+    drawing: &mut DrawingState,
+    event_loop: &EventLoopWindowTarget<instance::Command>,
+) {
+    log::warn!("The OpenGL context was lost, creating it again");
+    // The objects of the old context are gone with it, they mustn't be deleted from the new one
+    drawing.gl_context = init_gl_window(event_loop, &drawing.rect, &drawing.config, false);
+    drawing.text = text::TextRenderer::new(drawing.config.font_path.as_deref());
+    drawing.images = images::ImageRenderer::new();
+    drawing.patterns = patterns::PatternRenderer::new();
+    // A new context may not expand lines like the lost one, their triangles are made again
+    let was_freehand_on_gpu = drawing.lines.is_some();
+    drawing.lines = init_line_renderer(&drawing.gl_context, &drawing.config);
+    let freehand_on_gpu = drawing.lines.is_some();
+    if freehand_on_gpu != was_freehand_on_gpu {
+        let rect = &drawing.rect;
+        for stroke in drawing.strokes.iter_mut() {
+            Rc::make_mut(stroke).tessellate(rect, freehand_on_gpu);
+        }
+        for stroke in drawing.remote_strokes.values_mut().flatten() {
+            stroke.tessellate(rect, freehand_on_gpu);
+        }
+        drawing.strokes_changed = true;
+    }
+    drawing.is_context_lost = false;
+    update_click_through(drawing);
+}
+
+/// Expand freehand lines on the GPU when the context can, unless the config replaces the vertex
+/// shader of the strokes, which only sees their triangles
+fn init_line_renderer(gl_state: &GLState, config: &Config) -> Option<lines::LineRenderer> {
+    if config.vertex_shader_path.is_some() {
+        None
+    } else {
+        lines::LineRenderer::new(gl_state.fs)
+    }
+}
+
+/// Upload vertices of the stroke layout (see VERTEX_SIZE) to the vbo and draw them
+unsafe fn draw_vertices(vertices: &[f32], mode: GLenum) {
+    if vertices.is_empty() {
+        return;
+    }
+    gl::BufferData(
+        gl::ARRAY_BUFFER,
+        mem::size_of_val(vertices) as GLsizeiptr,
+        vertices.as_ptr() as *const GLvoid,
+        gl::STATIC_DRAW,
     );
+    gl::DrawArrays(mode, 0, (vertices.len() / VERTEX_SIZE) as i32);
+}
+
+fn redraw(drawing: &mut DrawingState, input: &mut Input, cursor_vertices: &mut [f32]) {
+    if is_gl_context_reset() {
+        drawing.is_context_lost = true;
+        return;
+    }
+
+    let cursor_gl_pos = screen_to_gl(input.cursor.x, input.cursor.y, &drawing.rect);
+
+    let tool_handler = tools::handler(drawing.tool);
+    let cursor_radius = tool_handler.reticle_radius(drawing);
+    let cursor_gl_size = screen_size_to_gl(cursor_radius, cursor_radius, &drawing.rect);
+    let cursor_outline_gl_size =
+        screen_size_to_gl(cursor_radius + 1.0, cursor_radius + 1.0, &drawing.rect);
+
+    let reticle_color = tool_handler.reticle_color(drawing);
 
     // Cursor circle overlay
     for i in 0..N_CURSOR_RETICLE_POINTS {
         let angle = (i as f32) / (N_CURSOR_RETICLE_POINTS as f32) * (2.0 * PI);
-        cursor_vertices[i * 6 + 0] = cursor_gl_pos.x + (angle.cos() * cursor_gl_size.width);
-        cursor_vertices[i * 6 + 1] = cursor_gl_pos.y + (angle.sin() * cursor_gl_size.height);
-        // skip z  [i * 6 + 2]
-        cursor_vertices[i * 6 + 3] = drawing.line_style.color[0];
-        cursor_vertices[i * 6 + 4] = drawing.line_style.color[1];
-        cursor_vertices[i * 6 + 5] = drawing.line_style.color[2];
+        let v = i * VERTEX_SIZE;
+        cursor_vertices[v] = cursor_gl_pos.x + (angle.cos() * cursor_gl_size.width);
+        cursor_vertices[v + 1] = cursor_gl_pos.y + (angle.sin() * cursor_gl_size.height);
+        // skip z  [v + 2]
+        cursor_vertices[v + 3] = reticle_color[0];
+        cursor_vertices[v + 4] = reticle_color[1];
+        cursor_vertices[v + 5] = reticle_color[2];
+        // skip edge, hardness and opacity [v + 6], [v + 7], [v + 8]
     }
     // // Cursor circle outline
     for i in N_CURSOR_RETICLE_POINTS..(N_CURSOR_RETICLE_POINTS * 2) {
         let angle = (i as f32) / (N_CURSOR_RETICLE_POINTS as f32) * (2.0 * PI);
-        cursor_vertices[i * 6 + 0] = cursor_gl_pos.x + (angle.cos() * cursor_outline_gl_size.width);
-        cursor_vertices[i * 6 + 1] =
-            cursor_gl_pos.y + (angle.sin() * cursor_outline_gl_size.height);
-        // skip z  [i * 6 + 2]
-        cursor_vertices[i * 6 + 3] = 0.0;
-        cursor_vertices[i * 6 + 4] = 0.0;
-        cursor_vertices[i * 6 + 5] = 0.0;
+        let v = i * VERTEX_SIZE;
+        cursor_vertices[v] = cursor_gl_pos.x + (angle.cos() * cursor_outline_gl_size.width);
+        cursor_vertices[v + 1] = cursor_gl_pos.y + (angle.sin() * cursor_outline_gl_size.height);
+        // skip z  [v + 2]
+        cursor_vertices[v + 3] = 0.0;
+        cursor_vertices[v + 4] = 0.0;
+        cursor_vertices[v + 5] = 0.0;
+        // skip edge, hardness and opacity [v + 6], [v + 7], [v + 8]
     }
 
-    if !input.cursor.pressed || drawing.is_window_hidden {
+    if !input.cursor.pressed || drawing.is_window_hidden || is_mirroring(drawing) {
         drawing.n_points_current_line = 0;
+    } else if drawing.stroke_drag.is_some() {
+        drag_strokes(drawing, input);
     } else {
-        /*
-        Each line segment is formed of 2 triangles that form a quad
-
-        p3 __ p4    - old cursor position
-          |\ |
-          | \|
-        p1 ¯¯ p2    - new cursor position
-
-        p1: current cursor position - line width
-        p2: current cursor position + line width
-        p3: previous cursor position - line width
-        p4: previous cursor position + line width
-
-        The cursor position is always between the two points
-        p3 ____ old cursor ____ p4
-          |                    |
-          |                    |
-          |                    |
-          |                    |
-        p1¯¯¯¯¯ new cursor ¯¯¯¯ p2
-        */
-
-        // Angle in radians of the line to draw
-        // Will be wrong if it's the first vertex since prev_positions isn't defined
-        // but we recalculate it before drawing when we get the second vertex
-        let angle =
-            (cursor_gl_pos.y - prev_cursor_gl_pos.y).atan2(cursor_gl_pos.x - prev_cursor_gl_pos.x);
-
-        // New line segment, add an undo point
-        if drawing.n_points_current_line == 0
-            && (input.cursor.released_time.is_none()
-                || input
-                    .cursor
-                    .released_time
-                    .unwrap()
-                    .elapsed()
-                    .unwrap()
-                    .as_millis()
-                    > 200)
-        {
-            drawing.undo_steps.push(drawing.vertices.len());
+        if drawing.n_points_current_line == 0 {
+            drawing.session_stats.line_start = Some(Instant::now());
+            drawing.is_ephemeral_line = drawing.tool == Tool::Brush
+                && drawing
+                    .config
+                    .ephemeral_modifier
+                    .is_some_and(|key| input.modifiers.is_held(key));
+        }
+        tool_handler.drag(drawing, input);
+    }
+    input.cursor.samples.clear();
+
+    // The line being drawn has its own buffers, so each point only adds to them instead of
+    // gathering every stroke again. It stays over the other strokes until it ends
+    let n_strokes = drawing.strokes.len();
+    let (strokes, line) = if drawing.n_points_current_line > 0 && drawing.tool == Tool::Brush {
+        drawing.strokes.split_at(n_strokes.saturating_sub(1))
+    } else {
+        (&drawing.strokes[..], &[][..])
+    };
+    let fade = auto_clear_fade(drawing);
+    let freehand_on_gpu = drawing.lines.is_some();
+    let opacity = |stroke: &Stroke| if stroke.locked { 1.0 } else { fade };
+    if drawing.strokes_changed {
+        drawing.strokes_changed = false;
+        drawing.line_changed = true;
+        drawing.stroke_buffers.clear();
+        // The halos all go under the strokes, so they never cover ink
+        if drawing.config.stroke_outlines {
+            for stroke in strokes {
+                if let Some(mut outline) = outline_stroke(stroke) {
+                    outline.tessellate(&drawing.rect, freehand_on_gpu);
+                    drawing
+                        .stroke_buffers
+                        .push(&outline, opacity(stroke), freehand_on_gpu);
+                }
+            }
+        }
+        for stroke in strokes {
+            drawing
+                .stroke_buffers
+                .push(stroke, opacity(stroke), freehand_on_gpu);
+        }
+        let now = Instant::now();
+        for (stroke, fade_time) in &drawing.ephemeral_strokes {
+            let fade = 1.0
+                - now.saturating_duration_since(*fade_time).as_secs_f32()
+                    / EPHEMERAL_FADE.as_secs_f32();
+            drawing
+                .stroke_buffers
+                .push(stroke, fade.max(0.0), freehand_on_gpu);
+        }
+        // The strokes of the other peers of the session go over the local ones, unfaded
+        for stroke in drawing.remote_strokes.values().flatten() {
+            drawing.stroke_buffers.push(stroke, 1.0, freehand_on_gpu);
+        }
+    }
+    if drawing.line_changed {
+        drawing.line_changed = false;
+        drawing.line_buffers.clear();
+        for stroke in line {
+            if drawing.config.stroke_outlines {
+                if let Some(mut outline) = outline_stroke(stroke) {
+                    outline.tessellate(&drawing.rect, freehand_on_gpu);
+                    drawing
+                        .line_buffers
+                        .push(&outline, opacity(stroke), freehand_on_gpu);
+                }
+            }
+            drawing
+                .line_buffers
+                .push(stroke, opacity(stroke), freehand_on_gpu);
         }
+    }
 
-        // update line width in gl scale
-        let line_gl_size = screen_size_to_gl(
-            drawing.line_style.width * drawing.line_style.pressure,
-            drawing.line_style.width * drawing.line_style.pressure,
+    drawing.grid_vertices.clear();
+    if grid_pattern(drawing) == Some(patterns::GridPattern::Lines) {
+        push_grid(
+            &mut drawing.grid_vertices,
+            drawing.config.grid_size,
             &drawing.rect,
         );
+    }
 
-        // Previous triangles ending points (old p1.x, p1.y, p2.x, p2.y)
-        let mut prev_p1 = Point {
-            x: 0.0,
-            y: 0.0,
-            z: 0.0,
-        };
-        let mut prev_p2 = Point {
-            x: 0.0,
-            y: 0.0,
-            z: 0.0,
-        };
-
-        // If it's the second vertex of the line segment,
-        // we need to recalculate the width of the first vertex since
-        // we didnt know the angle yet
-        if drawing.n_points_current_line == 1 {
-            prev_p1.x = prev_cursor_gl_pos.x + (angle - FRAC_PI_2).cos() * line_gl_size.width;
-            prev_p1.y = prev_cursor_gl_pos.y + (angle - FRAC_PI_2).sin() * line_gl_size.height;
-            prev_p2.x = prev_cursor_gl_pos.x + (angle + FRAC_PI_2).cos() * line_gl_size.width;
-            prev_p2.y = prev_cursor_gl_pos.y + (angle + FRAC_PI_2).sin() * line_gl_size.height;
-        // Get the previous positions from the list of vertices
-        } else if drawing.n_points_current_line > 1 {
-            prev_p1.x = drawing.vertices[drawing.vertices.len() - 24];
-            prev_p1.y = drawing.vertices[drawing.vertices.len() - 23];
-            prev_p2.x = drawing.vertices[drawing.vertices.len() - 12];
-            prev_p2.y = drawing.vertices[drawing.vertices.len() - 11];
-        }
-
-        // point to the left of the cursor
-        let p1 = Point {
-            x: cursor_gl_pos.x + (angle - FRAC_PI_2).cos() * line_gl_size.width,
-            y: cursor_gl_pos.y + (angle - FRAC_PI_2).sin() * line_gl_size.height,
-            z: 0.0,
-        };
-
-        // point to the right of the cursor
-        let p2 = Point {
-            x: cursor_gl_pos.x + (angle + FRAC_PI_2).cos() * line_gl_size.width,
-            y: cursor_gl_pos.y + (angle + FRAC_PI_2).sin() * line_gl_size.height,
-            z: 0.0,
-        };
+    // Text annotations are laid out again each frame, their glyphs may have left the atlas
+    drawing.text_vertices.clear();
 
-        // same position as previous p1
-        let p3 = if drawing.n_points_current_line > 0 {
-            prev_p1.clone()
-        } else {
-            // create 0 height rect for first line segment
-            p1.clone()
-        };
+    // The guide template is in screen coordinates, not overlay ones
+    if let Some(rects) = &drawing.guide_template {
+        for guide in rects {
+            let rect = Rect2D {
+                x: guide.x - drawing.rect.x,
+                y: guide.y - drawing.rect.y,
+                width: guide.width,
+                height: guide.height,
+            };
+            let color = color_to_gl(guide.color);
+            push_rect_outline(&mut drawing.grid_vertices, &rect, color, &drawing.rect);
+            if let Some(label) = &guide.label {
+                drawing.text.push_text(
+                    &mut drawing.text_vertices,
+                    label,
+                    rect.x + HUD_TEXT_OFFSET / 2.0,
+                    rect.y + HUD_TEXT_OFFSET / 2.0,
+                    GUIDE_LABEL_SIZE,
+                    [color[0], color[1], color[2], 1.0],
+                    &drawing.rect,
+                );
+            }
+        }
+    }
+    let fade = auto_clear_fade(drawing);
+    let local_strokes = drawing
+        .strokes
+        .iter()
+        .map(|stroke| (stroke.as_ref(), false));
+    let remote_strokes = drawing.remote_strokes.values().flatten();
+    for (stroke, is_remote) in local_strokes.chain(remote_strokes.map(|stroke| (stroke, true))) {
+        if let (Shape::Text { size }, Some(origin)) = (stroke.shape, stroke.points.first()) {
+            let line_height = drawing.text.line_height(size);
+            let opacity = if stroke.locked || is_remote {
+                1.0
+            } else {
+                fade
+            };
+            let color = [stroke.color[0], stroke.color[1], stroke.color[2], opacity];
+            for (i, line) in stroke.text.split('\n').enumerate() {
+                drawing.text.push_text(
+                    &mut drawing.text_vertices,
+                    line,
+                    origin.x,
+                    origin.y + i as f32 * line_height,
+                    size,
+                    color,
+                    &drawing.rect,
+                );
+            }
+        }
+    }
 
-        // same position as previous p2
-        let p4 = if drawing.n_points_current_line > 0 {
-            prev_p2.clone()
-        } else {
-            // create 0 height rect for first line segment
-            p2.clone()
+    // Selection box and selection rectangle
+    drawing.overlay_vertices.clear();
+    drawing.hud_text_vertices.clear();
+    push_beacon(drawing, input);
+    if let Some(bounds) = selection_bounds(drawing) {
+        push_rect_outline(
+            &mut drawing.overlay_vertices,
+            &bounds,
+            SELECTION_COLOR,
+            &drawing.rect,
+        );
+    }
+    if let Some((x, y)) = drawing.selection_marquee {
+        let marquee = Rect2D {
+            x: x.min(input.cursor.x),
+            y: y.min(input.cursor.y),
+            width: (input.cursor.x - x).abs(),
+            height: (input.cursor.y - y).abs(),
         };
+        push_rect_outline(
+            &mut drawing.overlay_vertices,
+            &marquee,
+            SELECTION_COLOR,
+            &drawing.rect,
+        );
+    }
+    if drawing.is_interacting {
+        for i in 0..INTERACT_MODE_BORDER_WIDTH {
+            let inset = i as f32;
+            let border = Rect2D {
+                x: inset,
+                y: inset,
+                width: drawing.rect.width - inset * 2.0 - 1.0,
+                height: drawing.rect.height - inset * 2.0 - 1.0,
+            };
+            push_rect_outline(
+                &mut drawing.overlay_vertices,
+                &border,
+                INTERACT_MODE_COLOR,
+                &drawing.rect,
+            );
+        }
+    }
+    if drawing.is_crosshair_visible {
+        push_crosshair(drawing, input);
+    }
 
-        // Triangle 3-2-1
-        // 3
-        drawing.vertices.extend(&(&p3).into_array());
-        drawing.vertices.extend(&drawing.line_style.color);
-
-        // 2
-        drawing.vertices.extend(&(&p2).into_array());
-        drawing.vertices.extend(&drawing.line_style.color);
+    if drawing.tool == Tool::Text {
+        push_text_hud(drawing, input);
+    }
 
-        // 1
-        drawing.vertices.extend(&(&p1).into_array());
-        drawing.vertices.extend(&drawing.line_style.color);
+    push_timers(drawing);
 
-        // Triangle 3-2-4
-        // 3
-        drawing.vertices.extend(&(&p3).into_array());
-        drawing.vertices.extend(&drawing.line_style.color);
+    if drawing.is_stats_visible {
+        push_stats_hud(drawing);
+    }
+    if drawing.is_session_report_visible {
+        push_session_report(drawing);
+    }
 
-        // 2
-        drawing.vertices.extend(&(&p2).into_array());
-        drawing.vertices.extend(&drawing.line_style.color);
+    // Caret of the text being typed
+    let strokes = &drawing.strokes;
+    let edited = drawing
+        .text_edit
+        .as_ref()
+        .and_then(|edit| Some((edit, strokes.get(edit.index)?)));
+    if let Some((edit, stroke)) = edited {
+        if let (Shape::Text { size }, Some(origin)) = (stroke.shape, stroke.points.first()) {
+            let (line, column) = caret_line(&stroke.text, edit.caret);
+            let before_caret: String = stroke
+                .text
+                .split('\n')
+                .nth(line)
+                .unwrap_or_default()
+                .chars()
+                .take(column)
+                .collect();
+            let x = origin.x + drawing.text.text_width(&before_caret, size);
+            let line_height = drawing.text.line_height(size);
+            let y = origin.y + line as f32 * line_height;
+            push_line(
+                &mut drawing.overlay_vertices,
+                (x, y),
+                (x, y + line_height),
+                stroke.color,
+                &drawing.rect,
+            );
+        }
+    }
 
-        // 4
-        drawing.vertices.extend(&(&p4).into_array());
-        drawing.vertices.extend(&drawing.line_style.color);
+    // Square filling up around the cursor while it dwells
+    if let Some(progress) = dwell_progress(drawing, input) {
+        let half_size = DWELL_INDICATOR_SIZE * progress;
+        let indicator = Rect2D {
+            x: input.cursor.x - half_size,
+            y: input.cursor.y - half_size,
+            width: half_size * 2.0,
+            height: half_size * 2.0,
+        };
+        push_rect_outline(
+            &mut drawing.overlay_vertices,
+            &indicator,
+            SELECTION_COLOR,
+            &drawing.rect,
+        );
+    }
 
-        drawing.n_points_current_line += 1;
+    // Show the end of a line the next shape corner would connect to
+    let is_shape_tool = matches!(drawing.tool, Tool::Arrow | Tool::HighlightBox);
+    if is_shape_tool && drawing.stroke_drag.is_none() {
+        if let Some((x, y)) = nearest_endpoint(drawing, input.cursor.x, input.cursor.y) {
+            let indicator = Rect2D {
+                x: x - ENDPOINT_INDICATOR_SIZE,
+                y: y - ENDPOINT_INDICATOR_SIZE,
+                width: ENDPOINT_INDICATOR_SIZE * 2.0,
+                height: ENDPOINT_INDICATOR_SIZE * 2.0,
+            };
+            push_rect_outline(
+                &mut drawing.overlay_vertices,
+                &indicator,
+                SELECTION_COLOR,
+                &drawing.rect,
+            );
+        }
     }
 
     if drawing.is_window_hidden {
@@ -926,57 +5578,145 @@ fn redraw(drawing: &mut DrawingState, input: &Input, cursor_vertices: &mut Vec<f
         // GL Draw Phase
         unsafe {
             // Start by clearing everything from last frame
-            // ClearColor has to come BEFORE Clear
-            if drawing.is_background_visible {
-                let bg_color_gl = color_to_gl(drawing.config.background_color);
-                gl::ClearColor(
-                    bg_color_gl[0],
-                    bg_color_gl[1],
-                    bg_color_gl[2],
-                    drawing.config.background_color_opacity,
-                );
+            let bg_color_gl = color_to_gl(drawing.config.background_color);
+            if drawing.flash_end.is_some() {
+                // Timer phase changes flash the opaque background
+                clear_to(bg_color_gl, 1.0);
+            } else if drawing.is_background_visible {
+                clear_to(bg_color_gl, drawing.config.background_color_opacity);
             } else {
-                gl::ClearColor(0.0, 0.0, 0.0, 0.0);
+                clear_to([0.0, 0.0, 0.0], 0.0);
             }
-            gl::Clear(gl::COLOR_BUFFER_BIT);
 
-            // Draw cursor reticle
-            gl::BufferData(
-                gl::ARRAY_BUFFER,
-                (cursor_vertices.len() * mem::size_of::<GLfloat>()) as GLsizeiptr,
-                mem::transmute(&cursor_vertices[0]),
-                gl::STATIC_DRAW,
-            );
+            // The background image is under everything, on the background color
+            if let Some(background) = &drawing.background_image {
+                if drawing.is_background_visible && drawing.flash_end.is_none() {
+                    let opacity = drawing.config.background_color_opacity;
+                    // Covering images are cut at the edges of their monitor
+                    gl::Enable(gl::SCISSOR_TEST);
+                    for (monitor, stroke) in &background.placements {
+                        gl::Scissor(
+                            monitor.x as i32,
+                            (drawing.rect.height - monitor.y - monitor.height) as i32,
+                            monitor.width as i32,
+                            monitor.height as i32,
+                        );
+                        drawing.images.draw(Some((stroke, opacity)), &drawing.rect);
+                    }
+                    gl::Disable(gl::SCISSOR_TEST);
+                }
+            }
 
-            gl::LineWidth(3.0);
-            gl::DrawArrays(gl::LINE_LOOP, 0, N_CURSOR_RETICLE_POINTS as i32);
-            gl::LineWidth(1.0);
-            gl::DrawArrays(
-                gl::LINE_LOOP,
-                N_CURSOR_RETICLE_POINTS as i32,
-                N_CURSOR_RETICLE_POINTS as i32,
-            );
+            let gl_state = &drawing.gl_context;
+            if gl_state.time_uniform != -1 {
+                let time = gl_state.start_time.elapsed().as_secs_f32();
+                gl::Uniform1f(gl_state.time_uniform, time);
+            }
+            if gl_state.resolution_uniform != -1 {
+                gl::Uniform2f(
+                    gl_state.resolution_uniform,
+                    drawing.rect.width,
+                    drawing.rect.height,
+                );
+            }
 
-            if drawing.vertices.len() > 0 {
-                // copy the vertices to the vertex buffer
-                gl::BufferData(
-                    gl::ARRAY_BUFFER,
-                    (drawing.vertices.len() * mem::size_of::<GLfloat>()) as GLsizeiptr,
-                    mem::transmute(&drawing.vertices[0]),
-                    gl::STATIC_DRAW,
+            draw_vertices(&drawing.grid_vertices, gl::LINES);
+            if let Some(pattern) = grid_pattern(drawing) {
+                drawing.patterns.draw(
+                    pattern,
+                    drawing.config.grid_size.max(4.0),
+                    GRID_COLOR,
+                    GRID_OPACITY,
+                    &drawing.rect,
                 );
+            }
 
-                // Draw lines using triangles to draw quads
-                // Divide by 6 since each vertex has 3 floats for pos + 3 for color
-                let n_line_vertices = drawing.vertices.len() / 6;
-                if n_line_vertices > 0 {
-                    gl::DrawArrays(gl::TRIANGLES, 0, n_line_vertices as i32);
+            // Images are under the ink, so they can be annotated
+            let local_images = drawing.strokes.iter().map(|stroke| {
+                let opacity = if stroke.locked { 1.0 } else { fade };
+                (stroke.as_ref(), opacity)
+            });
+            let remote_images = drawing.remote_strokes.values().flatten();
+            // The live watermark is in the corner of the overlay, spanning all the screens
+            let watermark = match &drawing.watermark {
+                Some(watermark) if drawing.config.watermark_live => {
+                    let (x, y) =
+                        watermark.position(drawing.rect.width as u32, drawing.rect.height as u32);
+                    let corner = |x: f32, y: f32| StrokePoint {
+                        x,
+                        y,
+                        pressure: 1.0,
+                        time: 0.0,
+                    };
+                    let (x, y) = (x as f32, y as f32);
+                    let stroke = Stroke {
+                        points: vec![
+                            corner(x, y),
+                            corner(x + watermark.width as f32, y + watermark.height as f32),
+                        ],
+                        shape: Shape::Image,
+                        image: Some(watermark.png.clone()),
+                        ..Default::default()
+                    };
+                    Some((stroke, watermark.opacity))
                 }
+                _ => None,
+            };
+            let image_strokes = local_images
+                .chain(remote_images.map(|stroke| (stroke, 1.0)))
+                .filter(|(stroke, _)| stroke.shape == Shape::Image)
+                .chain(
+                    watermark
+                        .as_ref()
+                        .map(|(stroke, opacity)| (stroke, *opacity)),
+                );
+            drawing.images.draw(image_strokes, &drawing.rect);
+
+            // Drawing the circle points in pairs gives the eraser a dashed reticle
+            let reticle_mode = if is_eraser(drawing.tool) {
+                gl::LINES
+            } else {
+                gl::LINE_LOOP
+            };
+            let cursor_image = cursor_image_stroke(drawing, input);
+            if cursor_image.is_none() {
+                let (reticle_outline, reticle) =
+                    cursor_vertices.split_at(N_CURSOR_RETICLE_POINTS * VERTEX_SIZE);
+                gl::LineWidth(3.0);
+                draw_vertices(reticle_outline, reticle_mode);
+                gl::LineWidth(1.0);
+                draw_vertices(reticle, reticle_mode);
+            }
+
+            // Lines are quads, expanded from their centerline on the GPU or tessellated
+            let time = drawing.gl_context.start_time.elapsed().as_secs_f32();
+            drawing
+                .stroke_buffers
+                .draw(drawing.lines.as_ref(), &drawing.rect, time);
+            drawing
+                .line_buffers
+                .draw(drawing.lines.as_ref(), &drawing.rect, time);
+
+            drawing.text.draw(&drawing.text_vertices);
+
+            draw_vertices(&drawing.overlay_vertices, gl::LINES);
+            draw_vertices(&drawing.beacon_vertices, gl::TRIANGLES);
+
+            // The cursor image is over the ink, unlike the reticle
+            if let Some(stroke) = &cursor_image {
+                drawing.images.draw(Some((stroke, 1.0)), &drawing.rect);
             }
+            drawing.images.free_unused();
+
+            drawing.text.draw(&drawing.hud_text_vertices);
         }
     }
 
-    drawing.gl_context.window_context.swap_buffers().unwrap();
+    match drawing.gl_context.window_context.swap_buffers() {
+        Ok(()) => (),
+        Err(glutin::ContextError::ContextLost) => drawing.is_context_lost = true,
+        Err(e) => log::error!("Failed to present the frame: {}", e),
+    }
 }
 
 fn create_default_config_file() -> std::io::Result<String> {
@@ -998,60 +5738,374 @@ fn load_config() -> Config {
     }
     .expect("Failed to read from config file");
 
-    serde_json::from_str(&config_file_contents).unwrap()
+    match serde_json::from_str(&config_file_contents) {
+        Ok(config) => config,
+        Err(e) => {
+            log::error!("Failed to read config.json: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Restore the state of the last session, if it was saved
+fn load_state(drawing: &mut DrawingState) {
+    // A missing or unreadable state file just means starting from the config defaults
+    let state: State = match fs::read_to_string("state.json")
+        .ok()
+        .and_then(|contents| serde_json::from_str(&contents).ok())
+    {
+        Some(state) => state,
+        None => return,
+    };
+
+    if let Some(&color) = state
+        .color_index
+        .and_then(|index| drawing.config.brush_colors.get(index))
+    {
+        drawing.line_style.color = color_to_gl(color);
+    }
+    if let Some(width) = state.brush_width {
+        drawing.line_style.width = width.max(1.0);
+    }
+    drawing.is_background_visible = state.is_background_visible;
+    drawing.is_grid_visible = state.is_grid_visible;
+    if state.grid_pattern_index < drawing.config.grid_patterns.len() {
+        drawing.grid_pattern_index = state.grid_pattern_index;
+    }
+    drawing.tool = state.tool;
+}
+
+/// Save the settings changed during this session for the next launch
+fn save_state(drawing: &DrawingState) {
+    let state = State {
+        color_index: drawing
+            .config
+            .brush_colors
+            .iter()
+            .position(|&color| color_to_gl(color) == drawing.line_style.color),
+        brush_width: Some(drawing.line_style.width),
+        is_background_visible: drawing.is_background_visible,
+        is_grid_visible: drawing.is_grid_visible,
+        grid_pattern_index: drawing.grid_pattern_index,
+        tool: drawing.tool,
+    };
+
+    let state_json = serde_json::to_string_pretty(&state).expect("Failed to encode state");
+    if let Err(e) = fs::write("state.json", state_json) {
+        log::error!("Failed to save state: {}", e);
+    }
 }
 
 fn color_to_gl(color: [u32; 3]) -> [f32; 3] {
-    return [
+    [
         color[0] as f32 / 255.0,
         color[1] as f32 / 255.0,
         color[2] as f32 / 255.0,
-    ];
+    ]
 }
 
 fn main() {
+    logger::init();
+    let mut start_hidden = false;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--start-hidden" => start_hidden = true,
+            "--verbose" => logger::set_verbose(true),
+            // Read the config and state files from another directory
+            "--config-dir" => {
+                let dir = args.next().unwrap_or_else(|| {
+                    log::error!("Missing directory after --config-dir");
+                    std::process::exit(1);
+                });
+                if let Err(e) = env::set_current_dir(&dir) {
+                    log::error!("Failed to open the config directory {}: {}", dir, e);
+                    std::process::exit(1);
+                }
+            }
+            "install-autostart" | "uninstall-autostart" => {
+                let result = if arg == "install-autostart" {
+                    autostart::install()
+                } else {
+                    autostart::uninstall()
+                };
+                match result {
+                    Ok(()) => log::info!("Done"),
+                    Err(e) => {
+                        log::error!("Failed to {}: {}", arg, e);
+                        std::process::exit(1);
+                    }
+                }
+                return;
+            }
+            // Ask the running instance to export its strokes
+            "export" => {
+                if let Err(e) = instance::send("export") {
+                    log::error!("Failed to reach the running instance: {}", e);
+                    std::process::exit(1);
+                }
+                return;
+            }
+            // Ask the running instance to export the timeline of its strokes
+            "export-timeline" => {
+                if let Err(e) = instance::send("export-timeline") {
+                    log::error!("Failed to reach the running instance: {}", e);
+                    std::process::exit(1);
+                }
+                return;
+            }
+            // Ask the running instance to add the strokes of a file
+            "import" => {
+                let path = args.next().unwrap_or_else(|| {
+                    log::error!("Missing file after import");
+                    std::process::exit(1);
+                });
+                // The running instance may have another working directory
+                let path = fs::canonicalize(&path).unwrap_or_else(|e| {
+                    log::error!("Failed to open {}: {}", path, e);
+                    std::process::exit(1);
+                });
+                if let Err(e) = instance::send(&format!("import {}", path.display())) {
+                    log::error!("Failed to reach the running instance: {}", e);
+                    std::process::exit(1);
+                }
+                return;
+            }
+            // Ask the running instance to place a QR code of the rest of the arguments
+            "qr" => {
+                let text = args.collect::<Vec<_>>().join(" ");
+                if text.is_empty() {
+                    log::error!("Missing link or text after qr");
+                    std::process::exit(1);
+                }
+                if let Err(e) = instance::send(&format!("qr {}", text)) {
+                    log::error!("Failed to reach the running instance: {}", e);
+                    std::process::exit(1);
+                }
+                return;
+            }
+            _ => log::warn!("Unknown argument: {}", arg),
+        }
+    }
+
+    let listener = match instance::acquire() {
+        instance::Instance::First(listener) => listener,
+        instance::Instance::AlreadyRunning => return,
+    };
+
     let config = load_config();
-    let event_loop = glutin::event_loop::EventLoop::new();
+    PREMULTIPLIED_ALPHA.store(config.premultiplied_alpha, Ordering::Relaxed);
+    if config.log_file {
+        logger::open_file("inke.log");
+    }
+    log::debug!("Inke {}", env!("CARGO_PKG_VERSION"));
+    let start_hidden = start_hidden || config.start_hidden;
+    let event_loop = EventLoop::with_user_event();
+    if let Some(listener) = listener {
+        instance::listen(listener, event_loop.create_proxy());
+    }
+    if config.midi_input {
+        midi::listen(event_loop.create_proxy());
+    }
+    if config.gamepad_input {
+        gamepad::listen(event_loop.create_proxy());
+    }
+    // The server only runs with a token, any local program could use it otherwise
+    match (config.websocket_port, &config.websocket_token) {
+        (Some(port), Some(token)) if !token.is_empty() => {
+            let page = websocket::remote_page(&config.brush_colors, &config.brush_sizes);
+            websocket::listen(
+                port,
+                token.clone(),
+                config.websocket_network_wide,
+                page,
+                event_loop.create_proxy(),
+            )
+        }
+        (Some(_), _) => log::warn!("The WebSocket server needs a websocket_token in the config"),
+        _ => (),
+    }
+    #[cfg(target_os = "macos")]
+    status_item::add(event_loop.create_proxy());
     let overlay_rect = get_overlay_rect(event_loop.available_monitors());
-    let mut cursor_vertices = Vec::new(); // List of vertices sent to the vba. Each vertices is x, y, z, r, g, b (6 length)
+    let mut cursor_vertices = Vec::new(); // List of vertices sent to the vba. Each vertices is x, y, z, r, g, b, edge, hardness, opacity (9 length)
     let mut drawing = DrawingState {
-        need_redraw: true,            // Triggers a screen redraw when set to true
-        is_window_hidden: true,       // Hide the drawing while keeping focus
+        need_redraw: true,                // Triggers a screen redraw when set to true
+        is_context_lost: false, // The GPU was reset, the OpenGL context must be created again
+        is_capturing: false,    // The overlay stays clear while the screen below it is captured
+        proxy: event_loop.create_proxy(), // Background work reports back through it
+        is_window_hidden: true, // Hide the drawing while keeping focus
         is_background_visible: false, // Toggle background color overlay
-        n_points_current_line: 0,     // Number of points in the current line
-        vertices: Vec::new(), // List of vertices sent to the vba. Each vertices is x, y, z, r, g, b (6 length)
-        gl_context: init_gl_window(&event_loop, &overlay_rect),
+        n_points_current_line: 0, // Number of points in the current line
+        stroke_buffers: lines::StrokeBuffers::default(), // Triangles and line points of the strokes, see lines::StrokeBuffers
+        line_buffers: lines::StrokeBuffers::default(), // The line being drawn, updated without the other strokes
+        gl_context: init_gl_window(&event_loop, &overlay_rect, &config, start_hidden),
+        text: text::TextRenderer::new(config.font_path.as_deref()), // After the GL context is created
+        text_vertices: Vec::new(), // Quads of the text annotations, see text::TextRenderer
+        hud_text_vertices: Vec::new(), // Quads of the text drawn over everything
+        images: images::ImageRenderer::new(), // Textures of the dropped images
+        patterns: patterns::PatternRenderer::new(), // Grid patterns other than lines
+        lines: None,               // Set right after, from the GL context
         rect: overlay_rect,
         line_style: LineStyle {
-            color: color_to_gl(config.brush_colors[config.default_brush_color_index as usize]), // rgb of the line to draw. Also used by the cursor reticle
+            color: color_to_gl(
+                config
+                    .brush_colors
+                    .get(config.default_brush_color_index as usize)
+                    .copied()
+                    .unwrap_or([255, 255, 255]),
+            ), // rgb of the line to draw. Also used by the cursor reticle
             width: config.default_brush_size, // Line width to draw *in pixels*
-            pressure: 1.0,                    // Used by pen pressure to change the width
+            hardness: config.brush_hardness.clamp(0.0, 1.0), // Part of the width drawn without feathering
+            pressure: 1.0, // Used by pen pressure to change the width
+            smoothing_mode: config.smoothing_mode,
             smoothing_range: config.smoothing_range,
             smoothing_intensity: config.smoothing_intensity,
+            pressure_smoothing: config.pressure_smoothing.clamp(0.0, 0.95), // Part of the last pressure kept by the low-pass filter
+            smoothed_pressure: None, // Filtered pressure of the last point, None until a line starts
         },
-        undo_steps: Vec::new(), // List of indexes in vertex_data representing each possible undo steps
-        smooth_index: 0,
-        config: config,
+        tool: Tool::Brush,
+        recent_colors: Vec::new(), // Last used colors, the current one first
+        secondary_color: color_to_gl(
+            config
+                .brush_colors
+                .get(config.default_secondary_color_index as usize)
+                .copied()
+                .unwrap_or([10, 10, 10]),
+        ), // Swapped with the brush color with x
+        arrow_head: config.arrow_head, // Head of the next arrows drawn
+        strokes: Vec::new(),       // Lines drawn on the overlay, from bottom to top
+        strokes_changed: false,
+        line_changed: false, // Only the line being drawn changed since the last frame
+        undo_steps: Vec::new(), // Previous states of the strokes list, one per possible undo step
+        boards: HashMap::new(), // Drawings of the other virtual desktops
+        is_passing_through: false, // Clicks go to the windows below while the pass-through key is held
+        is_interacting: false,     // Clicks go to the windows below until the mode is toggled back
+        is_pushing_to_draw: false, // Drawing while the push-to-draw key is held
+        ink_clear_time: None,      // The ink fades out and is cleared then, after push-to-draw
+        is_ephemeral_line: false,  // The line being drawn fades out once released
+        ephemeral_strokes: Vec::new(), // Released ephemeral lines, with when they start fading out
+        is_crosshair_visible: false, // Guide lines across the overlay through the cursor
+        is_grid_visible: false,    // Grid lines drawn under the strokes
+        grid_pattern_index: 0,     // Pattern of the config the grid is drawn with
+        guide_template: None,      // Rectangles of the guide template file, while shown
+        workspace: None,           // Virtual desktop the current drawing belongs to
+        is_erasing: false,
+        eraser_size: config.default_eraser_size, // Radius erased around the cursor *in pixels*
+        stroke_drag: None,                       // Strokes moved with ctrl-drag or the select tool
+        color_pick: None, // Alt-press that may be a click taking the color of a stroke
+        selection: Vec::new(), // Indexes of the selected strokes
+        selection_marquee: None, // Start corner of the selection rectangle being dragged
+        axis_lock: None,  // Axis the current line is locked to while the modifier is held
+        text_edit: None,  // Text annotation being typed
+        text_size: config.default_text_size, // Size of new text annotations *in pixels*
+        last_activity: Instant::now(), // Last input, the drawing is cleared after a while without any
+        timer: None,                   // Work and break timer, started with o
+        flash_end: None,               // The background flashes until then at timer phase changes
+        stopwatch: None,               // Started and paused with k, reset with shift-k
+        counter_values: vec![0; config.counters.len()], // Value of each counter of the config, changed with ctrl-1 to 9
+        announced: (Tool::Brush, [0.0; 3], 0.0),        // Tool, color and size last announced
+        announce_time: None,                            // When the pending changes get announced
+        last_frame_cursor: None, // Cursor position at the last frame of the line being drawn
+        is_stats_visible: false, // Diagnostics shown in the top left corner, toggled with F3
+        stats: FrameStats::default(),
+        is_session_report_visible: false, // Session statistics, toggled with shift-F3
+        session_stats: SessionStats {
+            start: Instant::now(),
+            strokes: 0,
+            undos: 0,
+            drawing_time: Duration::ZERO,
+            line_start: None,
+            pages: 0,
+            drawn_boards: HashSet::new(),
+            next_redraw: Instant::now(),
+        },
+        last_transform_time: None,
+        next_group_id: 0,
+        session: None, // Connection to the other peers of a shared whiteboard
+        remote_strokes: BTreeMap::new(), // Strokes of the other peers, by peer number
+        shared_strokes: Vec::new(), // Strokes last sent to the other peers
+        last_share_time: Instant::now(),
+        viewer: None,             // Sends the drawing to the browsers of the viewer page
+        is_viewer_outdated: true, // The strokes changed since they were last sent to the viewer
+        watermark: None,          // Composited into the screenshots, after the text renderer
+        cursor_image: load_cursor_image(&config),
+        background_image: None, // Set right after, fitted to the monitors of the overlay
+        overlay_vertices: Vec::new(), // Selection lines drawn over the strokes, same layout as vertices
+        beacon_vertices: Vec::new(),  // Rings showing where the cursor is, drawn over everything
+        beacon_start: None,           // When the rings started expanding from the cursor, with m
+        grid_vertices: Vec::new(),    // Grid lines drawn under the strokes, same layout as vertices
+        config,
+    };
+
+    drawing.lines = init_line_renderer(&drawing.gl_context, &drawing.config);
+    drawing.background_image = load_background_image(
+        &drawing.config,
+        event_loop.available_monitors(),
+        &drawing.rect,
+    );
+    load_state(&mut drawing);
+    drawing.session = start_session(&drawing.config, event_loop.create_proxy());
+    // The viewer is seen from other computers, so it needs a token too
+    drawing.viewer = match (drawing.config.viewer_port, &drawing.config.viewer_token) {
+        (Some(port), Some(token)) if !token.is_empty() => viewer::listen(port, token.clone()),
+        (Some(_), _) => {
+            log::warn!("The viewer page needs a viewer_token in the config");
+            None
+        }
+        _ => None,
     };
+    drawing.watermark = watermark::Watermark::new(
+        drawing.config.watermark_image_path.as_deref(),
+        drawing.config.watermark_text.as_deref(),
+        drawing.config.watermark_size,
+        &drawing.text,
+        drawing.config.watermark_corner,
+        drawing.config.watermark_opacity,
+    )
+    .map(Arc::new);
+    drawing.announced = (
+        drawing.tool,
+        drawing.line_style.color,
+        drawing.line_style.width.round(),
+    );
+    drawing.recent_colors.push(drawing.line_style.color);
 
     // Initialize cursor reticle vertices
     // Position will be updated during event loop
     for _i in 0..N_CURSOR_RETICLE_POINTS * 2 {
-        // line vertex
-        cursor_vertices.push(0.0);
-        cursor_vertices.push(0.0);
-        cursor_vertices.push(0.0);
-        // color
-        cursor_vertices.extend(&drawing.line_style.color);
+        push_vertex(
+            &mut cursor_vertices,
+            Point::default(),
+            drawing.line_style.color,
+            0.0,
+            1.0,
+        );
     }
     let mut input: Input = Default::default();
 
-    event_loop.run(move |event, _, control_flow| {
+    // First event handled since the last frame, for the latency of the diagnostics
+    let mut first_event_time = None;
+    event_loop.run(move |event, event_loop, control_flow| {
+        first_event_time.get_or_insert_with(Instant::now);
+        // A single frame is drawn once all the pending events are handled, so a slow frame
+        // doesn't hold up the pen positions queued behind it
+        let is_frame_time = matches!(event, Event::MainEventsCleared);
         handle_event(event, control_flow, &mut drawing, &mut input);
 
-        if drawing.need_redraw {
+        if !is_frame_time {
+            return;
+        }
+        if drawing.need_redraw && !drawing.is_capturing {
             drawing.need_redraw = false;
-            redraw(&mut drawing, &input, &mut cursor_vertices);
+            redraw(&mut drawing, &mut input, &mut cursor_vertices);
+            if drawing.is_context_lost {
+                rebuild_gl_context(&mut drawing, event_loop);
+                redraw(&mut drawing, &mut input, &mut cursor_vertices);
+            }
+            let event_time = first_event_time.unwrap_or_else(Instant::now);
+            record_frame(&mut drawing, event_time, &cursor_vertices);
         }
+        first_event_time = None;
     });
 }
@@ -0,0 +1,143 @@
+// MIDI controller input, knobs and pads sent to the event loop as commands
+
+use glutin::event_loop::EventLoopProxy;
+
+use crate::instance::Command;
+
+/// Turn a channel message into a command, whatever its channel
+#[cfg_attr(target_os = "macos", allow(dead_code))]
+fn to_command(status: u8, data1: u8, data2: u8) -> Option<Command> {
+    match status & 0xF0 {
+        0xB0 => Some(Command::MidiControl(data1, data2)),
+        // A note on with no velocity is a note off
+        0x90 if data2 > 0 => Some(Command::MidiNote(data1)),
+        _ => None,
+    }
+}
+
+/// Read the first raw MIDI device of ALSA (/dev/snd/midiC*D*) on a thread of its own
+#[cfg(all(unix, not(target_os = "macos")))]
+pub fn listen(proxy: EventLoopProxy<Command>) {
+    use std::io::{BufReader, Read};
+
+    let mut devices: Vec<_> = match std::fs::read_dir("/dev/snd") {
+        Ok(entries) => entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| {
+                path.file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| name.starts_with("midiC"))
+            })
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    devices.sort();
+    let path = match devices.first() {
+        Some(path) => path.clone(),
+        None => {
+            log::warn!("No MIDI device found");
+            return;
+        }
+    };
+    let device = match std::fs::File::open(&path) {
+        Ok(device) => device,
+        Err(e) => {
+            log::error!("Failed to open the MIDI device {}: {}", path.display(), e);
+            return;
+        }
+    };
+
+    std::thread::spawn(move || {
+        // Devices may skip the status byte of messages repeating the last one (running status)
+        let mut status = 0;
+        let mut data = Vec::with_capacity(2);
+        for byte in BufReader::new(device).bytes().map_while(Result::ok) {
+            match byte {
+                // Clock and other real-time bytes can come between the bytes of a message
+                0xF8..=0xFF => continue,
+                0x80..=0xF7 => {
+                    status = byte;
+                    data.clear();
+                    continue;
+                }
+                _ => data.push(byte),
+            }
+            // Only the two data byte messages are used, system messages are skipped
+            let length = match status & 0xF0 {
+                0x80 | 0x90 | 0xA0 | 0xB0 | 0xE0 => 2,
+                0xC0 | 0xD0 => 1,
+                _ => {
+                    data.clear();
+                    continue;
+                }
+            };
+            if data.len() == length {
+                let command = to_command(status, data[0], *data.get(1).unwrap_or(&0));
+                data.clear();
+                if let Some(command) = command {
+                    // The event loop is gone, Inke is closing
+                    if proxy.send_event(command).is_err() {
+                        return;
+                    }
+                }
+            }
+        }
+    });
+}
+
+/// Open the first MIDI input device of winmm, its callback is called on a thread of its own
+#[cfg(target_os = "windows")]
+pub fn listen(proxy: EventLoopProxy<Command>) {
+    use winapi::shared::basetsd::DWORD_PTR;
+    use winapi::shared::minwindef::UINT;
+    use winapi::um::mmeapi::{midiInGetNumDevs, midiInOpen, midiInStart};
+    use winapi::um::mmsystem::{CALLBACK_FUNCTION, HMIDIIN, MMSYSERR_NOERROR, MM_MIM_DATA};
+
+    extern "system" fn callback(
+        _handle: HMIDIIN,
+        message: UINT,
+        instance: DWORD_PTR,
+        param: DWORD_PTR,
+        _time: DWORD_PTR,
+    ) {
+        if message != MM_MIM_DATA {
+            return;
+        }
+        // Short messages are packed as status | data1 << 8 | data2 << 16
+        let proxy = unsafe { &*(instance as *const EventLoopProxy<Command>) };
+        let [status, data1, data2, _] = (param as u32).to_le_bytes();
+        if let Some(command) = to_command(status, data1, data2) {
+            let _ = proxy.send_event(command);
+        }
+    }
+
+    unsafe {
+        if midiInGetNumDevs() == 0 {
+            log::warn!("No MIDI device found");
+            return;
+        }
+        // The device stays open while Inke runs, so the proxy is never freed
+        let instance = Box::into_raw(Box::new(proxy));
+        let mut handle: HMIDIIN = std::ptr::null_mut();
+        let result = midiInOpen(
+            &mut handle,
+            0,
+            callback as *const () as usize,
+            instance as usize,
+            CALLBACK_FUNCTION,
+        );
+        if result != MMSYSERR_NOERROR {
+            log::error!("Failed to open the MIDI device: error {}", result);
+            drop(Box::from_raw(instance));
+            return;
+        }
+        midiInStart(handle);
+    }
+}
+
+/// CoreMIDI isn't supported yet
+#[cfg(target_os = "macos")]
+pub fn listen(_proxy: EventLoopProxy<Command>) {
+    log::warn!("MIDI input isn't supported on Mac yet");
+}
@@ -0,0 +1,125 @@
+// Math notation typed as plain text sequences, replaced by their symbol once complete
+
+// Sequences replaced as soon as they are typed, <-> and <=> build on the replaced <- and <=
+const SYMBOLS: [(&str, char); 11] = [
+    ("←>", '↔'),
+    ("≤>", '⇔'),
+    ("->", '→'),
+    ("<-", '←'),
+    ("=>", '⇒'),
+    ("<=", '≤'),
+    (">=", '≥'),
+    ("!=", '≠'),
+    ("+-", '±'),
+    ("~=", '≈'),
+    ("...", '…'),
+];
+
+// Greek letters, typed with a backslash before their name
+const GREEK: [(&str, char); 34] = [
+    ("alpha", 'α'),
+    ("beta", 'β'),
+    ("gamma", 'γ'),
+    ("delta", 'δ'),
+    ("epsilon", 'ε'),
+    ("zeta", 'ζ'),
+    ("eta", 'η'),
+    ("theta", 'θ'),
+    ("iota", 'ι'),
+    ("kappa", 'κ'),
+    ("lambda", 'λ'),
+    ("mu", 'μ'),
+    ("nu", 'ν'),
+    ("xi", 'ξ'),
+    ("pi", 'π'),
+    ("rho", 'ρ'),
+    ("sigma", 'σ'),
+    ("tau", 'τ'),
+    ("upsilon", 'υ'),
+    ("phi", 'φ'),
+    ("chi", 'χ'),
+    ("psi", 'ψ'),
+    ("omega", 'ω'),
+    ("Gamma", 'Γ'),
+    ("Delta", 'Δ'),
+    ("Theta", 'Θ'),
+    ("Lambda", 'Λ'),
+    ("Xi", 'Ξ'),
+    ("Pi", 'Π'),
+    ("Sigma", 'Σ'),
+    ("Phi", 'Φ'),
+    ("Psi", 'Ψ'),
+    ("Omega", 'Ω'),
+    ("infinity", '∞'),
+];
+
+// Characters typed after ^ and their superscript
+const SUPERSCRIPTS: [(char, char); 17] = [
+    ('0', '⁰'),
+    ('1', '¹'),
+    ('2', '²'),
+    ('3', '³'),
+    ('4', '⁴'),
+    ('5', '⁵'),
+    ('6', '⁶'),
+    ('7', '⁷'),
+    ('8', '⁸'),
+    ('9', '⁹'),
+    ('+', '⁺'),
+    ('-', '⁻'),
+    ('=', '⁼'),
+    ('(', '⁽'),
+    (')', '⁾'),
+    ('n', 'ⁿ'),
+    ('i', 'ⁱ'),
+];
+
+// Characters typed after _ and their subscript
+const SUBSCRIPTS: [(char, char); 15] = [
+    ('0', '₀'),
+    ('1', '₁'),
+    ('2', '₂'),
+    ('3', '₃'),
+    ('4', '₄'),
+    ('5', '₅'),
+    ('6', '₆'),
+    ('7', '₇'),
+    ('8', '₈'),
+    ('9', '₉'),
+    ('+', '₊'),
+    ('-', '₋'),
+    ('=', '₌'),
+    ('(', '₍'),
+    (')', '₎'),
+];
+
+/// Sequence completed at the end of a text, as the number of characters it spans and the
+/// symbol replacing them
+pub fn completed_sequence(text: &str) -> Option<(usize, char)> {
+    let mut chars = text.chars().rev();
+    let last = chars.next()?;
+
+    // x^2 and x_2, one character at a time
+    let scripts = match chars.next() {
+        Some('^') => Some(&SUPERSCRIPTS[..]),
+        Some('_') => Some(&SUBSCRIPTS[..]),
+        _ => None,
+    };
+    if let Some(&(_, script)) = scripts.and_then(|s| s.iter().find(|&&(c, _)| c == last)) {
+        return Some((2, script));
+    }
+
+    for &(sequence, symbol) in SYMBOLS.iter() {
+        if text.ends_with(sequence) {
+            return Some((sequence.chars().count(), symbol));
+        }
+    }
+
+    for &(name, letter) in GREEK.iter() {
+        if text.ends_with(name) && text[..text.len() - name.len()].ends_with('\\') {
+            return Some((name.chars().count() + 1, letter));
+        }
+    }
+
+    None
+}
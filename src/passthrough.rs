@@ -0,0 +1,48 @@
+// Let the clicks go through the overlay to the windows below it
+
+use glutin::window::Window;
+
+#[cfg(target_os = "windows")]
+pub fn set_click_through(window: &Window, click_through: bool) {
+    use glutin::platform::windows::WindowExtWindows;
+    use winapi::um::winuser::{
+        GetWindowLongPtrW, SetLayeredWindowAttributes, SetWindowLongPtrW, GWL_EXSTYLE, LWA_ALPHA,
+        WS_EX_LAYERED, WS_EX_TRANSPARENT,
+    };
+
+    unsafe {
+        let hwnd = window.hwnd() as winapi::shared::windef::HWND;
+        let style = GetWindowLongPtrW(hwnd, GWL_EXSTYLE) as u32;
+        // Only layered windows are skipped by the hit testing of transparent windows
+        if click_through {
+            SetWindowLongPtrW(
+                hwnd,
+                GWL_EXSTYLE,
+                (style | WS_EX_TRANSPARENT | WS_EX_LAYERED) as isize,
+            );
+            SetLayeredWindowAttributes(hwnd, 0, 255, LWA_ALPHA);
+        } else {
+            SetWindowLongPtrW(
+                hwnd,
+                GWL_EXSTYLE,
+                (style & !(WS_EX_TRANSPARENT | WS_EX_LAYERED)) as isize,
+            );
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+pub fn set_click_through(window: &Window, click_through: bool) {
+    use cocoa::appkit::NSWindow;
+    use cocoa::base::{id, NO, YES};
+    use glutin::platform::macos::WindowExtMacOS;
+
+    unsafe {
+        let ns_window = window.ns_window() as id;
+        ns_window.setIgnoresMouseEvents_(if click_through { YES } else { NO });
+    }
+}
+
+/// The other platforms' windows always get the clicks
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+pub fn set_click_through(_window: &Window, _click_through: bool) {}
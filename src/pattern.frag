@@ -0,0 +1,54 @@
+#version 150
+// Grid patterns drawn for each pixel of the overlay, so they stay crisp at any size
+uniform vec2 resolution;
+uniform float pattern; // 1 dots, 2 isometric, 3 ruled, 4 music staves
+uniform float spacing;
+uniform vec3 color;
+uniform float opacity;
+out vec4 out_color;
+
+// Distance in pixels to the closest of the lines `spacing` apart across `normal`
+float lineDistance(vec2 p, vec2 normal, float lineSpacing) {
+    float d = dot(p, normal) / lineSpacing;
+    return abs(fract(d + 0.5) - 0.5) * lineSpacing;
+}
+
+// Coverage of a line of 1 pixel at `d` pixels from its center
+float lineCoverage(float d) {
+    return clamp(1.0 - d, 0.0, 1.0);
+}
+
+void main() {
+    // From the top left corner, like the strokes, on the pixel centers
+    vec2 p = vec2(gl_FragCoord.x, resolution.y - gl_FragCoord.y) - 0.5;
+    float coverage = 0.0;
+    if (pattern < 1.5) {
+        vec2 closest = (fract(p / spacing + 0.5) - 0.5) * spacing;
+        coverage = clamp(2.0 - length(closest), 0.0, 1.0);
+    } else if (pattern < 2.5) {
+        // Equilateral triangles of `spacing` sides, from vertical lines and lines at 30 degrees
+        float height = spacing * 0.8660254;
+        coverage = max(
+            lineCoverage(lineDistance(p, vec2(1.0, 0.0), height)),
+            max(
+                lineCoverage(lineDistance(p, vec2(0.5, 0.8660254), height)),
+                lineCoverage(lineDistance(p, vec2(-0.5, 0.8660254), height))
+            )
+        );
+    } else if (pattern < 3.5) {
+        coverage = lineCoverage(lineDistance(p, vec2(0.0, 1.0), spacing));
+    } else {
+        // Staves of 5 lines half the spacing apart, with 3 spacings between them
+        float y = mod(p.y, spacing * 5.0) - spacing;
+        if (y > -1.0 && y < spacing * 2.0 + 1.0) {
+            coverage = lineCoverage(lineDistance(vec2(0.0, y), vec2(0.0, 1.0), spacing * 0.5));
+        }
+    }
+
+    float alpha = opacity * coverage;
+#ifdef PREMULTIPLIED_ALPHA
+    out_color = vec4(color * alpha, alpha);
+#else
+    out_color = vec4(color, alpha);
+#endif
+}
@@ -0,0 +1,161 @@
+// Grid patterns other than plain lines, drawn by a fragment shader over the whole overlay
+
+use std::ffi::CStr;
+use std::mem;
+use std::ptr;
+
+use gl::types::*;
+use inke_core::Rect2D;
+use serde::{Deserialize, Serialize};
+
+use crate::text::keep_bindings;
+use crate::{compile_shader, is_gles, link_program};
+
+static VS_SRC: &str = include_str!("pattern.vert");
+static FS_SRC: &str = include_str!("pattern.frag");
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum GridPattern {
+    /// Horizontal and vertical lines
+    Lines,
+    /// A dot at each intersection of the lines
+    Dots,
+    /// Equilateral triangles, for isometric drawings
+    Isometric,
+    /// Horizontal lines only, like ruled paper
+    Ruled,
+    /// Staves of 5 lines, for music notation
+    MusicStaff,
+}
+
+pub struct PatternRenderer {
+    program: GLuint,
+    vs: GLuint,
+    fs: GLuint,
+    vao: GLuint,
+    vbo: GLuint,
+    pattern_uniform: GLint,
+    spacing_uniform: GLint,
+    color_uniform: GLint,
+    opacity_uniform: GLint,
+    resolution_uniform: GLint,
+}
+
+impl PatternRenderer {
+    /// Create the GL objects drawing the patterns (the GL context must be current)
+    pub fn new() -> PatternRenderer {
+        let vs = compile_shader(VS_SRC, gl::VERTEX_SHADER);
+        let fs = compile_shader(FS_SRC, gl::FRAGMENT_SHADER);
+        let program = link_program(vs, fs);
+
+        let mut vao = 0;
+        let mut vbo = 0;
+        let uniform = |name: &[u8]| unsafe {
+            gl::GetUniformLocation(program, CStr::from_bytes_with_nul(name).unwrap().as_ptr())
+        };
+
+        unsafe {
+            keep_bindings(|| {
+                gl::GenVertexArrays(1, &mut vao);
+                gl::BindVertexArray(vao);
+                gl::GenBuffers(1, &mut vbo);
+                gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
+
+                gl::UseProgram(program);
+                // OpenGL ES shaders write gl_FragColor instead
+                if !is_gles() {
+                    gl::BindFragDataLocation(
+                        program,
+                        0,
+                        CStr::from_bytes_with_nul(b"out_color\0").unwrap().as_ptr(),
+                    );
+                }
+
+                // The two triangles covering the overlay never change
+                let corners: [f32; 12] = [
+                    -1.0, -1.0, 1.0, -1.0, 1.0, 1.0, //
+                    -1.0, -1.0, 1.0, 1.0, -1.0, 1.0,
+                ];
+                gl::BufferData(
+                    gl::ARRAY_BUFFER,
+                    mem::size_of_val(&corners) as GLsizeiptr,
+                    corners.as_ptr() as *const GLvoid,
+                    gl::STATIC_DRAW,
+                );
+                let attr = gl::GetAttribLocation(
+                    program,
+                    CStr::from_bytes_with_nul(b"position\0").unwrap().as_ptr(),
+                );
+                gl::EnableVertexAttribArray(attr as GLuint);
+                gl::VertexAttribPointer(
+                    attr as GLuint,
+                    2,
+                    gl::FLOAT,
+                    gl::FALSE as GLboolean,
+                    0,
+                    ptr::null(),
+                );
+            });
+        }
+
+        PatternRenderer {
+            program,
+            vs,
+            fs,
+            vao,
+            vbo,
+            pattern_uniform: uniform(b"pattern\0"),
+            spacing_uniform: uniform(b"spacing\0"),
+            color_uniform: uniform(b"color\0"),
+            opacity_uniform: uniform(b"opacity\0"),
+            resolution_uniform: uniform(b"resolution\0"),
+        }
+    }
+
+    /// Draw a pattern `spacing` pixels apart over the overlay. Lines are drawn as vertices instead
+    pub fn draw(
+        &self,
+        pattern: GridPattern,
+        spacing: f32,
+        color: [f32; 3],
+        opacity: f32,
+        overlay_rect: &Rect2D,
+    ) {
+        let index = match pattern {
+            GridPattern::Lines => return,
+            GridPattern::Dots => 1.0,
+            GridPattern::Isometric => 2.0,
+            GridPattern::Ruled => 3.0,
+            GridPattern::MusicStaff => 4.0,
+        };
+        unsafe {
+            keep_bindings(|| {
+                gl::UseProgram(self.program);
+                gl::BindVertexArray(self.vao);
+                gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo);
+                gl::Uniform1f(self.pattern_uniform, index);
+                gl::Uniform1f(self.spacing_uniform, spacing);
+                gl::Uniform3f(self.color_uniform, color[0], color[1], color[2]);
+                gl::Uniform1f(self.opacity_uniform, opacity);
+                gl::Uniform2f(
+                    self.resolution_uniform,
+                    overlay_rect.width,
+                    overlay_rect.height,
+                );
+                gl::DrawArrays(gl::TRIANGLES, 0, 6);
+            });
+        }
+    }
+
+    /// Free the GL objects
+    pub fn delete(&self) {
+        unsafe {
+            gl::DeleteProgram(self.program);
+            gl::DeleteShader(self.fs);
+            gl::DeleteShader(self.vs);
+            gl::DeleteBuffers(1, &self.vbo);
+            gl::DeleteVertexArrays(1, &self.vao);
+        }
+    }
+}
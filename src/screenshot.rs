@@ -0,0 +1,167 @@
+// Capture the screen below the overlay and save screenshots
+
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::Path;
+
+/// Pixels of a screen region as rgb rows from the top, or None if the screen can't be read
+///
+/// The region is in physical pixels, relative to the top left of the virtual screen
+pub fn capture(x: i32, y: i32, width: u32, height: u32, scale_factor: f64) -> Option<Vec<u8>> {
+    let pixels = capture_platform(x, y, width, height, scale_factor)?;
+    if pixels.len() == (width * height * 3) as usize {
+        Some(pixels)
+    } else {
+        None
+    }
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+fn capture_platform(
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+    _scale_factor: f64,
+) -> Option<Vec<u8>> {
+    use std::ptr;
+    use x11_dl::xlib::{Xlib, ZPixmap};
+
+    let xlib = Xlib::open().ok()?;
+    unsafe {
+        let display = (xlib.XOpenDisplay)(ptr::null());
+        if display.is_null() {
+            return None;
+        }
+        let root = (xlib.XDefaultRootWindow)(display);
+        let image = (xlib.XGetImage)(display, root, x, y, width, height, !0, ZPixmap);
+        let mut pixels = None;
+
+        // Only the usual 32 bits per pixel BGRX layout is supported
+        if !image.is_null() && (*image).bits_per_pixel == 32 && (*image).red_mask == 0xff0000 {
+            let data = std::slice::from_raw_parts(
+                (*image).data as *const u8,
+                ((*image).bytes_per_line * (*image).height) as usize,
+            );
+            let mut rgb = Vec::with_capacity((width * height * 3) as usize);
+            for row in data.chunks((*image).bytes_per_line as usize) {
+                for pixel in row[..(width * 4) as usize].chunks(4) {
+                    rgb.extend(&[pixel[2], pixel[1], pixel[0]]);
+                }
+            }
+            pixels = Some(rgb);
+        }
+
+        if !image.is_null() {
+            (xlib.XDestroyImage)(image);
+        }
+        (xlib.XCloseDisplay)(display);
+        pixels
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn capture_platform(
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+    _scale_factor: f64,
+) -> Option<Vec<u8>> {
+    use std::{mem, ptr};
+    use winapi::um::wingdi::{
+        BitBlt, CreateCompatibleBitmap, CreateCompatibleDC, DeleteDC, DeleteObject, GetDIBits,
+        SelectObject, BITMAPINFO, BITMAPINFOHEADER, BI_RGB, DIB_RGB_COLORS, SRCCOPY,
+    };
+    use winapi::um::winuser::{GetDC, ReleaseDC};
+
+    let (w, h) = (width as i32, height as i32);
+    let mut bgra = vec![0u8; (width * height * 4) as usize];
+    unsafe {
+        let screen_dc = GetDC(ptr::null_mut());
+        let memory_dc = CreateCompatibleDC(screen_dc);
+        let bitmap = CreateCompatibleBitmap(screen_dc, w, h);
+        let previous = SelectObject(memory_dc, bitmap as _);
+        let copied = BitBlt(memory_dc, 0, 0, w, h, screen_dc, x, y, SRCCOPY);
+        SelectObject(memory_dc, previous);
+
+        let mut info: BITMAPINFO = mem::zeroed();
+        info.bmiHeader.biSize = mem::size_of::<BITMAPINFOHEADER>() as u32;
+        info.bmiHeader.biWidth = w;
+        info.bmiHeader.biHeight = -h; // Rows from the top
+        info.bmiHeader.biPlanes = 1;
+        info.bmiHeader.biBitCount = 32;
+        info.bmiHeader.biCompression = BI_RGB;
+        let rows = GetDIBits(
+            memory_dc,
+            bitmap,
+            0,
+            height,
+            bgra.as_mut_ptr() as _,
+            &mut info,
+            DIB_RGB_COLORS,
+        );
+
+        DeleteObject(bitmap as _);
+        DeleteDC(memory_dc);
+        ReleaseDC(ptr::null_mut(), screen_dc);
+
+        if copied == 0 || rows != h {
+            return None;
+        }
+    }
+
+    Some(
+        bgra.chunks(4)
+            .flat_map(|pixel| vec![pixel[2], pixel[1], pixel[0]])
+            .collect(),
+    )
+}
+
+#[cfg(target_os = "macos")]
+fn capture_platform(x: i32, y: i32, width: u32, height: u32, scale_factor: f64) -> Option<Vec<u8>> {
+    use core_graphics::display::{
+        kCGNullWindowID, kCGWindowImageDefault, kCGWindowListOptionOnScreenOnly, CGDisplay,
+    };
+    use core_graphics::geometry::{CGPoint, CGRect, CGSize};
+
+    // Screen coordinates are in points on macOS
+    let bounds = CGRect::new(
+        &CGPoint::new(x as f64 / scale_factor, y as f64 / scale_factor),
+        &CGSize::new(width as f64 / scale_factor, height as f64 / scale_factor),
+    );
+    let image = CGDisplay::screenshot(
+        bounds,
+        kCGWindowListOptionOnScreenOnly,
+        kCGNullWindowID,
+        kCGWindowImageDefault,
+    )?;
+    if image.bits_per_pixel() != 32 {
+        return None;
+    }
+
+    // The image can be a little larger or smaller than asked, sample the closest pixels
+    let data = image.data();
+    let bytes = data.bytes();
+    let (image_width, image_height) = (image.width(), image.height());
+    let mut rgb = Vec::with_capacity((width * height * 3) as usize);
+    for row in 0..height as usize {
+        let image_row = (row * image_height / height as usize).min(image_height - 1);
+        for column in 0..width as usize {
+            let image_column = (column * image_width / width as usize).min(image_width - 1);
+            let i = image_row * image.bytes_per_row() + image_column * 4;
+            rgb.extend(&[bytes[i + 2], bytes[i + 1], bytes[i]]);
+        }
+    }
+    Some(rgb)
+}
+
+/// Save rgb pixels as a png file
+pub fn save_png(path: &Path, width: u32, height: u32, rgb: &[u8]) -> io::Result<()> {
+    let mut encoder = png::Encoder::new(BufWriter::new(File::create(path)?), width, height);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(rgb)?;
+    Ok(())
+}
@@ -0,0 +1,273 @@
+// Shared whiteboard sessions, one instance hosts and the others join it over TCP
+//
+// Messages are JSON lines. Each peer sends all of its own strokes whenever they change, the
+// host relays them to the other peers, and everyone draws the strokes of the others over their
+// own. Peers only undo, erase and clear their own strokes, so they never conflict. Mirrors only
+// receive the strokes, for a co-presenter or an overflow room.
+
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use glutin::event_loop::EventLoopProxy;
+use inke_core::interchange::StrokeData;
+use serde::{Deserialize, Serialize};
+
+use crate::instance::Command;
+
+// Peer number of the host, clients are numbered from 1
+pub const HOST_PEER: u32 = 0;
+// Longest join line, read before the token is checked
+const MAX_JOIN_LINE: u64 = 4096;
+// Longest strokes line, longer ones end the connection
+const MAX_MESSAGE_SIZE: u64 = 16 * 1024 * 1024;
+// Time a client has to send its join line, and to take each line written to it
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Message {
+    /// First line of a client, the host closes the connection without its token
+    Join {
+        token: String,
+        /// The strokes it sends are ignored
+        #[serde(default)]
+        mirror: bool,
+    },
+    /// Answer of the host to a join, with the number of the new peer
+    Welcome { peer: u32 },
+    /// All the strokes of a peer, replacing the ones it sent before
+    Strokes { peer: u32, strokes: Vec<StrokeData> },
+    /// A peer left, its strokes are removed
+    Leave { peer: u32 },
+}
+
+/// Connection to the other peers, messages are written on a thread of their own
+pub struct Session {
+    sender: Sender<Message>,
+}
+
+impl Session {
+    /// Send the strokes of this peer to the others
+    pub fn send_strokes(&self, strokes: Vec<StrokeData>) {
+        // The peer number is set by the host
+        let _ = self.sender.send(Message::Strokes {
+            peer: HOST_PEER,
+            strokes,
+        });
+    }
+}
+
+/// Clients connected to the host, and the last strokes of every peer for the next ones
+#[derive(Default)]
+struct Hub {
+    clients: HashMap<u32, TcpStream>,
+    last_strokes: HashMap<u32, String>,
+    next_peer: u32,
+}
+
+impl Hub {
+    /// Write a line to the clients, except to the one it came from
+    fn broadcast(&mut self, line: &str, from: u32) {
+        self.clients
+            .retain(|&peer, stream| peer == from || writeln!(stream, "{}", line).is_ok());
+    }
+}
+
+/// Read a line of at most max bytes, None at the end, on an error or on a longer line
+fn read_line<R: BufRead>(reader: &mut R, max: u64) -> Option<String> {
+    let mut line = String::new();
+    match reader.take(max).read_line(&mut line) {
+        Ok(n) if n > 0 && line.ends_with('\n') => Some(line),
+        _ => None,
+    }
+}
+
+fn to_line(message: &Message) -> String {
+    serde_json::to_string(message).expect("Failed to encode a session message")
+}
+
+/// Host a session on a port of all the network interfaces
+pub fn host(port: u16, token: String, proxy: EventLoopProxy<Command>) -> Option<Session> {
+    let listener = match TcpListener::bind(("0.0.0.0", port)) {
+        Ok(listener) => listener,
+        Err(e) => {
+            log::error!("Failed to host the session on port {}: {}", port, e);
+            return None;
+        }
+    };
+    log::info!("Hosting a shared session on port {}", port);
+
+    let hub = Arc::new(Mutex::new(Hub {
+        next_peer: HOST_PEER + 1,
+        ..Default::default()
+    }));
+
+    let accept_hub = hub.clone();
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let hub = accept_hub.clone();
+            let token = token.clone();
+            let proxy = proxy.clone();
+            thread::spawn(move || serve_client(stream, &token, &hub, &proxy));
+        }
+    });
+
+    let (sender, receiver) = mpsc::channel::<Message>();
+    thread::spawn(move || {
+        for message in receiver {
+            let line = to_line(&message);
+            let mut hub = hub.lock().unwrap();
+            if let Message::Strokes { .. } = message {
+                hub.last_strokes.insert(HOST_PEER, line.clone());
+            }
+            hub.broadcast(&line, HOST_PEER);
+        }
+    });
+    Some(Session { sender })
+}
+
+/// Welcome a client, then relay its strokes until it leaves
+fn serve_client(stream: TcpStream, token: &str, hub: &Mutex<Hub>, proxy: &EventLoopProxy<Command>) {
+    // A client that stops reading can't block the broadcasts to the others
+    if stream.set_read_timeout(Some(TIMEOUT)).is_err()
+        || stream.set_write_timeout(Some(TIMEOUT)).is_err()
+    {
+        return;
+    }
+    let mut writer = match stream.try_clone() {
+        Ok(writer) => writer,
+        Err(_) => return,
+    };
+    let mut reader = BufReader::new(stream);
+    // Whether the client joins as a mirror, None without the token
+    let join_mirror = read_line(&mut reader, MAX_JOIN_LINE)
+        .and_then(|line| serde_json::from_str::<Message>(&line).ok())
+        .and_then(|message| match message {
+            Message::Join { token: t, mirror } if t == token => Some(mirror),
+            _ => None,
+        });
+    let is_mirror = match join_mirror {
+        Some(is_mirror) => is_mirror,
+        None => {
+            log::warn!("Refused a session client without the token");
+            return;
+        }
+    };
+
+    let peer = {
+        let mut hub = hub.lock().unwrap();
+        let peer = hub.next_peer;
+        hub.next_peer += 1;
+        // The strokes drawn before the client joined
+        let mut welcome = to_line(&Message::Welcome { peer });
+        for line in hub.last_strokes.values() {
+            welcome.push('\n');
+            welcome.push_str(line);
+        }
+        if writeln!(writer, "{}", welcome).is_err() {
+            return;
+        }
+        hub.clients.insert(peer, writer);
+        peer
+    };
+    // Peers only send their strokes when they change
+    let _ = reader.get_ref().set_read_timeout(None);
+    if is_mirror {
+        log::info!("Peer {} joined the session as a mirror", peer);
+    } else {
+        log::info!("Peer {} joined the session", peer);
+    }
+
+    while let Some(line) = read_line(&mut reader, MAX_MESSAGE_SIZE) {
+        let strokes = match serde_json::from_str(&line) {
+            Ok(Message::Strokes { strokes, .. }) if !is_mirror => strokes,
+            _ => continue,
+        };
+        // Peers can only send their own strokes
+        let message = Message::Strokes { peer, strokes };
+        let line = to_line(&message);
+        {
+            let mut hub = hub.lock().unwrap();
+            hub.last_strokes.insert(peer, line.clone());
+            hub.broadcast(&line, peer);
+        }
+        if proxy.send_event(Command::Session(message)).is_err() {
+            return;
+        }
+    }
+
+    log::info!("Peer {} left the session", peer);
+    let message = Message::Leave { peer };
+    {
+        let mut hub = hub.lock().unwrap();
+        hub.clients.remove(&peer);
+        hub.last_strokes.remove(&peer);
+        hub.broadcast(&to_line(&message), peer);
+    }
+    let _ = proxy.send_event(Command::Session(message));
+}
+
+/// Join the session hosted at an address such as 192.168.1.10:47500, only receiving the strokes
+/// of the others as a mirror
+pub fn join(
+    address: &str,
+    token: String,
+    is_mirror: bool,
+    proxy: EventLoopProxy<Command>,
+) -> Option<Session> {
+    let mut stream = match TcpStream::connect(address) {
+        Ok(stream) => stream,
+        Err(e) => {
+            log::error!("Failed to join the session at {}: {}", address, e);
+            return None;
+        }
+    };
+    let reader = match stream.try_clone() {
+        Ok(reader) => reader,
+        Err(e) => {
+            log::error!("Failed to join the session at {}: {}", address, e);
+            return None;
+        }
+    };
+    if let Err(e) = writeln!(
+        stream,
+        "{}",
+        to_line(&Message::Join {
+            token,
+            mirror: is_mirror,
+        })
+    ) {
+        log::error!("Failed to join the session at {}: {}", address, e);
+        return None;
+    }
+
+    thread::spawn(move || {
+        let mut reader = BufReader::new(reader);
+        while let Some(line) = read_line(&mut reader, MAX_MESSAGE_SIZE) {
+            match serde_json::from_str(&line) {
+                Ok(message) => {
+                    if proxy.send_event(Command::Session(message)).is_err() {
+                        return;
+                    }
+                }
+                Err(e) => log::debug!("Unknown session message: {}", e),
+            }
+        }
+        log::warn!("Disconnected from the session");
+    });
+
+    let (sender, receiver) = mpsc::channel::<Message>();
+    thread::spawn(move || {
+        for message in receiver {
+            if writeln!(stream, "{}", to_line(&message)).is_err() {
+                return;
+            }
+        }
+    });
+    Some(Session { sender })
+}
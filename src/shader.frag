@@ -1,7 +1,19 @@
 #version 150
 out vec4 out_color;
 in vec3 fColor;
+in float fEdge;
+in float fHardness;
+in float fOpacity;
 
 void main() {
-    out_color = vec4(fColor.r, fColor.g, fColor.b, 1.0);
+    // Fade out the sides of the line past its hard part
+    float alpha = fOpacity;
+    if (fHardness < 1.0) {
+        alpha *= 1.0 - smoothstep(fHardness, 1.0, abs(fEdge));
+    }
+#ifdef PREMULTIPLIED_ALPHA
+    out_color = vec4(fColor * alpha, alpha);
+#else
+    out_color = vec4(fColor.r, fColor.g, fColor.b, alpha);
+#endif
 }
\ No newline at end of file
@@ -1,9 +1,18 @@
 #version 150
 in vec3 position;
 in vec3 vColor;
+in float vEdge;
+in float vHardness;
+in float vOpacity;
 out vec3 fColor;
+out float fEdge;
+out float fHardness;
+out float fOpacity;
 
 void main() {
     fColor = vColor;
+    fEdge = vEdge;
+    fHardness = vHardness;
+    fOpacity = vOpacity;
     gl_Position = vec4(position, 1.0);
 }
\ No newline at end of file
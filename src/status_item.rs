@@ -0,0 +1,87 @@
+// Menu bar icon with the main actions, the usual home of background utilities on macOS
+
+use std::os::raw::c_void;
+
+use cocoa::appkit::{
+    NSButton, NSMenu, NSMenuItem, NSStatusBar, NSStatusItem, NSVariableStatusItemLength,
+};
+use cocoa::base::{id, nil};
+use cocoa::foundation::{NSAutoreleasePool, NSInteger, NSString};
+use glutin::event_loop::EventLoopProxy;
+use objc::declare::ClassDecl;
+use objc::runtime::{Class, Object, Sel};
+use objc::{class, msg_send, sel, sel_impl};
+
+use crate::instance::Command;
+
+// Menu items, in order. None adds a separator
+const ITEMS: [Option<(&str, Command)>; 6] = [
+    Some(("Show", Command::Activate)),
+    Some(("Hide", Command::Hide)),
+    Some(("Clear", Command::Clear)),
+    Some(("Toggle background", Command::ToggleBackground)),
+    None,
+    Some(("Quit Inke", Command::Quit)),
+];
+
+/// Add the Inke menu to the menu bar, its items are sent to the event loop as commands
+pub fn add(proxy: EventLoopProxy<Command>) {
+    unsafe {
+        // Lives as long as the app, like the status item
+        let target: id = msg_send![menu_target_class(), new];
+        let proxy = Box::into_raw(Box::new(proxy)) as *mut c_void;
+        (*target).set_ivar("proxy", proxy);
+
+        let menu = NSMenu::new(nil).autorelease();
+        for (index, item) in ITEMS.iter().enumerate() {
+            let menu_item = match item {
+                Some((title, _)) => {
+                    let menu_item = NSMenuItem::alloc(nil)
+                        .initWithTitle_action_keyEquivalent_(
+                            NSString::alloc(nil).init_str(title),
+                            sel!(menuAction:),
+                            NSString::alloc(nil).init_str(""),
+                        )
+                        .autorelease();
+                    NSMenuItem::setTarget_(menu_item, target);
+                    let _: () = msg_send![menu_item, setTag: index as NSInteger];
+                    menu_item
+                }
+                None => NSMenuItem::separatorItem(nil),
+            };
+            menu.addItem_(menu_item);
+        }
+
+        let status_item =
+            NSStatusBar::systemStatusBar(nil).statusItemWithLength_(NSVariableStatusItemLength);
+        let _: id = msg_send![status_item, retain];
+        status_item
+            .button()
+            .setTitle_(NSString::alloc(nil).init_str("Inke"));
+        status_item.setMenu_(menu);
+    }
+}
+
+/// Objective-C class receiving the menu item actions
+fn menu_target_class() -> &'static Class {
+    let mut decl = ClassDecl::new("InkeMenuTarget", class!(NSObject)).unwrap();
+    decl.add_ivar::<*mut c_void>("proxy");
+    unsafe {
+        decl.add_method(
+            sel!(menuAction:),
+            menu_action as extern "C" fn(&Object, Sel, id),
+        );
+    }
+    decl.register()
+}
+
+extern "C" fn menu_action(this: &Object, _: Sel, sender: id) {
+    unsafe {
+        let index: NSInteger = msg_send![sender, tag];
+        if let Some(Some((_, command))) = ITEMS.get(index as usize) {
+            let proxy = *this.get_ivar::<*mut c_void>("proxy") as *const EventLoopProxy<Command>;
+            // Nothing to do if the event loop is already gone
+            let _ = (*proxy).send_event(command.clone());
+        }
+    }
+}
@@ -0,0 +1,15 @@
+#version 150
+uniform sampler2D atlas;
+in vec2 fUv;
+in vec4 fColor;
+out vec4 out_color;
+
+void main() {
+    // The atlas only holds the coverage of the glyphs
+    float alpha = fColor.a * texture(atlas, fUv).r;
+#ifdef PREMULTIPLIED_ALPHA
+    out_color = vec4(fColor.rgb * alpha, alpha);
+#else
+    out_color = vec4(fColor.rgb, alpha);
+#endif
+}
\ No newline at end of file
@@ -0,0 +1,421 @@
+// Text drawn with a TrueType font, its glyphs rasterized on demand into an atlas texture
+
+use std::collections::HashMap;
+use std::ffi::CStr;
+use std::{fs, mem};
+
+use gl::types::*;
+use inke_core::{screen_to_gl, Rect2D};
+use rusttype::{point, Font, GlyphId, Scale};
+
+use crate::{compile_shader, is_gles, link_program};
+
+static VS_SRC: &str = include_str!("text.vert");
+static FS_SRC: &str = include_str!("text.frag");
+// Used when no font is configured, or the configured one can't be loaded
+static EMBEDDED_FONT: &[u8] = include_bytes!("../build_resources/fonts/DejaVuSans.ttf");
+
+// Glyphs are rasterized once at this size (in pixels), then scaled to the size of the text
+const RASTER_SIZE: f32 = 64.0;
+const ATLAS_SIZE: i32 = 1024;
+// Empty pixels around each glyph, so scaled down glyphs don't bleed into their neighbours
+const GLYPH_PADDING: i32 = 4;
+// Floats per vertex: x, y, u, v, r, g, b, a
+const TEXT_VERTEX_SIZE: usize = 8;
+
+/// Where a rasterized glyph is in the atlas
+#[derive(Clone, Copy)]
+struct AtlasGlyph {
+    uv_min: (f32, f32),
+    uv_max: (f32, f32),
+    // Pixel bounds of the glyph from its origin on the baseline, at the raster size
+    min: (f32, f32),
+    max: (f32, f32),
+}
+
+pub struct TextRenderer {
+    font: Font<'static>,
+    glyphs: HashMap<GlyphId, Option<AtlasGlyph>>, // None for glyphs without pixels, like spaces
+    shelf_x: i32,
+    shelf_y: i32,
+    shelf_height: i32,
+    program: GLuint,
+    vs: GLuint,
+    fs: GLuint,
+    vao: GLuint,
+    vbo: GLuint,
+    texture: GLuint,
+}
+
+/// Internal and pixel formats of the single channel atlas, OpenGL ES 2 has no red only textures
+fn atlas_formats() -> (GLenum, GLenum) {
+    // Missing from the core profile bindings
+    const GL_LUMINANCE: GLenum = 0x1909;
+    if is_gles() {
+        (GL_LUMINANCE, GL_LUMINANCE)
+    } else {
+        (gl::R8, gl::RED)
+    }
+}
+
+/// Run GL calls binding other objects, then restore the program and buffers drawing the strokes
+pub unsafe fn keep_bindings<F: FnOnce()>(f: F) {
+    let mut program = 0;
+    let mut vao = 0;
+    let mut vbo = 0;
+    gl::GetIntegerv(gl::CURRENT_PROGRAM, &mut program);
+    gl::GetIntegerv(gl::VERTEX_ARRAY_BINDING, &mut vao);
+    gl::GetIntegerv(gl::ARRAY_BUFFER_BINDING, &mut vbo);
+
+    f();
+
+    gl::UseProgram(program as GLuint);
+    gl::BindVertexArray(vao as GLuint);
+    gl::BindBuffer(gl::ARRAY_BUFFER, vbo as GLuint);
+}
+
+/// Load the TrueType font at `path`, or the embedded one
+fn load_font(path: Option<&str>) -> Font<'static> {
+    if let Some(path) = path {
+        match fs::read(path).map(Font::from_bytes) {
+            Ok(Ok(font)) => return font,
+            Ok(Err(e)) => log::error!("Failed to read the font {}: {}", path, e),
+            Err(e) => log::error!("Failed to open the font {}: {}", path, e),
+        }
+    }
+    Font::from_bytes(EMBEDDED_FONT).expect("Failed to read the embedded font")
+}
+
+impl TextRenderer {
+    /// Load the font and create the GL objects drawing it (the GL context must be current)
+    pub fn new(font_path: Option<&str>) -> TextRenderer {
+        let vs = compile_shader(VS_SRC, gl::VERTEX_SHADER);
+        let fs = compile_shader(FS_SRC, gl::FRAGMENT_SHADER);
+        let program = link_program(vs, fs);
+
+        let mut vao = 0;
+        let mut vbo = 0;
+        let mut texture = 0;
+
+        unsafe {
+            keep_bindings(|| {
+                gl::GenVertexArrays(1, &mut vao);
+                gl::BindVertexArray(vao);
+                gl::GenBuffers(1, &mut vbo);
+                gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
+
+                gl::UseProgram(program);
+                // OpenGL ES shaders write gl_FragColor instead
+                if !is_gles() {
+                    gl::BindFragDataLocation(
+                        program,
+                        0,
+                        CStr::from_bytes_with_nul(b"out_color\0").unwrap().as_ptr(),
+                    );
+                }
+
+                // position, uv and color attribs
+                let stride = (TEXT_VERTEX_SIZE * mem::size_of::<f32>()) as GLint;
+                for &(name, size, offset) in [
+                    (&b"position\0"[..], 2, 0),
+                    (&b"vUv\0"[..], 2, 2),
+                    (&b"vColor\0"[..], 4, 4),
+                ]
+                .iter()
+                {
+                    let attr = gl::GetAttribLocation(
+                        program,
+                        CStr::from_bytes_with_nul(name).unwrap().as_ptr(),
+                    );
+                    gl::EnableVertexAttribArray(attr as GLuint);
+                    gl::VertexAttribPointer(
+                        attr as GLuint,
+                        size,
+                        gl::FLOAT,
+                        gl::FALSE as GLboolean,
+                        stride,
+                        (offset * mem::size_of::<f32>()) as *const GLvoid,
+                    );
+                }
+
+                // Single channel atlas, filled as glyphs get used
+                gl::GenTextures(1, &mut texture);
+                gl::BindTexture(gl::TEXTURE_2D, texture);
+                gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+                let blank = vec![0u8; (ATLAS_SIZE * ATLAS_SIZE) as usize];
+                let (internal_format, format) = atlas_formats();
+                gl::TexImage2D(
+                    gl::TEXTURE_2D,
+                    0,
+                    internal_format as GLint,
+                    ATLAS_SIZE,
+                    ATLAS_SIZE,
+                    0,
+                    format,
+                    gl::UNSIGNED_BYTE,
+                    blank.as_ptr() as *const GLvoid,
+                );
+                gl::TexParameteri(
+                    gl::TEXTURE_2D,
+                    gl::TEXTURE_MIN_FILTER,
+                    gl::LINEAR_MIPMAP_LINEAR as GLint,
+                );
+                gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as GLint);
+                gl::TexParameteri(
+                    gl::TEXTURE_2D,
+                    gl::TEXTURE_WRAP_S,
+                    gl::CLAMP_TO_EDGE as GLint,
+                );
+                gl::TexParameteri(
+                    gl::TEXTURE_2D,
+                    gl::TEXTURE_WRAP_T,
+                    gl::CLAMP_TO_EDGE as GLint,
+                );
+                gl::GenerateMipmap(gl::TEXTURE_2D);
+            });
+        }
+
+        TextRenderer {
+            font: load_font(font_path),
+            glyphs: HashMap::new(),
+            shelf_x: 0,
+            shelf_y: 0,
+            shelf_height: 0,
+            program,
+            vs,
+            fs,
+            vao,
+            vbo,
+            texture,
+        }
+    }
+
+    /// Glyphs of a line of text with their position from its start, kerning included
+    fn layout(&self, text: &str, size: f32) -> Vec<(GlyphId, f32)> {
+        let scale = Scale::uniform(size);
+        let mut x = 0.0;
+        let mut last = None;
+        let mut glyphs = Vec::new();
+
+        for c in text.chars() {
+            let glyph = self.font.glyph(c).scaled(scale);
+            let id = glyph.id();
+            if let Some(last) = last {
+                x += self.font.pair_kerning(scale, last, id);
+            }
+            glyphs.push((id, x));
+            x += glyph.h_metrics().advance_width;
+            last = Some(id);
+        }
+
+        glyphs
+    }
+
+    /// Width (in pixels) of a line of text
+    pub fn text_width(&self, text: &str, size: f32) -> f32 {
+        let scale = Scale::uniform(size);
+        self.layout(text, size).last().map_or(0.0, |&(id, x)| {
+            x + self.font.glyph(id).scaled(scale).h_metrics().advance_width
+        })
+    }
+
+    /// Height (in pixels) of a line of text, from the top of the highest glyphs to the bottom
+    /// of the lowest ones
+    pub fn line_height(&self, size: f32) -> f32 {
+        let v_metrics = self.font.v_metrics(Scale::uniform(size));
+        v_metrics.ascent - v_metrics.descent
+    }
+
+    /// Coverage (from 0 to 255) of a line of text, with its width and height in pixels, for
+    /// drawing it on the CPU
+    pub fn rasterize(&self, text: &str, size: f32) -> (u32, u32, Vec<u8>) {
+        let scale = Scale::uniform(size);
+        let ascent = self.font.v_metrics(scale).ascent;
+        let width = self.text_width(text, size).ceil().max(1.0) as u32;
+        let height = self.line_height(size).ceil().max(1.0) as u32;
+        let mut coverage = vec![0u8; (width * height) as usize];
+
+        for (id, x) in self.layout(text, size) {
+            let glyph = self
+                .font
+                .glyph(id)
+                .scaled(scale)
+                .positioned(point(x, ascent));
+            let bounds = match glyph.pixel_bounding_box() {
+                Some(bounds) => bounds,
+                None => continue,
+            };
+            glyph.draw(|glyph_x, glyph_y, glyph_coverage| {
+                let px = bounds.min.x + glyph_x as i32;
+                let py = bounds.min.y + glyph_y as i32;
+                if px >= 0 && py >= 0 && (px as u32) < width && (py as u32) < height {
+                    let pixel = &mut coverage[(py as u32 * width + px as u32) as usize];
+                    *pixel = (*pixel).max((glyph_coverage * 255.0) as u8);
+                }
+            });
+        }
+        (width, height, coverage)
+    }
+
+    /// Rasterize a glyph into the atlas the first time it is used
+    fn atlas_glyph(&mut self, id: GlyphId) -> Option<AtlasGlyph> {
+        if let Some(glyph) = self.glyphs.get(&id) {
+            return *glyph;
+        }
+
+        let glyph = self
+            .font
+            .glyph(id)
+            .scaled(Scale::uniform(RASTER_SIZE))
+            .positioned(point(0.0, 0.0));
+        let bounds = match glyph.pixel_bounding_box() {
+            Some(bounds) => bounds,
+            None => {
+                self.glyphs.insert(id, None);
+                return None;
+            }
+        };
+        let width = bounds.width() + GLYPH_PADDING * 2;
+        let height = bounds.height() + GLYPH_PADDING * 2;
+
+        // Glyphs are packed left to right on shelves, a full atlas starts over from the top
+        if self.shelf_x + width > ATLAS_SIZE {
+            self.shelf_x = 0;
+            self.shelf_y += self.shelf_height;
+            self.shelf_height = 0;
+        }
+        if self.shelf_y + height > ATLAS_SIZE {
+            self.glyphs.clear();
+            self.shelf_x = 0;
+            self.shelf_y = 0;
+            self.shelf_height = 0;
+        }
+
+        let mut pixels = vec![0u8; (width * height) as usize];
+        glyph.draw(|x, y, coverage| {
+            let index = (y as i32 + GLYPH_PADDING) * width + x as i32 + GLYPH_PADDING;
+            pixels[index as usize] = (coverage * 255.0) as u8;
+        });
+
+        unsafe {
+            gl::BindTexture(gl::TEXTURE_2D, self.texture);
+            gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+            gl::TexSubImage2D(
+                gl::TEXTURE_2D,
+                0,
+                self.shelf_x,
+                self.shelf_y,
+                width,
+                height,
+                atlas_formats().1,
+                gl::UNSIGNED_BYTE,
+                pixels.as_ptr() as *const GLvoid,
+            );
+            gl::GenerateMipmap(gl::TEXTURE_2D);
+        }
+
+        let atlas_size = ATLAS_SIZE as f32;
+        let padding = GLYPH_PADDING as f32;
+        let atlas_glyph = AtlasGlyph {
+            uv_min: (
+                self.shelf_x as f32 / atlas_size,
+                self.shelf_y as f32 / atlas_size,
+            ),
+            uv_max: (
+                (self.shelf_x + width) as f32 / atlas_size,
+                (self.shelf_y + height) as f32 / atlas_size,
+            ),
+            min: (bounds.min.x as f32 - padding, bounds.min.y as f32 - padding),
+            max: (bounds.max.x as f32 + padding, bounds.max.y as f32 + padding),
+        };
+
+        self.shelf_x += width;
+        self.shelf_height = self.shelf_height.max(height);
+        self.glyphs.insert(id, Some(atlas_glyph));
+        Some(atlas_glyph)
+    }
+
+    /// Add the quads of a line of text (in screen pixels) with its top left corner at x, y
+    #[allow(clippy::too_many_arguments)]
+    pub fn push_text(
+        &mut self,
+        vertices: &mut Vec<f32>,
+        text: &str,
+        x: f32,
+        y: f32,
+        size: f32,
+        color: [f32; 4],
+        overlay_rect: &Rect2D,
+    ) {
+        let baseline = y + self.font.v_metrics(Scale::uniform(size)).ascent;
+        let ratio = size / RASTER_SIZE;
+
+        for (id, glyph_x) in self.layout(text, size) {
+            let glyph = match self.atlas_glyph(id) {
+                Some(glyph) => glyph,
+                None => continue,
+            };
+            let left = x + glyph_x + glyph.min.0 * ratio;
+            let right = x + glyph_x + glyph.max.0 * ratio;
+            let top = baseline + glyph.min.1 * ratio;
+            let bottom = baseline + glyph.max.1 * ratio;
+
+            let top_left = (left, top, glyph.uv_min.0, glyph.uv_min.1);
+            let top_right = (right, top, glyph.uv_max.0, glyph.uv_min.1);
+            let bottom_right = (right, bottom, glyph.uv_max.0, glyph.uv_max.1);
+            let bottom_left = (left, bottom, glyph.uv_min.0, glyph.uv_max.1);
+            for &(px, py, u, v) in [
+                top_left,
+                top_right,
+                bottom_right,
+                top_left,
+                bottom_right,
+                bottom_left,
+            ]
+            .iter()
+            {
+                let p = screen_to_gl(px, py, overlay_rect);
+                vertices.extend(&[p.x, p.y, u, v]);
+                vertices.extend(&color);
+            }
+        }
+    }
+
+    /// Draw text quads
+    pub fn draw(&self, vertices: &[f32]) {
+        if vertices.is_empty() {
+            return;
+        }
+        unsafe {
+            keep_bindings(|| {
+                gl::UseProgram(self.program);
+                gl::BindVertexArray(self.vao);
+                gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo);
+                gl::BindTexture(gl::TEXTURE_2D, self.texture);
+                gl::BufferData(
+                    gl::ARRAY_BUFFER,
+                    mem::size_of_val(vertices) as GLsizeiptr,
+                    vertices.as_ptr() as *const GLvoid,
+                    gl::STREAM_DRAW,
+                );
+                gl::DrawArrays(
+                    gl::TRIANGLES,
+                    0,
+                    (vertices.len() / TEXT_VERTEX_SIZE) as GLint,
+                );
+            });
+        }
+    }
+
+    /// Free the GL objects
+    pub fn delete(&self) {
+        unsafe {
+            gl::DeleteProgram(self.program);
+            gl::DeleteShader(self.fs);
+            gl::DeleteShader(self.vs);
+            gl::DeleteBuffers(1, &self.vbo);
+            gl::DeleteVertexArrays(1, &self.vao);
+            gl::DeleteTextures(1, &self.texture);
+        }
+    }
+}
@@ -0,0 +1,12 @@
+#version 150
+in vec2 position;
+in vec2 vUv;
+in vec4 vColor;
+out vec2 fUv;
+out vec4 fColor;
+
+void main() {
+    fUv = vUv;
+    fColor = vColor;
+    gl_Position = vec4(position, 0.0, 1.0);
+}
\ No newline at end of file
@@ -0,0 +1,216 @@
+// Behavior of each tool, what pressing, dragging and releasing the cursor does with it
+//
+// A new tool is a type implementing ToolHandler, added to the Tool enum and to handler(). The
+// event loop only asks the handler of the current tool, it doesn't match on tools itself.
+
+use std::mem;
+use std::rc::Rc;
+use std::time::{Duration, SystemTime};
+
+use inke_core::stroke::{Shape, Stroke};
+use inke_core::Rect2D;
+
+use crate::{
+    add_brush_point, add_redaction, draw_shape, erase, predict_brush_point, push_undo_step,
+    select_in_rect, start_selection, take_screenshot, taper_length, unix_millis, DrawingState,
+    Input, Tool,
+};
+
+// Color of the reticle of tools not drawing, so they can't be mistaken for the brush
+const NEUTRAL_RETICLE_COLOR: [f32; 3] = [0.5, 0.5, 0.5];
+
+pub trait ToolHandler {
+    /// The cursor was pressed, before any drag
+    fn press(&self, _drawing: &mut DrawingState, _input: &mut Input) {}
+
+    /// Each frame while the cursor is held, adding or changing strokes
+    fn drag(&self, _drawing: &mut DrawingState, _input: &mut Input) {}
+
+    /// The cursor was released after dragging a marquee from a press of this tool
+    fn select_region(&self, drawing: &mut DrawingState, input: &Input, region: &Rect2D) {
+        select_in_rect(
+            drawing,
+            region.x,
+            region.y,
+            region.x + region.width,
+            region.y + region.height,
+            input.modifiers.shift,
+        );
+    }
+
+    /// Color of the circle drawn around the cursor
+    fn reticle_color(&self, drawing: &DrawingState) -> [f32; 3] {
+        drawing.line_style.color
+    }
+
+    /// Radius of the circle drawn around the cursor, in pixels
+    fn reticle_radius(&self, drawing: &DrawingState) -> f32 {
+        drawing.line_style.width
+    }
+}
+
+/// Handler of a tool
+pub fn handler(tool: Tool) -> &'static dyn ToolHandler {
+    match tool {
+        Tool::Brush => &Brush,
+        Tool::StrokeEraser | Tool::SegmentEraser => &Eraser,
+        Tool::Select => &Select,
+        Tool::Arrow => &Arrow,
+        Tool::Screenshot => &Screenshot,
+        Tool::Redaction => &Redaction,
+        Tool::HighlightBox => &HighlightBox,
+        Tool::Text => &Text,
+    }
+}
+
+struct Brush;
+
+impl ToolHandler for Brush {
+    fn drag(&self, drawing: &mut DrawingState, input: &mut Input) {
+        // New line, add an undo point unless the last line was released very recently
+        // A grouping window of 0 gives every line its own undo step, ephemeral lines have none
+        if drawing.n_points_current_line == 0 {
+            let grouping_window = Duration::from_millis(drawing.config.stroke_grouping_ms);
+            if !drawing.is_ephemeral_line
+                && input.cursor.released_time.is_none_or(|time| {
+                    grouping_window.is_zero()
+                        || time.elapsed().unwrap_or_default() > grouping_window
+                })
+            {
+                push_undo_step(drawing);
+            }
+
+            // From the first position reported, which can be before this frame
+            let start_time = input
+                .cursor
+                .samples
+                .first()
+                .map_or_else(SystemTime::now, |&(_, _, time)| time);
+            drawing.strokes.push(Rc::new(Stroke {
+                color: drawing.line_style.color,
+                width: drawing.line_style.width,
+                hardness: drawing.line_style.hardness,
+                taper: taper_length(drawing),
+                start_time: unix_millis(start_time),
+                ..Default::default()
+            }));
+            drawing.axis_lock = None;
+        }
+
+        // Every position reported since the last frame is kept, fast lines stay smooth
+        let from = drawing
+            .strokes
+            .last()
+            .map_or(0, |stroke| stroke.points.len());
+        let samples = mem::take(&mut input.cursor.samples);
+        let current = (input.cursor.x, input.cursor.y);
+        let now = (current.0, current.1, SystemTime::now());
+        let mut has_new_points = false;
+        for &sample in samples.iter().chain(std::iter::once(&now)) {
+            has_new_points |= add_brush_point(drawing, input, sample);
+        }
+        // Only the segments of the new points are tessellated, and the predicted one replaced
+        if drawing.config.input_prediction {
+            // The predicted segment is replaced by the real points on the next frame
+            predict_brush_point(drawing, current, from);
+        } else if has_new_points {
+            let stroke = Rc::make_mut(drawing.strokes.last_mut().unwrap());
+            stroke.tessellate_from(from, &drawing.rect, drawing.lines.is_some());
+            drawing.line_changed = true;
+        }
+
+        drawing.n_points_current_line += 1;
+    }
+}
+
+/// Both erasers, erase() tells them apart
+struct Eraser;
+
+impl ToolHandler for Eraser {
+    fn drag(&self, drawing: &mut DrawingState, input: &mut Input) {
+        erase(drawing, input);
+    }
+
+    fn reticle_color(&self, _drawing: &DrawingState) -> [f32; 3] {
+        NEUTRAL_RETICLE_COLOR
+    }
+
+    // The reticle shows exactly the erased radius
+    fn reticle_radius(&self, drawing: &DrawingState) -> f32 {
+        drawing.eraser_size
+    }
+}
+
+struct Select;
+
+impl ToolHandler for Select {
+    fn press(&self, drawing: &mut DrawingState, input: &mut Input) {
+        start_selection(drawing, input);
+        drawing.need_redraw = true;
+    }
+
+    fn reticle_color(&self, _drawing: &DrawingState) -> [f32; 3] {
+        NEUTRAL_RETICLE_COLOR
+    }
+}
+
+struct Arrow;
+
+impl ToolHandler for Arrow {
+    fn drag(&self, drawing: &mut DrawingState, input: &mut Input) {
+        // Shift toggles heads on both ends while dragging
+        let shape = Shape::Arrow {
+            head: drawing.arrow_head,
+            both_ends: drawing.config.arrow_both_ends != input.modifiers.shift,
+        };
+        draw_shape(drawing, input, shape);
+    }
+}
+
+struct HighlightBox;
+
+impl ToolHandler for HighlightBox {
+    fn drag(&self, drawing: &mut DrawingState, input: &mut Input) {
+        let shape = Shape::HighlightBox {
+            fill_opacity: drawing.config.highlight_box_fill_opacity.clamp(0.0, 1.0),
+        };
+        draw_shape(drawing, input, shape);
+    }
+}
+
+struct Screenshot;
+
+impl ToolHandler for Screenshot {
+    fn press(&self, drawing: &mut DrawingState, input: &mut Input) {
+        drawing.selection_marquee = Some((input.cursor.x, input.cursor.y));
+    }
+
+    fn select_region(&self, drawing: &mut DrawingState, _input: &Input, region: &Rect2D) {
+        take_screenshot(drawing, region);
+    }
+
+    fn reticle_color(&self, _drawing: &DrawingState) -> [f32; 3] {
+        NEUTRAL_RETICLE_COLOR
+    }
+}
+
+struct Redaction;
+
+impl ToolHandler for Redaction {
+    fn press(&self, drawing: &mut DrawingState, input: &mut Input) {
+        drawing.selection_marquee = Some((input.cursor.x, input.cursor.y));
+    }
+
+    fn select_region(&self, drawing: &mut DrawingState, _input: &Input, region: &Rect2D) {
+        add_redaction(drawing, region);
+    }
+
+    fn reticle_color(&self, _drawing: &DrawingState) -> [f32; 3] {
+        NEUTRAL_RETICLE_COLOR
+    }
+}
+
+/// Clicks start and edit text annotations before reaching the handler, see click_text()
+struct Text;
+
+impl ToolHandler for Text {}
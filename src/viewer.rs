@@ -0,0 +1,119 @@
+// Web page showing the drawing live in a browser, for meeting participants without Inke
+//
+// The page at http://<address>:<port>/?token=<token> connects back to the same port with a
+// WebSocket, and receives the whole drawing as an interchange document each time it changes.
+
+use crate::websocket::{
+    has_token, read_frame, read_request, write_frame, write_handshake, OPCODE_CLOSE, OPCODE_TEXT,
+};
+use std::io::{BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+static PAGE: &str = include_str!("viewer.html");
+
+// Browsers too slow to take the drawing are disconnected, they reconnect on their own
+const WRITE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Browsers watching the drawing, and the last drawing sent for the next ones
+#[derive(Default)]
+struct Watchers {
+    clients: Vec<TcpStream>,
+    board: String,
+}
+
+/// Sends the drawing to the browsers, on a thread of its own
+pub struct Viewer {
+    sender: Sender<String>,
+}
+
+impl Viewer {
+    /// Send the drawing, as an interchange document, to the browsers watching it
+    pub fn send_board(&self, json: String) {
+        let _ = self.sender.send(json);
+    }
+}
+
+/// Serve the viewer page and its WebSocket on a port of all the network interfaces
+pub fn listen(port: u16, token: String) -> Option<Viewer> {
+    let listener = match TcpListener::bind(("0.0.0.0", port)) {
+        Ok(listener) => listener,
+        Err(e) => {
+            log::error!("Failed to start the viewer on port {}: {}", port, e);
+            return None;
+        }
+    };
+    log::info!("Viewer page served on port {}", port);
+
+    let watchers = Arc::new(Mutex::new(Watchers::default()));
+    let accept_watchers = watchers.clone();
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let watchers = accept_watchers.clone();
+            let token = token.clone();
+            thread::spawn(move || {
+                if let Err(e) = serve(stream, &token, &watchers) {
+                    log::debug!("Viewer client disconnected: {}", e);
+                }
+            });
+        }
+    });
+
+    let (sender, receiver) = mpsc::channel::<String>();
+    thread::spawn(move || {
+        for json in receiver {
+            let mut watchers = watchers.lock().unwrap();
+            watchers
+                .clients
+                .retain_mut(|stream| write_frame(stream, OPCODE_TEXT, json.as_bytes()).is_ok());
+            watchers.board = json;
+        }
+    });
+    Some(Viewer { sender })
+}
+
+/// Send the page, or the drawing to the WebSocket of a page until it closes
+fn serve(stream: TcpStream, token: &str, watchers: &Mutex<Watchers>) -> std::io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = stream;
+
+    let (path, key) = read_request(&mut reader)?;
+    if !has_token(&path, token) {
+        return writer.write_all(b"HTTP/1.1 401 Unauthorized\r\n\r\n");
+    }
+    let key = match key {
+        Some(key) => key,
+        None => {
+            return write!(
+                writer,
+                "HTTP/1.1 200 OK\r\n\
+                 Content-Type: text/html; charset=utf-8\r\n\
+                 Content-Length: {}\r\n\
+                 Connection: close\r\n\r\n{}",
+                PAGE.len(),
+                PAGE
+            );
+        }
+    };
+    write_handshake(&mut writer, &key)?;
+
+    writer.set_write_timeout(Some(WRITE_TIMEOUT))?;
+    {
+        let mut watchers = watchers.lock().unwrap();
+        if !watchers.board.is_empty() {
+            write_frame(&mut writer, OPCODE_TEXT, watchers.board.as_bytes())?;
+        }
+        watchers.clients.push(writer);
+    }
+
+    // Nothing is read from the page, only its close
+    loop {
+        let (_, opcode, _) = read_frame(&mut reader)?;
+        if opcode == OPCODE_CLOSE {
+            return Ok(());
+        }
+    }
+}
@@ -0,0 +1,149 @@
+// Watermark, such as a handle or a logo, composited into the screenshots and optionally shown
+// over the drawing
+
+use std::fs;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::images::decode_png;
+use crate::text::TextRenderer;
+
+// Distance between the watermark and the corner, in pixels
+const MARGIN: u32 = 16;
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+pub struct Watermark {
+    pub width: u32,
+    pub height: u32,
+    rgba: Vec<u8>,
+    pub png: Arc<Vec<u8>>, // Drawn by the image renderer when shown live
+    corner: Corner,
+    pub opacity: f32,
+}
+
+impl Watermark {
+    /// Load the PNG image, or else draw the text with the font of the text annotations
+    pub fn new(
+        image_path: Option<&str>,
+        text: Option<&str>,
+        text_size: f32,
+        text_renderer: &TextRenderer,
+        corner: Corner,
+        opacity: f32,
+    ) -> Option<Watermark> {
+        let (png, width, height, rgba) = if let Some(path) = image_path {
+            let image = fs::read(path).map_err(|e| e.to_string()).and_then(|png| {
+                let (width, height, rgba) = decode_png(&png)?;
+                Ok((png, width, height, rgba))
+            });
+            match image {
+                Ok(image) => image,
+                Err(e) => {
+                    log::error!("Failed to open the watermark {}: {}", path, e);
+                    return None;
+                }
+            }
+        } else {
+            let (width, height, rgba) = text_image(text?, text_size, text_renderer);
+            (encode_png(width, height, &rgba)?, width, height, rgba)
+        };
+
+        Some(Watermark {
+            width,
+            height,
+            rgba,
+            png: Arc::new(png),
+            corner,
+            opacity: opacity.clamp(0.0, 1.0),
+        })
+    }
+
+    /// Top left corner of the watermark in an image of `width` by `height` pixels
+    pub fn position(&self, width: u32, height: u32) -> (i32, i32) {
+        let left = MARGIN as i32;
+        let top = MARGIN as i32;
+        let right = width as i32 - (self.width + MARGIN) as i32;
+        let bottom = height as i32 - (self.height + MARGIN) as i32;
+        match self.corner {
+            Corner::TopLeft => (left, top),
+            Corner::TopRight => (right, top),
+            Corner::BottomLeft => (left, bottom),
+            Corner::BottomRight => (right, bottom),
+        }
+    }
+
+    /// Blend the watermark over rgb pixels, rows from the top
+    pub fn composite(&self, rgb: &mut [u8], width: u32, height: u32) {
+        let (x, y) = self.position(width, height);
+        for row in 0..self.height as i32 {
+            for column in 0..self.width as i32 {
+                let (px, py) = (x + column, y + row);
+                if px < 0 || py < 0 || px >= width as i32 || py >= height as i32 {
+                    continue;
+                }
+                let source = ((row as u32 * self.width + column as u32) * 4) as usize;
+                let target = ((py as u32 * width + px as u32) * 3) as usize;
+                let alpha = f32::from(self.rgba[source + 3]) / 255.0 * self.opacity;
+                for channel in 0..3 {
+                    let color = f32::from(self.rgba[source + channel]);
+                    let below = f32::from(rgb[target + channel]);
+                    rgb[target + channel] = (color * alpha + below * (1.0 - alpha)).round() as u8;
+                }
+            }
+        }
+    }
+}
+
+/// White text with a dark shadow, readable over any screen
+fn text_image(text: &str, size: f32, text_renderer: &TextRenderer) -> (u32, u32, Vec<u8>) {
+    let (text_width, text_height, coverage) = text_renderer.rasterize(text, size);
+    let shadow = (size / 16.0).ceil().max(1.0) as u32;
+    let (width, height) = (text_width + shadow, text_height + shadow);
+    let coverage_at = |x: i64, y: i64| {
+        if x < 0 || y < 0 || x >= i64::from(text_width) || y >= i64::from(text_height) {
+            0.0
+        } else {
+            f32::from(coverage[(y as u32 * text_width + x as u32) as usize]) / 255.0
+        }
+    };
+
+    let mut rgba = Vec::with_capacity((width * height * 4) as usize);
+    for y in 0..i64::from(height) {
+        for x in 0..i64::from(width) {
+            let text_alpha = coverage_at(x, y);
+            let shadow_alpha = coverage_at(x - i64::from(shadow), y - i64::from(shadow));
+            // Text over its shadow
+            let alpha = text_alpha + shadow_alpha * (1.0 - text_alpha);
+            let color = if alpha > 0.0 { text_alpha / alpha } else { 0.0 };
+            let color = (color * 255.0).round() as u8;
+            rgba.extend(&[color, color, color, (alpha * 255.0).round() as u8]);
+        }
+    }
+    (width, height, rgba)
+}
+
+fn encode_png(width: u32, height: u32, rgba: &[u8]) -> Option<Vec<u8>> {
+    let mut png = Vec::new();
+    let mut encoder = png::Encoder::new(&mut png, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let result = encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(rgba));
+    match result {
+        Ok(()) => Some(png),
+        Err(e) => {
+            log::error!("Failed to create the watermark: {}", e);
+            None
+        }
+    }
+}
@@ -0,0 +1,454 @@
+// WebSocket server receiving JSON commands, for Stream Deck and Touch Portal buttons
+//
+// Clients connect to ws://127.0.0.1:<port>/?token=<token> and send messages such as
+// {"action": "tool", "tool": "arrow"}. Each message is answered with {"ok": true} or
+// {"ok": false, "error": "..."}. Browsers opening http://<address>:<port>/?token=<token> get a
+// page of buttons sending these messages, to use a phone as a remote.
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+
+use glutin::event_loop::EventLoopProxy;
+use inke_core::base64;
+use serde::Deserialize;
+
+use crate::instance::Command;
+use crate::Tool;
+
+static REMOTE_PAGE: &str = include_str!("remote.html");
+
+// Appended to the key of the client before hashing it, from the WebSocket RFC
+const HANDSHAKE_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+// Messages larger than this close the connection
+const MAX_MESSAGE_SIZE: u64 = 64 * 1024;
+
+pub const OPCODE_CONTINUATION: u8 = 0x0;
+pub const OPCODE_TEXT: u8 = 0x1;
+pub const OPCODE_CLOSE: u8 = 0x8;
+pub const OPCODE_PING: u8 = 0x9;
+pub const OPCODE_PONG: u8 = 0xA;
+
+#[derive(Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+enum Request {
+    Tool {
+        tool: Tool,
+    },
+    /// Index of the color in the config, from 0
+    Color {
+        index: usize,
+    },
+    /// Index of the brush size in the config, from 0
+    Size {
+        index: usize,
+    },
+    Undo,
+    Clear,
+    Background,
+    Hide,
+    Activate,
+    Stopwatch,
+    StopwatchReset,
+    /// Number of the counter, from 1 as in the instance commands
+    Counter {
+        number: usize,
+        amount: i64,
+    },
+    CountersReset,
+}
+
+/// The remote control page, with buttons for the colors (rgb from 0 to 255) and sizes of the config
+pub fn remote_page(colors: &[[u32; 3]], sizes: &[f32]) -> String {
+    REMOTE_PAGE
+        .replace("{{COLORS}}", &serde_json::to_string(colors).unwrap())
+        .replace("{{SIZES}}", &serde_json::to_string(sizes).unwrap())
+}
+
+/// Accept WebSocket clients on a port and forward their commands to the event loop, and serve the
+/// remote control page to browsers
+///
+/// The server only listens to this computer unless `is_network_wide`, for phones.
+pub fn listen(
+    port: u16,
+    token: String,
+    is_network_wide: bool,
+    page: String,
+    proxy: EventLoopProxy<Command>,
+) {
+    let address = if is_network_wide {
+        "0.0.0.0"
+    } else {
+        "127.0.0.1"
+    };
+    let listener = match TcpListener::bind((address, port)) {
+        Ok(listener) => listener,
+        Err(e) => {
+            log::error!(
+                "Failed to start the WebSocket server on port {}: {}",
+                port,
+                e
+            );
+            return;
+        }
+    };
+    let page = std::sync::Arc::new(page);
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let token = token.clone();
+            let page = page.clone();
+            let proxy = proxy.clone();
+            thread::spawn(move || {
+                if let Err(e) = serve(stream, &token, &page, &proxy) {
+                    log::debug!("WebSocket client disconnected: {}", e);
+                }
+            });
+        }
+    });
+}
+
+/// Read the request line and headers of an HTTP request, as its path and WebSocket key
+pub fn read_request(reader: &mut impl BufRead) -> std::io::Result<(String, Option<String>)> {
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut key = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("sec-websocket-key") {
+                key = Some(value.trim().to_string());
+            }
+        }
+    }
+
+    // GET /?token=... HTTP/1.1
+    let path = request_line.split_whitespace().nth(1).unwrap_or_default();
+    Ok((path.to_string(), key))
+}
+
+/// Whether the query of a path has the token, as in /?token=...
+pub fn has_token(path: &str, token: &str) -> bool {
+    path.split_once('?')
+        .map(|(_, query)| {
+            query
+                .split('&')
+                .any(|pair| pair == format!("token={}", token))
+        })
+        .unwrap_or(false)
+}
+
+/// Accept the WebSocket connection of a client, from the key of its request
+pub fn write_handshake(writer: &mut impl Write, key: &str) -> std::io::Result<()> {
+    let accept = base64::encode(&sha1(format!("{}{}", key, HANDSHAKE_GUID).as_bytes()));
+    write!(
+        writer,
+        "HTTP/1.1 101 Switching Protocols\r\n\
+         Upgrade: websocket\r\n\
+         Connection: Upgrade\r\n\
+         Sec-WebSocket-Accept: {}\r\n\r\n",
+        accept
+    )
+}
+
+/// Answer the handshake of a client, then handle its messages until it leaves, or send the page
+fn serve(
+    stream: TcpStream,
+    token: &str,
+    page: &str,
+    proxy: &EventLoopProxy<Command>,
+) -> std::io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = stream;
+
+    let (path, key) = read_request(&mut reader)?;
+    if !has_token(&path, token) {
+        return writer.write_all(b"HTTP/1.1 401 Unauthorized\r\n\r\n");
+    }
+    let key = match key {
+        Some(key) => key,
+        None => {
+            return write!(
+                writer,
+                "HTTP/1.1 200 OK\r\n\
+                 Content-Type: text/html; charset=utf-8\r\n\
+                 Content-Length: {}\r\n\
+                 Connection: close\r\n\r\n{}",
+                page.len(),
+                page
+            );
+        }
+    };
+    write_handshake(&mut writer, &key)?;
+
+    let mut message = Vec::new();
+    loop {
+        let (is_final, opcode, payload) = read_frame(&mut reader)?;
+        match opcode {
+            OPCODE_TEXT | OPCODE_CONTINUATION => {
+                message.extend(payload);
+                if message.len() as u64 > MAX_MESSAGE_SIZE {
+                    return write_frame(&mut writer, OPCODE_CLOSE, &[]);
+                }
+                if is_final {
+                    let reply = match handle_message(&message, proxy) {
+                        Ok(()) => String::from(r#"{"ok":true}"#),
+                        Err(e) => serde_json::json!({ "ok": false, "error": e }).to_string(),
+                    };
+                    write_frame(&mut writer, OPCODE_TEXT, reply.as_bytes())?;
+                    message.clear();
+                }
+            }
+            OPCODE_PING => write_frame(&mut writer, OPCODE_PONG, &payload)?,
+            OPCODE_CLOSE => return write_frame(&mut writer, OPCODE_CLOSE, &[]),
+            _ => (),
+        }
+    }
+}
+
+/// Turn a JSON message into a command for the event loop
+fn handle_message(message: &[u8], proxy: &EventLoopProxy<Command>) -> Result<(), String> {
+    let request: Request = serde_json::from_slice(message).map_err(|e| e.to_string())?;
+    let command = match request {
+        Request::Tool { tool } => Command::SetTool(tool),
+        Request::Color { index } => Command::SetColor(index),
+        Request::Size { index } => Command::SetSize(index),
+        Request::Undo => Command::Undo,
+        Request::Clear => Command::Clear,
+        Request::Background => Command::ToggleBackground,
+        Request::Hide => Command::Hide,
+        Request::Activate => Command::Activate,
+        Request::Stopwatch => Command::ToggleStopwatch,
+        Request::StopwatchReset => Command::ResetStopwatch,
+        Request::Counter { number, amount } => {
+            let index = number.checked_sub(1).ok_or("Counters start at 1")?;
+            Command::AddToCounter(index, amount)
+        }
+        Request::CountersReset => Command::ResetCounters,
+    };
+    proxy
+        .send_event(command)
+        .map_err(|_| String::from("Inke is closing"))
+}
+
+/// Read a frame sent by a client, as whether it ends a message, its opcode and its unmasked payload
+pub fn read_frame(reader: &mut impl Read) -> std::io::Result<(bool, u8, Vec<u8>)> {
+    let mut header = [0u8; 2];
+    reader.read_exact(&mut header)?;
+    let is_final = header[0] & 0x80 != 0;
+    let opcode = header[0] & 0x0F;
+    let is_masked = header[1] & 0x80 != 0;
+
+    let len = match header[1] & 0x7F {
+        126 => {
+            let mut len = [0u8; 2];
+            reader.read_exact(&mut len)?;
+            u64::from(u16::from_be_bytes(len))
+        }
+        127 => {
+            let mut len = [0u8; 8];
+            reader.read_exact(&mut len)?;
+            u64::from_be_bytes(len)
+        }
+        len => u64::from(len),
+    };
+    if len > MAX_MESSAGE_SIZE {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "Message too large",
+        ));
+    }
+
+    let mut mask = [0u8; 4];
+    if is_masked {
+        reader.read_exact(&mut mask)?;
+    }
+    let mut payload = vec![0u8; len as usize];
+    reader.read_exact(&mut payload)?;
+    for (i, byte) in payload.iter_mut().enumerate() {
+        *byte ^= mask[i % 4];
+    }
+
+    Ok((is_final, opcode, payload))
+}
+
+/// Send a whole message in a single unmasked frame, as servers do
+pub fn write_frame(writer: &mut impl Write, opcode: u8, payload: &[u8]) -> std::io::Result<()> {
+    let mut frame = vec![0x80 | opcode];
+    match payload.len() {
+        len if len < 126 => frame.push(len as u8),
+        len if len <= u16::MAX as usize => {
+            frame.push(126);
+            frame.extend(&(len as u16).to_be_bytes());
+        }
+        len => {
+            frame.push(127);
+            frame.extend(&(len as u64).to_be_bytes());
+        }
+    }
+    frame.extend(payload);
+    writer.write_all(&frame)
+}
+
+/// SHA-1 digest, only used to answer the handshake
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+
+    // Padded with a 1 bit, zeros, then the length in bits
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend(&(data.len() as u64 * 8).to_be_bytes());
+
+    for chunk in message.chunks(64) {
+        let mut w = [0u32; 80];
+        for i in 0..16 {
+            w[i] = u32::from_be_bytes([
+                chunk[i * 4],
+                chunk[i * 4 + 1],
+                chunk[i * 4 + 2],
+                chunk[i * 4 + 3],
+            ]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, &word) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5A827999),
+                20..=39 => (b ^ c ^ d, 0x6ED9EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+                _ => (b ^ c ^ d, 0xCA62C1D6),
+            };
+            let temp = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+
+        for (h, v) in h.iter_mut().zip([a, b, c, d, e].iter()) {
+            *h = h.wrapping_add(*v);
+        }
+    }
+
+    let mut digest = [0u8; 20];
+    for (i, v) in h.iter().enumerate() {
+        digest[i * 4..i * 4 + 4].copy_from_slice(&v.to_be_bytes());
+    }
+    digest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A frame as a client sends it, masked
+    fn masked_frame(opcode: u8, len_bytes: &[u8], payload: &[u8]) -> Vec<u8> {
+        let mask = [0x37, 0xFA, 0x21, 0x3D];
+        let mut frame = vec![0x80 | opcode];
+        frame.extend(len_bytes);
+        frame[1] |= 0x80;
+        frame.extend(&mask);
+        frame.extend(
+            payload
+                .iter()
+                .enumerate()
+                .map(|(i, byte)| byte ^ mask[i % 4]),
+        );
+        frame
+    }
+
+    #[test]
+    fn handshake_accepts_the_rfc_key() {
+        let key = "dGhlIHNhbXBsZSBub25jZQ==";
+        let accept = base64::encode(&sha1(format!("{}{}", key, HANDSHAKE_GUID).as_bytes()));
+        assert_eq!(accept, "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+
+        let mut response = Vec::new();
+        write_handshake(&mut response, key).unwrap();
+        let response = String::from_utf8(response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 101 Switching Protocols\r\n"));
+        assert!(response.contains("Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n"));
+    }
+
+    #[test]
+    fn read_frame_unmasks_the_payload() {
+        let frame = masked_frame(OPCODE_TEXT, &[5], b"Hello");
+        let (is_final, opcode, payload) = read_frame(&mut &frame[..]).unwrap();
+        assert!(is_final);
+        assert_eq!(opcode, OPCODE_TEXT);
+        assert_eq!(payload, b"Hello");
+    }
+
+    #[test]
+    fn read_frame_reads_extended_lengths() {
+        let payload = vec![b'a'; 300];
+
+        let mut len_bytes = vec![126];
+        len_bytes.extend(&300u16.to_be_bytes());
+        let frame = masked_frame(OPCODE_TEXT, &len_bytes, &payload);
+        assert_eq!(read_frame(&mut &frame[..]).unwrap().2, payload);
+
+        let mut len_bytes = vec![127];
+        len_bytes.extend(&300u64.to_be_bytes());
+        let frame = masked_frame(OPCODE_TEXT, &len_bytes, &payload);
+        assert_eq!(read_frame(&mut &frame[..]).unwrap().2, payload);
+    }
+
+    #[test]
+    fn write_frame_round_trips() {
+        for &len in &[0, 125, 126, u16::MAX as usize] {
+            let payload = vec![b'b'; len];
+            let mut frame = Vec::new();
+            write_frame(&mut frame, OPCODE_TEXT, &payload).unwrap();
+            let expected_header = match len {
+                0..=125 => 2,
+                _ => 4,
+            };
+            assert_eq!(frame.len(), expected_header + len);
+            assert_eq!(
+                read_frame(&mut &frame[..]).unwrap(),
+                (true, OPCODE_TEXT, payload)
+            );
+        }
+    }
+
+    #[test]
+    fn read_frame_refuses_large_messages() {
+        let payload = vec![b'c'; MAX_MESSAGE_SIZE as usize + 1];
+        let mut frame = Vec::new();
+        write_frame(&mut frame, OPCODE_TEXT, &payload).unwrap();
+        // Longer than 65535 bytes, the length takes 8 bytes
+        assert_eq!(frame[1], 127);
+        let error = read_frame(&mut &frame[..]).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+
+        let mut frame = Vec::new();
+        write_frame(&mut frame, OPCODE_TEXT, &payload[1..]).unwrap();
+        assert!(read_frame(&mut &frame[..]).is_ok());
+    }
+
+    #[test]
+    fn has_token_matches_whole_query_pairs() {
+        assert!(has_token("/?token=abc", "abc"));
+        assert!(has_token("/?mode=page&token=abc", "abc"));
+        assert!(!has_token("/", "abc"));
+        assert!(!has_token("/?token=abcd", "abc"));
+        assert!(!has_token("/?token=ab", "abc"));
+        assert!(!has_token("/?xtoken=abc", "abc"));
+        assert!(!has_token("/token=abc", "abc"));
+    }
+}
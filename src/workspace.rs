@@ -0,0 +1,24 @@
+// Identify the virtual desktop (workspace) the user is on, where the platform exposes it
+
+/// Id of the current virtual desktop, or None when it can't be known
+#[cfg(all(unix, not(target_os = "macos")))]
+pub fn current() -> Option<String> {
+    // Set by EWMH window managers on X11, e.g. "_NET_CURRENT_DESKTOP(CARDINAL) = 1"
+    let output = std::process::Command::new("xprop")
+        .args(["-root", "_NET_CURRENT_DESKTOP"])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    let output = String::from_utf8(output.stdout).ok()?;
+    let (_, id) = output.split_once('=')?;
+    Some(id.trim().to_string())
+}
+
+/// Windows and macOS don't expose the current virtual desktop to applications
+#[cfg(not(all(unix, not(target_os = "macos"))))]
+pub fn current() -> Option<String> {
+    None
+}